@@ -0,0 +1,79 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{self, Write};
+use std::time::UNIX_EPOCH;
+
+use redscript::bundle::{ConstantPool, ScriptBundle};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::ShellConfig;
+
+const POOL_FILE: &str = "redscript-cache.redscripts";
+const MANIFEST_FILE: &str = "redscript-cache.toml";
+
+/// The inputs of a compilation, a cached pool is only reused if all of them are unchanged.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    version: String,
+    bundle: String,
+    natives: Vec<String>,
+    sources: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn new(config: &ShellConfig) -> anyhow::Result<Self> {
+        let bundle_path = config.bundle_path()?;
+        let metadata = std::fs::metadata(&bundle_path)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+        let bundle = format!("{}:{}:{}", bundle_path.display(), metadata.len(), modified);
+
+        let natives = config
+            .natives
+            .iter()
+            .map(|set| format!("{set:?}"))
+            .chain(config.plugins.iter().map(|path| path.display().to_string()))
+            .collect();
+
+        let mut sources = BTreeMap::new();
+        for entry in WalkDir::new(&config.source_dir) {
+            let path = entry?.into_path();
+            if path.extension() == Some(OsStr::new("reds")) {
+                let mut hasher = DefaultHasher::new();
+                hasher.write(&std::fs::read(&path)?);
+                sources.insert(path.display().to_string(), format!("{:016x}", hasher.finish()));
+            }
+        }
+
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            bundle,
+            natives,
+            sources,
+        })
+    }
+}
+
+/// Loads the pool saved by a previous session if it was compiled from the same inputs.
+pub fn load(manifest: &Manifest) -> Option<ConstantPool> {
+    let contents = std::fs::read_to_string(MANIFEST_FILE).ok()?;
+    let cached: Manifest = toml::from_str(&contents).ok()?;
+    if cached != *manifest {
+        return None;
+    }
+    let mut file = io::BufReader::new(File::open(POOL_FILE).ok()?);
+    ScriptBundle::load(&mut file).ok().map(|bundle| bundle.pool)
+}
+
+/// Saves a compiled pool, the manifest is written last so that an incomplete save is never reused.
+pub fn save(pool: &ConstantPool, manifest: &Manifest) -> anyhow::Result<()> {
+    let bundle = ScriptBundle { pool: pool.clone() };
+    let mut file = io::BufWriter::new(File::create(POOL_FILE)?);
+    bundle.save(&mut file)?;
+    file.flush()?;
+    std::fs::write(MANIFEST_FILE, toml::to_string(manifest)?)?;
+    Ok(())
+}
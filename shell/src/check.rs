@@ -0,0 +1,64 @@
+use redscript::bundle::ConstantPool;
+use redscript_vm::compat::compat_report;
+use redscript_vm::metadata::VTableChecks;
+use redscript_vm::VM;
+use serde::Deserialize;
+
+use crate::natives::Plugin;
+use crate::{compile_sources, plain_vm, timed, ShellConfig};
+
+#[derive(Debug, Deserialize)]
+pub struct CheckConfig {
+    #[serde(default = "CheckConfig::enabled")]
+    signatures: bool,
+    #[serde(default = "CheckConfig::enabled")]
+    final_overrides: bool,
+}
+
+impl CheckConfig {
+    fn enabled() -> bool {
+        true
+    }
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        Self {
+            signatures: true,
+            final_overrides: true,
+        }
+    }
+}
+
+pub fn run_checks(mut pool: ConstantPool, config: &ShellConfig) -> anyhow::Result<()> {
+    compile_sources(&mut pool, config)?;
+
+    let mut vm = VM::new(&pool);
+    let meta = vm.metadata_mut();
+    meta.set_vtable_checks(VTableChecks {
+        signatures: config.check.signatures,
+        final_overrides: config.check.final_overrides,
+    });
+    timed(config.verbose, "vtable construction", || meta.build_vtables());
+
+    let warnings = meta.vtable_warnings();
+    for warning in warnings {
+        println!("warning: {}", warning.describe(&pool));
+    }
+    println!("{} warnings", warnings.len());
+    Ok(())
+}
+
+/// Reports the functions that would fail on missing natives or unsupported instructions, with the
+/// natives of the config registered.
+pub fn run_compat(mut pool: ConstantPool, config: &ShellConfig, plugins: &[Plugin]) -> anyhow::Result<()> {
+    compile_sources(&mut pool, config)?;
+
+    let vm = plain_vm(&pool, config, plugins)?;
+    let issues = compat_report(&vm);
+    for issue in &issues {
+        println!("{issue}");
+    }
+    println!("{} issues", issues.len());
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use redscript::bundle::ConstantPool;
+use serde::{Deserialize, Serialize};
+
+use crate::natives::Plugin;
+use crate::{call_entry_point, compile_sources, plain_vm, ShellConfig};
+
+#[derive(Debug, Deserialize)]
+pub struct CompareConfig {
+    /// The entry points to measure, all of the configured ones when empty.
+    #[serde(default)]
+    entry_points: Vec<String>,
+    #[serde(default = "CompareConfig::default_runs")]
+    runs: u32,
+    #[serde(default = "CompareConfig::default_threshold")]
+    threshold: f64,
+}
+
+impl CompareConfig {
+    fn default_runs() -> u32 {
+        5
+    }
+
+    fn default_threshold() -> f64 {
+        10.
+    }
+}
+
+impl Default for CompareConfig {
+    fn default() -> Self {
+        Self {
+            entry_points: vec![],
+            runs: Self::default_runs(),
+            threshold: Self::default_threshold(),
+        }
+    }
+}
+
+/// The outcome of an entry point, the time is the fastest of all runs to reduce noise.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Measurement {
+    result: Option<String>,
+    instructions: u64,
+    micros: u64,
+}
+
+/// Measures the entry points and compares them against the baseline, which is created if it
+/// doesn't exist. Fails if any of them returned a different result or got slower by more than the
+/// threshold, in time or in executed instructions.
+pub fn run_compare(
+    mut pool: ConstantPool,
+    path: &Path,
+    config: &ShellConfig,
+    plugins: &[Plugin],
+) -> anyhow::Result<()> {
+    compile_sources(&mut pool, config)?;
+
+    let mut vm = plain_vm(&pool, config, plugins)?;
+    let mut names = config.compare.entry_points.clone();
+    if names.is_empty() {
+        names = config.entry_points.keys().cloned().collect();
+    }
+    if names.is_empty() {
+        anyhow::bail!("no entry points to compare, add some to the config");
+    }
+
+    let mut measurements = BTreeMap::new();
+    for name in names {
+        let mut result = None;
+        let mut fastest = Duration::MAX;
+        for _ in 0..config.compare.runs.max(1) {
+            let start = Instant::now();
+            result = call_entry_point(&mut vm, &format!("@{name}"), config)?;
+            fastest = fastest.min(start.elapsed());
+        }
+        let measurement = Measurement {
+            result,
+            instructions: vm.fuel_used(),
+            micros: fastest.as_micros().try_into().unwrap_or(u64::MAX),
+        };
+        measurements.insert(name, measurement);
+    }
+
+    if !path.exists() {
+        std::fs::write(path, serde_json::to_string_pretty(&measurements)?)?;
+        println!(
+            "Baseline of {} entry points written to {}",
+            measurements.len(),
+            path.display()
+        );
+        return Ok(());
+    }
+    let baseline: BTreeMap<String, Measurement> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+
+    let mut regressions = 0;
+    for (name, current) in &measurements {
+        let Some(base) = baseline.get(name) else {
+            println!("{}", format!("? {name} is not in the baseline").yellow());
+            continue;
+        };
+        let time = change(base.micros, current.micros);
+        let instructions = change(base.instructions, current.instructions);
+        let line = format!(
+            "{name}: {}us ({time:+.1}%), {} instructions ({instructions:+.1}%)",
+            current.micros, current.instructions
+        );
+        if current.result != base.result {
+            regressions += 1;
+            println!("{}", format!("- {line}").red());
+            println!(
+                "{}",
+                format!("  result changed from {:?} to {:?}", base.result, current.result).red()
+            );
+        } else if time > config.compare.threshold || instructions > config.compare.threshold {
+            regressions += 1;
+            println!("{}", format!("- {line}").red());
+        } else {
+            println!("{}", format!("+ {line}").green());
+        }
+    }
+    for name in baseline.keys().filter(|name| !measurements.contains_key(*name)) {
+        println!("{}", format!("? {name} was not measured").yellow());
+    }
+    if regressions > 0 {
+        anyhow::bail!("{} regressions beyond {}%", regressions, config.compare.threshold);
+    }
+    Ok(())
+}
+
+/// The relative change from the baseline in percent.
+fn change(base: u64, current: u64) -> f64 {
+    if base == 0 {
+        return if current == 0 { 0. } else { f64::INFINITY };
+    }
+    (current as f64 - base as f64) / base as f64 * 100.
+}
@@ -0,0 +1,92 @@
+use redscript::bundle::PoolIndex;
+use redscript::definition::Function;
+use redscript_vm::interop::{IntoVM, Mutation};
+use redscript_vm::metadata::{Metadata, TypeId};
+use redscript_vm::value::Value;
+use serde::Deserialize;
+
+/// A function invoked with fixed arguments by `run @name`.
+#[derive(Debug, Deserialize)]
+pub struct EntryPoint {
+    pub function: String,
+    #[serde(default)]
+    pub args: Vec<toml::Value>,
+}
+
+/// An argument of an entry point converted to the type of its parameter.
+#[derive(Debug, Clone)]
+pub enum Arg {
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    String(String),
+}
+
+impl Arg {
+    pub fn into_value<'gc>(self, mc: &Mutation<'gc>, meta: &Metadata<'_>) -> Value<'gc> {
+        match self {
+            Arg::I8(val) => Value::I8(val),
+            Arg::I16(val) => Value::I16(val),
+            Arg::I32(val) => Value::I32(val),
+            Arg::I64(val) => Value::I64(val),
+            Arg::U8(val) => Value::U8(val),
+            Arg::U16(val) => Value::U16(val),
+            Arg::U32(val) => Value::U32(val),
+            Arg::U64(val) => Value::U64(val),
+            Arg::F32(val) => Value::F32(val),
+            Arg::F64(val) => Value::F64(val),
+            Arg::Bool(val) => Value::Bool(val),
+            Arg::String(val) => val.into_vm(mc, meta),
+        }
+    }
+}
+
+/// Converts the configured arguments to the types of the parameters of the function.
+pub fn convert_args(args: &[toml::Value], idx: PoolIndex<Function>, meta: &Metadata<'_>) -> anyhow::Result<Vec<Arg>> {
+    let pool = meta.pool();
+    let function = pool.function(idx)?;
+    if args.len() != function.parameters.len() {
+        anyhow::bail!("expected {} arguments, got {}", function.parameters.len(), args.len());
+    }
+    function
+        .parameters
+        .iter()
+        .zip(args)
+        .map(|(&param, arg)| {
+            let name = pool.def_name(param)?;
+            let typ = meta
+                .get_type(pool.parameter(param)?.type_)
+                .ok_or_else(|| anyhow::anyhow!("unknown type of parameter {}", name))?;
+            convert_arg(arg, typ).ok_or_else(|| anyhow::anyhow!("invalid value {} for parameter {}", arg, name))
+        })
+        .collect()
+}
+
+fn convert_arg(arg: &toml::Value, typ: &TypeId) -> Option<Arg> {
+    let res = match (arg, typ) {
+        (toml::Value::Integer(val), TypeId::I8) => Arg::I8((*val).try_into().ok()?),
+        (toml::Value::Integer(val), TypeId::I16) => Arg::I16((*val).try_into().ok()?),
+        (toml::Value::Integer(val), TypeId::I32) => Arg::I32((*val).try_into().ok()?),
+        (toml::Value::Integer(val), TypeId::I64) => Arg::I64(*val),
+        (toml::Value::Integer(val), TypeId::U8) => Arg::U8((*val).try_into().ok()?),
+        (toml::Value::Integer(val), TypeId::U16) => Arg::U16((*val).try_into().ok()?),
+        (toml::Value::Integer(val), TypeId::U32) => Arg::U32((*val).try_into().ok()?),
+        (toml::Value::Integer(val), TypeId::U64) => Arg::U64((*val).try_into().ok()?),
+        (toml::Value::Integer(val), TypeId::F32) => Arg::F32(*val as f32),
+        (toml::Value::Integer(val), TypeId::F64) => Arg::F64(*val as f64),
+        (toml::Value::Float(val), TypeId::F32) => Arg::F32(*val as f32),
+        (toml::Value::Float(val), TypeId::F64) => Arg::F64(*val),
+        (toml::Value::Boolean(val), TypeId::Bool) => Arg::Bool(*val),
+        (toml::Value::String(val), TypeId::String) => Arg::String(val.clone()),
+        _ => return None,
+    };
+    Some(res)
+}
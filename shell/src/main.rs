@@ -1,49 +1,112 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
 
+use check::CheckConfig;
+use colored::Colorize;
+use compare::CompareConfig;
+use entry::EntryPoint;
+use natives::{Arithmetic, NativeSet, Plugin, VmSettings};
 use redscript::bundle::{ConstantPool, ScriptBundle};
 use redscript_compiler::error::Error;
 use redscript_compiler::source_map::{Files, SourceFilter};
 use redscript_compiler::unit::CompilationUnit;
-use redscript_vm::{args, native, VM};
+use redscript_vm::debug::WatchError;
+use redscript_vm::diff::{diff_pools, layout_changes, PoolChange};
+use redscript_vm::profiler::ProfileOrder;
+use redscript_vm::trace::CallTracer;
+use redscript_vm::value::Value;
+use redscript_vm::VM;
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
 use serde::Deserialize;
+use test::TestOptions;
 
+mod cache;
+mod check;
+mod compare;
+mod entry;
+mod natives;
+mod pool;
+mod setup;
 mod test;
 
 const HISTORY_FILE: &str = "redscript-history.txt";
+const INVALID_COMMAND: &str = "Invalid command, enter 'help' for more information";
+const TRACE_FILE: &str = "redscript-trace.txt";
+const PROFILE_FILE: &str = "redscript-profile.folded";
+const CALL_GRAPH_FILE: &str = "redscript-calls.dot";
+const VALUE_GRAPH_FILE: &str = "redscript-values.dot";
 
 fn main() -> anyhow::Result<()> {
     let location = std::env::current_dir()?.join("redscript.toml");
+    if std::env::args().nth(1).as_deref() == Some("init") {
+        match setup::write_starter_config(&location)? {
+            Some(bundle) => println!(
+                "Created {} using the bundle at {}",
+                location.display(),
+                bundle.display()
+            ),
+            None => println!(
+                "Created {}, the game wasn't found so bundle_path has to be set manually",
+                location.display()
+            ),
+        }
+        return Ok(());
+    }
     match ShellConfig::load(&location) {
-        Ok(config) => {
-            let mut file = io::BufReader::new(File::open(&config.bundle_path)?);
-            let bundle = ScriptBundle::load(&mut file)?;
-            repl(bundle.pool, &config)
+        Ok(mut config) => {
+            config.verbose = std::env::args().any(|arg| arg == "--verbose");
+
+            let bundle_path = config.bundle_path()?;
+            if config.verbose {
+                println!("Using the bundle at {}", bundle_path.display());
+            }
+            let mut file = io::BufReader::new(File::open(bundle_path)?);
+            let bundle = timed(config.verbose, "bundle loading", || ScriptBundle::load(&mut file))?;
+            if config.verbose {
+                println!("{}", pool::PoolStats::new(&bundle.pool));
+            }
+            let plugins = Plugin::load_all(&config.plugins)?;
+            // `redscript-sh compare <baseline>` runs the comparison without the shell, for CI
+            match std::env::args().skip(1).collect::<Vec<_>>().as_slice() {
+                [command, path, ..] if command == "compare" => {
+                    compare::run_compare(bundle.pool, Path::new(path), &config, &plugins)
+                }
+                _ => repl(bundle.pool, &config, &plugins),
+            }
         }
         Err(error) => {
-            println!("Failed to load the shell config (redscript.toml is required)");
+            println!("Failed to load the shell config (redscript.toml is required, run with `init` to create one)");
             Err(error.into())
         }
     }
 }
 
-fn repl(pool: ConstantPool, config: &ShellConfig) -> anyhow::Result<()> {
+fn repl(pool: ConstantPool, config: &ShellConfig, plugins: &[Plugin]) -> anyhow::Result<()> {
     println!("Welcome to the redscript shell! Type 'help' for more information.");
 
     let mut rl = DefaultEditor::new()?;
     if rl.load_history(HISTORY_FILE).is_err() {
         println!("No previous history");
     }
+    // only the commands declared by plugins are routed to them, so mistyped commands are reported as invalid
+    let plugin_commands = plugins
+        .iter()
+        .flat_map(Plugin::commands)
+        .map(|&name| name.to_owned())
+        .collect::<Vec<_>>();
     loop {
         let readline = rl.readline(">> ");
         match readline {
             Ok(line) => {
                 rl.add_history_entry(line.as_str())?;
-                match Command::parse(&line) {
-                    Ok(cmd) => match execute(cmd, pool.clone(), config) {
+                match Command::parse(&line, &plugin_commands) {
+                    Ok(cmd) => match execute(cmd, pool.clone(), config, plugins) {
                         Ok(true) => break,
                         Ok(false) => {}
                         Err(err) => println!("{:?}", err),
@@ -67,75 +130,389 @@ fn repl(pool: ConstantPool, config: &ShellConfig) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn execute(command: Command<'_>, pool: ConstantPool, config: &ShellConfig) -> anyhow::Result<bool> {
+fn execute(command: Command<'_>, pool: ConstantPool, config: &ShellConfig, plugins: &[Plugin]) -> anyhow::Result<bool> {
     match command {
         Command::RunMain => {
-            run_function(pool, "main;", config)?;
+            run_function(pool, "main;", config, plugins, None, false)?;
             Ok(false)
         }
         Command::Run(func) => {
-            run_function(pool, func, config)?;
+            run_function(pool, func, config, plugins, None, false)?;
+            Ok(false)
+        }
+        Command::TraceRun(func) => {
+            let tracer = CallTracer::new();
+            let res = run_function(pool, func, config, plugins, Some(tracer.clone()), false);
+
+            let mut file = io::BufWriter::new(File::create(TRACE_FILE)?);
+            for entry in tracer.entries().iter() {
+                writeln!(file, "{}", entry)?;
+            }
+            file.flush()?;
+            println!("Call trace written to {}", TRACE_FILE);
+            res?;
+            Ok(false)
+        }
+        Command::ProfileRun(func) => {
+            run_function(pool, func, config, plugins, None, true)?;
+            Ok(false)
+        }
+        Command::Test(suite, options) => {
+            test::run_suite(pool, suite, &options, config, plugins)?;
+            Ok(false)
+        }
+        Command::Check => {
+            check::run_checks(pool, config)?;
+            Ok(false)
+        }
+        Command::Compat => {
+            check::run_compat(pool, config, plugins)?;
+            Ok(false)
+        }
+        Command::Diff(path) => {
+            diff_bundle(pool, Path::new(path), config)?;
+            Ok(false)
+        }
+        Command::Compare(path) => {
+            compare::run_compare(pool, Path::new(path), config, plugins)?;
             Ok(false)
         }
-        Command::Test(suite) => {
-            test::run_suite(pool, suite, config)?;
+        Command::Dump(expr) => {
+            println!(
+                "{}",
+                evaluate_call(pool, expr, config, plugins, |vm, expr| vm.dump(expr))?
+            );
+            Ok(false)
+        }
+        Command::Graph(expr) => {
+            let graph = evaluate_call(pool, expr, config, plugins, |vm, expr| vm.value_graph(expr))?;
+            std::fs::write(VALUE_GRAPH_FILE, graph)?;
+            println!("Value graph written to {}", VALUE_GRAPH_FILE);
+            Ok(false)
+        }
+        Command::Pool => {
+            pool::print_summary(&pool, config, plugins)?;
+            Ok(false)
+        }
+        Command::PoolSearch(query) => {
+            pool::search(&pool, query);
             Ok(false)
         }
         Command::Help => {
-            println!("Available commands: runMain, run [function], test [suite], help, exit");
+            println!("Available commands: runMain, run [function | @entry point], trace run [function], profile run [function], test [suite] [--include tag] [--exclude tag] [--shuffle] [--repro settings], check, compat, diff [bundle], compare [baseline], dump [function], graph [function], pool, pool search [text], help, exit");
+            let commands = plugins.iter().flat_map(Plugin::commands).copied().collect::<Vec<_>>();
+            if !commands.is_empty() {
+                println!("Plugin commands: {}", commands.join(", "));
+            }
             Ok(false)
         }
         Command::Exit => Ok(true),
+        Command::Plugin(name, args) => {
+            run_plugin_command(pool, name, &args, config, plugins)?;
+            Ok(false)
+        }
     }
 }
 
-fn run_function(mut pool: ConstantPool, func_name: &str, config: &ShellConfig) -> anyhow::Result<()> {
-    let sources = Files::from_dir(&config.source_dir, &SourceFilter::None)?;
-    CompilationUnit::new_with_defaults(&mut pool)?.compile_files(&sources)?;
+fn run_function(
+    mut pool: ConstantPool,
+    func_name: &str,
+    config: &ShellConfig,
+    plugins: &[Plugin],
+    tracer: Option<CallTracer>,
+    profile: bool,
+) -> anyhow::Result<()> {
+    compile_sources(&mut pool, config)?;
 
-    let mut vm = VM::new(&pool);
-    native::register_natives(&mut vm, |str| println!("{}", str));
+    let test_errors = Rc::new(RefCell::new(vec![]));
+    let mut vm = natives::new_vm(&pool, config, &config.vm_settings(), plugins, &test_errors, |_| {})?;
+    vm.set_call_tracer(tracer);
+    vm.set_profiling(profile);
+    vm.set_fold_natives(config.fold_natives);
+    vm.set_stack_profiling(profile);
 
-    let main = vm
-        .metadata()
-        .get_function(func_name)
-        .ok_or_else(|| anyhow::anyhow!("no main function"))?;
-    let out = vm.call_with_callback(main, args!(), |res| res.map(|val| val.to_string(&pool)))?;
-    if let Some(res) = out {
+    if let Some(res) = call_entry_point(&mut vm, func_name, config)? {
         println!("result: {}", res);
     }
+    if config.verbose {
+        println!("GC: {}", vm.gc_stats());
+    }
+    if profile {
+        print_profile(&vm);
+        let mut file = io::BufWriter::new(File::create(PROFILE_FILE)?);
+        vm.write_folded_stacks(&mut file)?;
+        file.flush()?;
+        println!("Folded call stacks written to {}", PROFILE_FILE);
+
+        let mut file = io::BufWriter::new(File::create(CALL_GRAPH_FILE)?);
+        vm.write_call_graph(&mut file)?;
+        file.flush()?;
+        println!("Call graph written to {}", CALL_GRAPH_FILE);
+    }
+    for error in test_errors.borrow().iter() {
+        println!("{}", format!("- {}", error).red());
+    }
     Ok(())
 }
 
+/// Calls a function or a configured entry point, returning its result rendered as a string.
+fn call_entry_point(vm: &mut VM<'_>, func_name: &str, config: &ShellConfig) -> anyhow::Result<Option<String>> {
+    let (func_name, args) = config.entry_point(func_name)?;
+    let pool = vm.metadata().pool();
+    let to_string = |res: Option<Value<'_>>| res.map(|val| val.to_string(pool));
+    let meta = vm.metadata();
+    match meta.resolve_function(func_name) {
+        Some(main) => {
+            let args = entry::convert_args(args, main, meta)?;
+            vm.call_with_callback(
+                main,
+                |mc, meta| args.iter().map(|arg| arg.clone().into_value(mc, meta)).collect(),
+                to_string,
+            )
+        }
+        // instance methods are called on a new instance of the class
+        None if !args.is_empty() => anyhow::bail!("only static functions can be called with arguments"),
+        None => {
+            let (class, method) = func_name
+                .split_once("::")
+                .and_then(|(class, method)| Some((meta.get_class(class)?, method)))
+                .ok_or_else(|| anyhow::anyhow!("no main function"))?;
+            vm.call_on_default_instance(class, method, to_string)
+        }
+    }
+    .map_err(|err| anyhow::anyhow!("{}", err.display_with(vm.metadata())))
+}
+
+/// Calls a function without parameters and renders its result with one of the evaluation methods
+/// of the VM.
+fn evaluate_call(
+    mut pool: ConstantPool,
+    expr: &str,
+    config: &ShellConfig,
+    plugins: &[Plugin],
+    render: impl FnOnce(&mut VM<'_>, &str) -> Result<String, WatchError>,
+) -> anyhow::Result<String> {
+    compile_sources(&mut pool, config)?;
+    let mut vm = plain_vm(&pool, config, plugins)?;
+    let expr = if expr.ends_with("()") {
+        expr.to_owned()
+    } else {
+        format!("{expr}()")
+    };
+    Ok(render(&mut vm, &expr)?)
+}
+
+/// Runs a command provided by one of the plugins on a VM with the project sources compiled.
+fn run_plugin_command(
+    mut pool: ConstantPool,
+    name: &str,
+    args: &[&str],
+    config: &ShellConfig,
+    plugins: &[Plugin],
+) -> anyhow::Result<()> {
+    let Some(plugin) = plugins.iter().find(|plugin| plugin.commands().contains(&name)) else {
+        anyhow::bail!(INVALID_COMMAND);
+    };
+    compile_sources(&mut pool, config)?;
+    let mut vm = plain_vm(&pool, config, plugins)?;
+    plugin.run_command(&mut vm, name, args)
+}
+
+/// Creates a VM with the settings of the config for the commands that only call functions, the
+/// failures reported by test natives are dropped.
+pub fn plain_vm<'pool>(
+    pool: &'pool ConstantPool,
+    config: &ShellConfig,
+    plugins: &[Plugin],
+) -> anyhow::Result<VM<'pool>> {
+    natives::new_vm(pool, config, &config.vm_settings(), plugins, &Rc::default(), |_| {})
+}
+
+/// Compiles the project sources into the pool. With warm starts enabled, the pool compiled by
+/// a previous session is reused as long as the sources, the bundle and the natives are unchanged.
+pub fn compile_sources(pool: &mut ConstantPool, config: &ShellConfig) -> anyhow::Result<()> {
+    let manifest = config.warm_start.then(|| cache::Manifest::new(config)).transpose()?;
+    if let Some(cached) = manifest.as_ref().and_then(cache::load) {
+        if config.verbose {
+            println!("Reusing the pool compiled by a previous session");
+        }
+        *pool = cached;
+        return Ok(());
+    }
+
+    let sources = Files::from_dir(&config.source_dir, &SourceFilter::None)?;
+    timed(config.verbose, "compilation", || {
+        CompilationUnit::new_with_defaults(pool)?.compile_files(&sources)
+    })?;
+    if let Some(manifest) = &manifest {
+        cache::save(pool, manifest)?;
+    }
+    Ok(())
+}
+
+fn print_profile(vm: &VM<'_>) {
+    const MAX_FUNCTIONS: usize = 20;
+
+    let pool = vm.metadata().pool();
+    println!("{}", "functions (by self time):".bold());
+    for (idx, profile) in vm
+        .profiling_report_by(ProfileOrder::SelfTime)
+        .into_iter()
+        .take(MAX_FUNCTIONS)
+    {
+        let name = pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default();
+        println!(
+            "  {:<60} {:>8} calls {:>12?} self {:>12?} native {:>12?} total",
+            name, profile.calls, profile.self_time, profile.native_time, profile.total
+        );
+    }
+    println!("{} {}", "max call depth:".bold(), vm.max_call_depth());
+    let recursive = vm.recursion_report();
+    if !recursive.is_empty() {
+        println!("{}", "recursive functions:".bold());
+        for (idx, profile) in recursive.into_iter().take(MAX_FUNCTIONS) {
+            let name = pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default();
+            println!(
+                "  {:<60} {:>8} calls {:>8} frames deep",
+                name, profile.calls, profile.max_frames
+            );
+        }
+    }
+    let regions = vm.region_report();
+    if !regions.is_empty() {
+        println!("{}", "regions:".bold());
+        for (name, profile) in regions {
+            println!("  {:<60} {:>8} times {:>12?}", name, profile.entries, profile.total);
+        }
+    }
+}
+
+fn diff_bundle(mut pool: ConstantPool, path: &Path, config: &ShellConfig) -> anyhow::Result<()> {
+    let mut file = io::BufReader::new(File::open(path)?);
+    let other = ScriptBundle::load(&mut file)?;
+
+    compile_sources(&mut pool, config)?;
+
+    let changes = diff_pools(&other.pool, &pool);
+    for change in &changes {
+        let line = change.to_string();
+        match change {
+            PoolChange::Added { .. } => println!("{}", line.green()),
+            PoolChange::Removed { .. } => println!("{}", line.red()),
+            PoolChange::SignatureChanged { .. } => println!("{}", line.yellow()),
+        }
+    }
+    println!("{} changes", changes.len());
+
+    for layout in layout_changes(&other.pool, &pool) {
+        if layout.is_migratable() {
+            println!(
+                "{}",
+                format!("{} layout changed, instances can be migrated:", layout.class).yellow()
+            );
+        } else {
+            println!("{}", format!("{} layout changed incompatibly:", layout.class).red());
+        }
+        for field in &layout.fields {
+            println!("  {}", field);
+        }
+    }
+    Ok(())
+}
+
+pub fn timed<A>(verbose: bool, label: &str, f: impl FnOnce() -> A) -> A {
+    let start = Instant::now();
+    let res = f();
+    if verbose {
+        println!("{} took {:?}", label, start.elapsed());
+    }
+    res
+}
+
 enum Command<'inp> {
     RunMain,
     Run(&'inp str),
-    Test(&'inp str),
+    TraceRun(&'inp str),
+    ProfileRun(&'inp str),
+    Test(&'inp str, TestOptions),
+    Check,
+    Compat,
+    Diff(&'inp str),
+    Compare(&'inp str),
+    Dump(&'inp str),
+    Graph(&'inp str),
+    Pool,
+    PoolSearch(&'inp str),
     Help,
     Exit,
+    /// A command contributed by a plugin, with its arguments.
+    Plugin(&'inp str, Vec<&'inp str>),
 }
 
 impl<'inp> Command<'inp> {
-    fn parse(input: &'inp str) -> Result<Self, &'static str> {
+    fn parse(input: &'inp str, plugin_commands: &[String]) -> Result<Self, &'static str> {
         let parts = input.split(' ').collect::<Vec<_>>();
         match parts.as_slice() {
             ["runMain"] => Ok(Command::RunMain),
             ["run", method] => Ok(Command::Run(method)),
-            ["test", suite] => Ok(Command::Test(suite)),
+            ["trace", "run", method] => Ok(Command::TraceRun(method)),
+            ["profile", "run", method] => Ok(Command::ProfileRun(method)),
+            ["test", suite, options @ ..] => Ok(Command::Test(suite, TestOptions::parse(options)?)),
+            ["check"] => Ok(Command::Check),
+            ["compat"] => Ok(Command::Compat),
+            ["diff", path] => Ok(Command::Diff(path)),
+            ["compare", path] => Ok(Command::Compare(path)),
+            ["dump", expr] => Ok(Command::Dump(expr)),
+            ["graph", expr] => Ok(Command::Graph(expr)),
+            ["pool"] => Ok(Command::Pool),
+            ["pool", "search", query] => Ok(Command::PoolSearch(query)),
             ["help"] => Ok(Command::Help),
             ["exit"] => Ok(Command::Exit),
-            _ => Err("Invalid command, enter 'help' for more information"),
+            [name, args @ ..] if plugin_commands.iter().any(|command| command == name) => {
+                Ok(Command::Plugin(name, args.to_vec()))
+            }
+            _ => Err(INVALID_COMMAND),
         }
     }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ShellConfig {
-    bundle_path: PathBuf,
+    bundle_path: Option<PathBuf>,
     #[serde(default = "ShellConfig::default_source_dir")]
     source_dir: PathBuf,
     #[serde(default = "ShellConfig::default_test_dir")]
     test_dir: PathBuf,
+    #[serde(default = "ShellConfig::default_natives")]
+    natives: Vec<NativeSet>,
+    #[serde(default)]
+    plugins: Vec<PathBuf>,
+    #[serde(default)]
+    record_calls: bool,
+    #[serde(default)]
+    check: CheckConfig,
+    #[serde(default)]
+    globals: HashMap<String, toml::Value>,
+    #[serde(default)]
+    float_parity: bool,
+    #[serde(default)]
+    lenient_nulls: bool,
+    #[serde(default)]
+    arithmetic: Arithmetic,
+    #[serde(default)]
+    fuel_limit: Option<u64>,
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+    #[serde(default)]
+    warm_start: bool,
+    #[serde(default)]
+    fold_natives: bool,
+    #[serde(default)]
+    entry_points: HashMap<String, EntryPoint>,
+    #[serde(default)]
+    compare: CompareConfig,
+    #[serde(skip)]
+    verbose: bool,
 }
 
 impl ShellConfig {
@@ -146,6 +523,41 @@ impl ShellConfig {
         Ok(res)
     }
 
+    /// The configured bundle path, or the one found in the common install locations of the game.
+    fn bundle_path(&self) -> anyhow::Result<PathBuf> {
+        match &self.bundle_path {
+            Some(path) => Ok(path.clone()),
+            None => setup::find_bundle()
+                .ok_or_else(|| anyhow::anyhow!("the game bundle wasn't found, set bundle_path in redscript.toml")),
+        }
+    }
+
+    /// Resolves `@name` to the function and the arguments of a configured entry point, other names
+    /// are functions called without arguments.
+    fn entry_point<'a>(&'a self, name: &'a str) -> anyhow::Result<(&'a str, &'a [toml::Value])> {
+        match name.strip_prefix('@') {
+            Some(name) => {
+                let entry = self
+                    .entry_points
+                    .get(name)
+                    .ok_or_else(|| anyhow::anyhow!("no entry point named {}", name))?;
+                Ok((&entry.function, &entry.args))
+            }
+            None => Ok((name, &[])),
+        }
+    }
+
+    fn vm_settings(&self) -> VmSettings {
+        VmSettings {
+            natives: self.natives.clone(),
+            arithmetic: self.arithmetic,
+            float_parity: self.float_parity,
+            lenient_nulls: self.lenient_nulls,
+            fuel_limit: self.fuel_limit,
+            timeout_secs: self.timeout_secs,
+        }
+    }
+
     fn default_source_dir() -> PathBuf {
         "src".into()
     }
@@ -153,4 +565,8 @@ impl ShellConfig {
     fn default_test_dir() -> PathBuf {
         "test".into()
     }
+
+    fn default_natives() -> Vec<NativeSet> {
+        vec![NativeSet::Core, NativeSet::Strings]
+    }
 }
@@ -0,0 +1,261 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Duration;
+
+use libloading::Library;
+use redscript::bundle::ConstantPool;
+use redscript_vm::native::ArithmeticMode;
+use redscript_vm::value::FloatFormat;
+use redscript_vm::{native, VM};
+use serde::Deserialize;
+
+use crate::{test, timed, ShellConfig};
+
+/// Exported by [`redscript_vm::plugin_version!`], checked before anything else is called.
+const PLUGIN_VERSION: &[u8] = b"redscript_plugin_version\0";
+const PLUGIN_ENTRY_POINT: &[u8] = b"redscript_register_natives\0";
+/// Optional, returns the names of the shell commands provided by the plugin.
+const PLUGIN_COMMANDS: &[u8] = b"redscript_shell_commands\0";
+/// Optional, runs one of the commands returned by [`PLUGIN_COMMANDS`] with its arguments.
+const PLUGIN_COMMAND_HANDLER: &[u8] = b"redscript_run_command\0";
+
+type CommandHandler = fn(&mut VM<'_>, &str, &[&str]) -> Result<(), String>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NativeSet {
+    Core,
+    Strings,
+    Game,
+    Files,
+    Reflection,
+    Regex,
+    Test,
+}
+
+impl NativeSet {
+    const ALL: [NativeSet; 7] = [
+        NativeSet::Core,
+        NativeSet::Strings,
+        NativeSet::Game,
+        NativeSet::Files,
+        NativeSet::Reflection,
+        NativeSet::Regex,
+        NativeSet::Test,
+    ];
+
+    /// The name used in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            NativeSet::Core => "core",
+            NativeSet::Strings => "strings",
+            NativeSet::Game => "game",
+            NativeSet::Files => "files",
+            NativeSet::Reflection => "reflection",
+            NativeSet::Regex => "regex",
+            NativeSet::Test => "test",
+        }
+    }
+}
+
+impl FromStr for NativeSet {
+    type Err = ();
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|set| set.name() == str).ok_or(())
+    }
+}
+
+/// Mirrors [`ArithmeticMode`] for the shell config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arithmetic {
+    #[default]
+    Wrapping,
+    Saturating,
+    Checked,
+}
+
+impl Arithmetic {
+    /// The name used in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            Arithmetic::Wrapping => "wrapping",
+            Arithmetic::Saturating => "saturating",
+            Arithmetic::Checked => "checked",
+        }
+    }
+}
+
+impl FromStr for Arithmetic {
+    type Err = ();
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        [Arithmetic::Wrapping, Arithmetic::Saturating, Arithmetic::Checked]
+            .into_iter()
+            .find(|mode| mode.name() == str)
+            .ok_or(())
+    }
+}
+
+impl From<Arithmetic> for ArithmeticMode {
+    fn from(mode: Arithmetic) -> Self {
+        match mode {
+            Arithmetic::Wrapping => ArithmeticMode::Wrapping,
+            Arithmetic::Saturating => ArithmeticMode::Saturating,
+            Arithmetic::Checked => ArithmeticMode::Checked,
+        }
+    }
+}
+
+/// The parts of the config that change how scripts behave, test runs record them so that they can be repeated.
+#[derive(Debug, Clone, Default)]
+pub struct VmSettings {
+    pub natives: Vec<NativeSet>,
+    pub arithmetic: Arithmetic,
+    pub float_parity: bool,
+    pub lenient_nulls: bool,
+    pub fuel_limit: Option<u64>,
+    pub timeout_secs: Option<u64>,
+}
+
+impl VmSettings {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs)
+    }
+}
+
+pub struct Plugin {
+    library: Library,
+}
+
+impl Plugin {
+    pub fn load_all(paths: &[PathBuf]) -> anyhow::Result<Vec<Plugin>> {
+        paths
+            .iter()
+            .map(|path| {
+                let library = unsafe { Library::new(path) }
+                    .map_err(|err| anyhow::anyhow!("failed to load plugin {}: {}", path.display(), err))?;
+                let plugin = Plugin { library };
+                plugin.check_version(path)?;
+                Ok(plugin)
+            })
+            .collect()
+    }
+
+    /// The entry points of plugins take Rust values, which are only compatible when the plugin is
+    /// built against the same version of the VM as the shell.
+    fn check_version(&self, path: &Path) -> anyhow::Result<()> {
+        let version =
+            unsafe { self.library.get::<extern "C" fn() -> *const c_char>(PLUGIN_VERSION) }.map_err(|_| {
+                anyhow::anyhow!(
+                    "plugin {} doesn't export its VM version, it has to call redscript_vm::plugin_version!()",
+                    path.display()
+                )
+            })?;
+        let version = unsafe { CStr::from_ptr(version()) }.to_string_lossy();
+        if version != redscript_vm::VERSION {
+            anyhow::bail!(
+                "plugin {} is built against redscript-vm {}, but the shell uses {}",
+                path.display(),
+                version,
+                redscript_vm::VERSION
+            );
+        }
+        Ok(())
+    }
+
+    fn register(&self, vm: &mut VM<'_>) -> anyhow::Result<()> {
+        let register = unsafe { self.library.get::<fn(&mut VM<'_>)>(PLUGIN_ENTRY_POINT)? };
+        register(vm);
+        Ok(())
+    }
+
+    /// The shell commands contributed by the plugin, plugins without commands don't export them.
+    /// The names belong to the library, so they can't outlive the plugin.
+    pub fn commands(&self) -> &[&str] {
+        match unsafe { self.library.get::<fn() -> &'static [&'static str]>(PLUGIN_COMMANDS) } {
+            Ok(commands) => commands(),
+            Err(_) => &[],
+        }
+    }
+
+    pub fn run_command(&self, vm: &mut VM<'_>, name: &str, args: &[&str]) -> anyhow::Result<()> {
+        let run = unsafe { self.library.get::<CommandHandler>(PLUGIN_COMMAND_HANDLER)? };
+        run(vm, name, args).map_err(|err| anyhow::anyhow!("{} failed: {}", name, err))
+    }
+}
+
+pub fn register(vm: &mut VM<'_>, sets: &[NativeSet], plugins: &[Plugin]) -> anyhow::Result<()> {
+    for set in sets {
+        match set {
+            NativeSet::Core => native::register_core(vm, |str| println!("{}", str)),
+            NativeSet::Strings => native::register_strings(vm),
+            NativeSet::Game => native::register_game_stubs(vm, |str| println!("{}", str)),
+            NativeSet::Files => native::register_file_io(vm),
+            NativeSet::Reflection => native::register_reflection(vm),
+            NativeSet::Regex => native::register_regex(vm),
+            // test asserts report into an error list owned by the caller, so they're registered separately
+            NativeSet::Test => {}
+        }
+    }
+    for plugin in plugins {
+        plugin.register(vm)?;
+    }
+    Ok(())
+}
+
+pub fn register_globals(vm: &mut VM<'_>, globals: &HashMap<String, toml::Value>) -> anyhow::Result<()> {
+    for (name, value) in globals {
+        let res = match value {
+            toml::Value::Boolean(val) => vm.set_global(name, *val),
+            toml::Value::Integer(val) => match i32::try_from(*val) {
+                Ok(val) => vm.set_global(name, val),
+                Err(_) => vm.set_global(name, *val),
+            },
+            toml::Value::Float(val) => vm.set_global(name, *val as f32),
+            toml::Value::String(val) => vm.set_global(name, val.clone()),
+            _ => anyhow::bail!("unsupported type of global {}", name),
+        };
+        if res.is_none() {
+            println!("Warning: global {} has no matching native function", name);
+        }
+    }
+    Ok(())
+}
+
+/// Creates a VM with the natives, globals and execution settings of the config. The test natives
+/// report into `test_errors` when the config includes them, `extras` registers the natives of the
+/// command before the ones of the config, so that plugins can still replace them.
+pub fn new_vm<'pool>(
+    pool: &'pool ConstantPool,
+    config: &ShellConfig,
+    settings: &VmSettings,
+    plugins: &[Plugin],
+    test_errors: &Rc<RefCell<Vec<String>>>,
+    extras: impl FnOnce(&mut VM<'pool>),
+) -> anyhow::Result<VM<'pool>> {
+    let mut vm = timed(config.verbose, "metadata construction", || VM::new(pool));
+    timed(config.verbose, "native registration", || {
+        if settings.natives.contains(&NativeSet::Test) {
+            test::register_test_natives(&mut vm, test_errors.clone());
+        }
+        extras(&mut vm);
+        register(&mut vm, &settings.natives, plugins)?;
+        if settings.natives.contains(&NativeSet::Core) {
+            native::register_arithmetic(&mut vm, settings.arithmetic.into());
+        }
+        register_globals(&mut vm, &config.globals)
+    })?;
+    if settings.float_parity {
+        vm.set_float_format(FloatFormat::GAME);
+    }
+    vm.set_lenient_nulls(settings.lenient_nulls);
+    vm.set_fuel_limit(settings.fuel_limit);
+    vm.set_timeout(settings.timeout());
+    Ok(vm)
+}
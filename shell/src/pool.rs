@@ -0,0 +1,116 @@
+use std::fmt;
+
+use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::definition::AnyDefinition;
+use redscript_vm::metadata::qualified_name;
+
+use crate::natives::Plugin;
+use crate::{plain_vm, ShellConfig};
+
+const LARGEST_FUNCTIONS: usize = 10;
+const MAX_LISTED_NATIVES: usize = 20;
+
+#[derive(Debug, Default)]
+pub struct PoolStats {
+    classes: usize,
+    structs: usize,
+    enums: usize,
+    functions: usize,
+    fields: usize,
+    types: usize,
+    names: usize,
+    strings: usize,
+    tweakdb_ids: usize,
+    resources: usize,
+}
+
+impl PoolStats {
+    pub fn new(pool: &ConstantPool) -> Self {
+        let mut stats = PoolStats::default();
+        for (_, def) in pool.definitions() {
+            match &def.value {
+                AnyDefinition::Class(class) if class.flags.is_struct() => stats.structs += 1,
+                AnyDefinition::Class(_) => stats.classes += 1,
+                AnyDefinition::Enum(_) => stats.enums += 1,
+                AnyDefinition::Function(_) => stats.functions += 1,
+                AnyDefinition::Field(_) => stats.fields += 1,
+                AnyDefinition::Type(_) => stats.types += 1,
+                _ => {}
+            }
+        }
+        stats.names = count_entries(|i| pool.names.get(PoolIndex::new(i)).is_ok());
+        stats.strings = count_entries(|i| pool.strings.get(PoolIndex::new(i)).is_ok());
+        stats.tweakdb_ids = count_entries(|i| pool.tweakdb_ids.get(PoolIndex::new(i)).is_ok());
+        stats.resources = count_entries(|i| pool.resources.get(PoolIndex::new(i)).is_ok());
+        stats
+    }
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "classes: {}", self.classes)?;
+        writeln!(f, "structs: {}", self.structs)?;
+        writeln!(f, "enums: {}", self.enums)?;
+        writeln!(f, "functions: {}", self.functions)?;
+        writeln!(f, "fields: {}", self.fields)?;
+        writeln!(f, "types: {}", self.types)?;
+        writeln!(f, "names: {}", self.names)?;
+        writeln!(f, "strings: {}", self.strings)?;
+        writeln!(f, "tweakdb ids: {}", self.tweakdb_ids)?;
+        write!(f, "resources: {}", self.resources)
+    }
+}
+
+pub fn print_summary(pool: &ConstantPool, config: &ShellConfig, plugins: &[Plugin]) -> anyhow::Result<()> {
+    println!("{}", PoolStats::new(pool));
+
+    let mut functions = pool
+        .definitions()
+        .filter_map(|(idx, def)| match &def.value {
+            AnyDefinition::Function(fun) => Some((idx, fun.code.as_ref().len())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    functions.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    println!("largest functions:");
+    for (idx, size) in functions.into_iter().take(LARGEST_FUNCTIONS) {
+        println!("  {} ({} instructions)", qualified_name(pool, idx), size);
+    }
+
+    let vm = plain_vm(pool, config, plugins)?;
+    let unresolved = pool
+        .definitions()
+        .filter(|(_, def)| matches!(&def.value, AnyDefinition::Function(fun) if fun.flags.is_native()))
+        .filter(|(idx, _)| vm.metadata().get_native(idx.cast()).is_none())
+        .map(|(idx, _)| qualified_name(pool, idx))
+        .collect::<Vec<_>>();
+    println!("unresolved natives: {}", unresolved.len());
+    for name in unresolved.iter().take(MAX_LISTED_NATIVES) {
+        println!("  {}", name);
+    }
+    if unresolved.len() > MAX_LISTED_NATIVES {
+        println!("  ... and {} more", unresolved.len() - MAX_LISTED_NATIVES);
+    }
+    Ok(())
+}
+
+pub fn search(pool: &ConstantPool, query: &str) {
+    let query = query.to_lowercase();
+    let print_matches = |kind: &str, get: &dyn Fn(u32) -> Option<String>| {
+        for (i, str) in (0..).map_while(|i| Some((i, get(i)?))) {
+            if str.to_lowercase().contains(&query) {
+                println!("{} #{}: {}", kind, i, str);
+            }
+        }
+    };
+    print_matches("name", &|i| {
+        pool.names.get(PoolIndex::new(i)).ok().map(|str| str.to_string())
+    });
+    print_matches("string", &|i| {
+        pool.strings.get(PoolIndex::new(i)).ok().map(|str| str.to_string())
+    });
+}
+
+fn count_entries(exists: impl Fn(u32) -> bool) -> usize {
+    (0..).take_while(|&i| exists(i)).count()
+}
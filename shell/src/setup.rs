@@ -0,0 +1,61 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Install locations of the game used by the common launchers.
+const INSTALL_DIRS: &[&str] = &[
+    "C:\\Program Files (x86)\\Steam\\steamapps\\common\\Cyberpunk 2077",
+    "C:\\Program Files\\Steam\\steamapps\\common\\Cyberpunk 2077",
+    "C:\\Program Files (x86)\\GOG Galaxy\\Games\\Cyberpunk 2077",
+    "C:\\GOG Games\\Cyberpunk 2077",
+    "C:\\Program Files\\Epic Games\\Cyberpunk 2077",
+    ".local/share/Steam/steamapps/common/Cyberpunk 2077",
+    ".steam/steam/steamapps/common/Cyberpunk 2077",
+];
+
+/// The backup made by redscript is preferred because it contains the unmodified game scripts.
+const BUNDLE_FILES: &[&str] = &["final.redscripts.bk", "final.redscripts"];
+
+/// Looks for the compiled game scripts in the common install locations.
+pub fn find_bundle() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    INSTALL_DIRS
+        .iter()
+        .map(|dir| match &home {
+            Some(home) if Path::new(dir).is_relative() => Path::new(home).join(dir),
+            _ => PathBuf::from(dir),
+        })
+        .flat_map(|dir| {
+            BUNDLE_FILES
+                .iter()
+                .map(move |file| dir.join("r6").join("cache").join(file))
+        })
+        .find(|path| path.is_file())
+}
+
+/// Writes a starter config, the bundle path is left commented out if the game couldn't be found.
+pub fn write_starter_config(path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    if path.exists() {
+        anyhow::bail!("{} already exists", path.display());
+    }
+    let bundle = find_bundle();
+
+    let mut contents =
+        String::from("# where to look for the compiled bytecode (detected automatically when omitted)\n");
+    match &bundle {
+        Some(bundle) => {
+            let bundle = toml::Value::String(bundle.display().to_string());
+            writeln!(contents, "bundle_path = {bundle}")?;
+        }
+        None => writeln!(contents, "# bundle_path = \"path/to/final.redscripts\"")?,
+    }
+    contents.push_str(
+        "# where to look for project sources\n\
+         source_dir = \"src\"\n\
+         # where to look for test sources\n\
+         test_dir = \"test\"\n\
+         # native groups to register: core, strings, game, files, reflection, regex, test\n\
+         natives = [\"core\", \"strings\", \"test\"]\n",
+    );
+    std::fs::write(path, contents)?;
+    Ok(bundle)
+}
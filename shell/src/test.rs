@@ -1,18 +1,42 @@
 use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::ffi::OsStr;
+use std::hash::Hasher;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::str::FromStr;
+use std::{fmt, fs};
 
 use colored::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::bytecode::Instr;
 use redscript::definition::{Function, Visibility};
 use redscript_compiler::source_map::Files;
 use redscript_compiler::unit::CompilationUnit;
+use redscript_vm::error::{RuntimeError, RuntimeResult};
+use redscript_vm::intercept::{Intercept, NativeInterceptor};
+use redscript_vm::interop::{NativeCtx, Ret, ScriptClass};
+use redscript_vm::metadata::{short_name, Metadata, TypeId};
+use redscript_vm::recorder::{CallRecorder, NativeCall};
+use redscript_vm::value::{StringType, Value};
 use redscript_vm::{args, native, VM};
+use regex::Regex;
 use walkdir::WalkDir;
 
-use crate::ShellConfig;
+use crate::natives::{self, NativeSet, Plugin, VmSettings};
+use crate::{timed, ShellConfig};
 
-pub fn run_suite(mut pool: ConstantPool, suite: &str, config: &ShellConfig) -> anyhow::Result<()> {
+pub fn run_suite(
+    mut pool: ConstantPool,
+    suite: &str,
+    options: &TestOptions,
+    config: &ShellConfig,
+    plugins: &[Plugin],
+) -> anyhow::Result<()> {
     let sources = WalkDir::new(&config.source_dir).into_iter();
     let tests = WalkDir::new(&config.test_dir).into_iter();
     let all = sources
@@ -21,13 +45,36 @@ pub fn run_suite(mut pool: ConstantPool, suite: &str, config: &ShellConfig) -> a
     let mut files = Files::from_files(all)?;
     files.add("stdlib.reds".into(), include_str!("test-stdlib.reds").to_owned());
 
-    CompilationUnit::new_with_defaults(&mut pool)?.compile_files(&files)?;
+    timed(config.verbose, "compilation", || {
+        CompilationUnit::new_with_defaults(&mut pool)?.compile_files(&files)
+    })?;
 
-    let mut vm = VM::new(&pool);
+    let mut settings = options.repro.clone().unwrap_or_else(|| ReproSettings {
+        seed: rand::random(),
+        shuffle: options.shuffle.then(rand::random),
+        record_calls: config.record_calls,
+        vm: config.vm_settings(),
+    });
+    // the test library declares the test natives, so they're needed even if the config leaves them out
+    if !settings.vm.natives.contains(&NativeSet::Test) {
+        settings.vm.natives.push(NativeSet::Test);
+    }
 
     let test_errors = Rc::new(RefCell::new(vec![]));
-    native::register_natives(&mut vm, |str| println!("{}", str));
-    register_test_natives(&mut vm, test_errors.clone());
+    let stubs = NativeStubs::default();
+    let scope = TestScope::default();
+    let mut vm = natives::new_vm(&pool, config, &settings.vm, plugins, &test_errors, |vm| {
+        register_stub_natives(vm, stubs.clone());
+        register_context_natives(vm, scope.clone());
+        if settings.record_calls {
+            let recorder = CallRecorder::new();
+            register_recorder_natives(vm, recorder.clone(), test_errors.clone());
+            vm.set_call_recorder(Some(recorder));
+        }
+    })?;
+    if settings.vm.natives.contains(&NativeSet::Core) {
+        native::register_random(&mut vm, settings.seed);
+    }
 
     let class_idx = vm
         .metadata()
@@ -35,22 +82,224 @@ pub fn run_suite(mut pool: ConstantPool, suite: &str, config: &ShellConfig) -> a
         .ok_or_else(|| anyhow::anyhow!("test suite not defined"))?;
     let class = vm.metadata().pool().class(class_idx)?;
 
+    let mut tests = vec![];
+    let mut skipped = 0;
     for fun_idx in &class.functions {
         let fun = vm.metadata().pool().function(*fun_idx)?;
-        if fun.parameters.is_empty() && fun.visibility == Visibility::Public {
-            run_test(&mut vm, *fun_idx, test_errors.clone())?;
+        if fun.visibility == Visibility::Public && (fun.parameters.is_empty() || takes_context(vm.metadata(), fun)) {
+            if options.accepts(&test_tags(vm.metadata().pool(), *fun_idx)?) {
+                tests.push(*fun_idx);
+            } else {
+                skipped += 1;
+            }
+        }
+    }
+    if let Some(seed) = settings.shuffle {
+        tests.shuffle(&mut StdRng::seed_from_u64(seed));
+    }
+
+    let mut failed = 0;
+    for fun_idx in tests {
+        scope.enter(settings.seed, vm.metadata().pool(), fun_idx)?;
+        let passed = run_test(&mut vm, fun_idx, &stubs, test_errors.clone());
+        scope.exit();
+        if !passed? {
+            failed += 1;
         }
     }
+    if skipped > 0 {
+        println!("{} tests skipped by tag filters", skipped);
+    }
+    if failed > 0 {
+        let msg = format!(
+            "{} tests failed, rerun with: test {} --repro {}",
+            failed, suite, settings
+        );
+        println!("{}", msg.red());
+    }
     Ok(())
 }
 
-fn run_test(vm: &mut VM<'_>, fun_idx: PoolIndex<Function>, errors: Rc<RefCell<Vec<String>>>) -> anyhow::Result<()> {
-    vm.call_void(fun_idx, args!())?;
+#[derive(Debug, Default)]
+pub struct TestOptions {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    shuffle: bool,
+    repro: Option<ReproSettings>,
+}
+
+impl TestOptions {
+    pub fn parse(args: &[&str]) -> Result<Self, &'static str> {
+        const INVALID: &str =
+            "Invalid test options, expected --include [tag], --exclude [tag], --shuffle or --repro [settings]";
+
+        let mut options = TestOptions::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "--include" => options.include.push(args.next().ok_or(INVALID)?.to_lowercase()),
+                "--exclude" => options.exclude.push(args.next().ok_or(INVALID)?.to_lowercase()),
+                "--shuffle" => options.shuffle = true,
+                "--repro" => options.repro = Some(args.next().ok_or(INVALID)?.parse()?),
+                _ => return Err(INVALID),
+            }
+        }
+        Ok(options)
+    }
+
+    fn accepts(&self, tags: &[String]) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|tag| tags.contains(tag));
+        included && !self.exclude.iter().any(|tag| tags.contains(tag))
+    }
+}
+
+/// Collects the tags of a test, given by a name prefix like `Slow_LoadsAllRecords` or by
+/// `Tag("slow")` calls at the start of the test body.
+fn test_tags(pool: &ConstantPool, fun_idx: PoolIndex<Function>) -> anyhow::Result<Vec<String>> {
+    let name = pool.def_name(fun_idx)?;
+    let mut tags = vec![];
+    if let Some((prefix, _)) = name.split_once('_') {
+        tags.push(prefix.to_lowercase());
+    }
+
+    let fun = pool.function(fun_idx)?;
+    for instrs in fun.code.as_ref().chunks(3) {
+        match instrs {
+            [Instr::InvokeStatic(_, _, idx, _), Instr::StringConst(str), Instr::ParamEnd]
+                if &*pool.def_name(*idx)? == "Tag" =>
+            {
+                tags.push(pool.strings.get(*str)?.to_lowercase());
+            }
+            _ => break,
+        }
+    }
+    Ok(tags)
+}
+
+/// Everything that affects the outcome of a test run, printed when tests fail so that the run can be repeated.
+#[derive(Debug, Clone)]
+struct ReproSettings {
+    seed: u64,
+    shuffle: Option<u64>,
+    record_calls: bool,
+    vm: VmSettings,
+}
+
+impl fmt::Display for ReproSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "seed={}", self.seed)?;
+        if let Some(shuffle) = self.shuffle {
+            write!(f, ",shuffle={}", shuffle)?;
+        }
+        // natives are joined with '+' because entries are separated by commas
+        let natives = self
+            .vm
+            .natives
+            .iter()
+            .copied()
+            .map(NativeSet::name)
+            .collect::<Vec<_>>()
+            .join("+");
+        write!(
+            f,
+            ",float_parity={},record_calls={},lenient_nulls={},arithmetic={},natives={}",
+            self.vm.float_parity,
+            self.record_calls,
+            self.vm.lenient_nulls,
+            self.vm.arithmetic.name(),
+            natives
+        )?;
+        if let Some(fuel_limit) = self.vm.fuel_limit {
+            write!(f, ",fuel_limit={}", fuel_limit)?;
+        }
+        if let Some(timeout_secs) = self.vm.timeout_secs {
+            write!(f, ",timeout_secs={}", timeout_secs)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ReproSettings {
+    type Err = &'static str;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        const INVALID: &str = "Invalid repro settings";
+
+        let mut settings = ReproSettings {
+            seed: 0,
+            shuffle: None,
+            record_calls: false,
+            vm: VmSettings {
+                natives: ShellConfig::default_natives(),
+                ..VmSettings::default()
+            },
+        };
+        for entry in str.split(',') {
+            match entry.split_once('=').ok_or(INVALID)? {
+                ("seed", val) => settings.seed = val.parse().map_err(|_| INVALID)?,
+                ("shuffle", val) => settings.shuffle = Some(val.parse().map_err(|_| INVALID)?),
+                ("float_parity", val) => settings.vm.float_parity = val.parse().map_err(|_| INVALID)?,
+                ("record_calls", val) => settings.record_calls = val.parse().map_err(|_| INVALID)?,
+                ("lenient_nulls", val) => settings.vm.lenient_nulls = val.parse().map_err(|_| INVALID)?,
+                ("arithmetic", val) => settings.vm.arithmetic = val.parse().map_err(|()| INVALID)?,
+                ("natives", val) => {
+                    settings.vm.natives = val
+                        .split('+')
+                        .filter(|name| !name.is_empty())
+                        .map(str::parse)
+                        .collect::<Result<_, ()>>()
+                        .map_err(|()| INVALID)?;
+                }
+                ("fuel_limit", val) => settings.vm.fuel_limit = Some(val.parse().map_err(|_| INVALID)?),
+                ("timeout_secs", val) => settings.vm.timeout_secs = Some(val.parse().map_err(|_| INVALID)?),
+                _ => return Err(INVALID),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+fn run_test(
+    vm: &mut VM<'_>,
+    fun_idx: PoolIndex<Function>,
+    stubs: &NativeStubs,
+    errors: Rc<RefCell<Vec<String>>>,
+) -> anyhow::Result<bool> {
+    if let Some(recorder) = vm.call_recorder() {
+        recorder.clear();
+    }
+    stubs.clear();
+    let res = if takes_context(vm.metadata(), vm.metadata().pool().function(fun_idx)?) {
+        let name = vm.metadata().pool().def_name(fun_idx)?;
+        vm.new_ref::<TestContext>().and_then(|context| {
+            let res = vm
+                .write_field(&context, "name", short_name(&name).to_owned())
+                .and_then(|()| vm.call_with_ref(fun_idx, &context, args!()));
+            vm.release_ref(context);
+            res
+        })
+    } else {
+        vm.call_void(fun_idx, args!())
+    };
+    if let Err(err) = res {
+        let RuntimeError::Thrown { message, location } = err.cause() else {
+            anyhow::bail!("{}", err.display_with(vm.metadata()));
+        };
+        let mut msg = match location {
+            Some(location) => format!("Uncaught error: {} ({})", message, location),
+            None => format!("Uncaught error: {}", message),
+        };
+        if let Some(backtrace) = err.backtrace() {
+            msg = format!("{}\n{}", msg, backtrace);
+        }
+        errors.borrow_mut().push(msg);
+    }
 
     let name = vm.metadata().pool().def_name(fun_idx)?;
     let pretty_name = pretty_test_name(&name);
     let mut errors = errors.borrow_mut();
-    if errors.is_empty() {
+    let passed = errors.is_empty();
+    if passed {
         println!("{}", format!("+ {}", pretty_name).green());
     } else {
         println!("{}", format!("- {}", pretty_name).red());
@@ -59,7 +308,11 @@ fn run_test(vm: &mut VM<'_>, fun_idx: PoolIndex<Function>, errors: Rc<RefCell<Ve
         }
         errors.clear();
     }
-    Ok(())
+    for warning in vm.warnings() {
+        let location = warning.location.qualified_name(vm.metadata().pool());
+        println!("{}", format!("! {} in {}", warning.kind, location).yellow());
+    }
+    Ok(passed)
 }
 
 fn pretty_test_name(name: &str) -> String {
@@ -77,7 +330,8 @@ fn pretty_test_name(name: &str) -> String {
     str
 }
 
-fn register_test_natives(vm: &mut VM<'_>, errors: Rc<RefCell<Vec<String>>>) {
+pub fn register_test_natives(vm: &mut VM<'_>, errors: Rc<RefCell<Vec<String>>>) {
+    vm.register_try();
     let meta = vm.metadata_mut();
 
     let copy = errors.clone();
@@ -90,9 +344,309 @@ fn register_test_natives(vm: &mut VM<'_>, errors: Rc<RefCell<Vec<String>>>) {
         let msg = format!("{} is equal to {}", a, b);
         copy.borrow_mut().push(msg);
     });
+    let copy = errors.clone();
+    meta.register_native("FailArrayLength", move |a: i32, b: i32| {
+        let msg = format!("array of length {} is not equal to array of length {}", a, b);
+        copy.borrow_mut().push(msg);
+    });
+    let copy = errors.clone();
+    meta.register_native("FailArrayElement", move |i: i32, a: String, b: String| {
+        let msg = format!("element {} of the array is {}, expected {}", i, a, b);
+        copy.borrow_mut().push(msg);
+    });
+    let copy = errors.clone();
+    meta.register_native("FailContains", move |val: String| {
+        let msg = format!("array does not contain {}", val);
+        copy.borrow_mut().push(msg);
+    });
+    let copy = errors.clone();
+    meta.register_native("FailNoError", move |function: String| {
+        let msg = format!("{} did not fail", function);
+        copy.borrow_mut().push(msg);
+    });
+    let copy = errors.clone();
+    meta.register_native("FailErrorMessage", move |error: String, expected: String| {
+        let msg = format!("failed with {:?}, expected {:?}", error, expected);
+        copy.borrow_mut().push(msg);
+    });
+    let copy = errors.clone();
+    meta.register_native("AssertStringMatches", move |str: String, pattern: String| {
+        let msg = match Regex::new(&pattern) {
+            Ok(regex) if regex.is_match(&str) => return,
+            Ok(_) => format!("{:?} does not match {}", str, pattern),
+            Err(err) => format!("invalid pattern {}: {}", pattern, err),
+        };
+        copy.borrow_mut().push(msg);
+    });
+    meta.register_native("Tag", |_: String| {});
     meta.register_native("Assert", move |res: bool| {
         if !res {
             errors.borrow_mut().push("Assertion failed".to_owned());
         }
     });
 }
+
+/// The script class passed to the test methods that accept a context.
+struct TestContext;
+
+impl ScriptClass for TestContext {
+    const NAME: &'static str = "TestContext";
+}
+
+fn takes_context(meta: &Metadata<'_>, fun: &Function) -> bool {
+    let Some(class) = meta.get_class(TestContext::NAME) else {
+        return false;
+    };
+    match fun.parameters.as_slice() {
+        [param] => meta
+            .pool()
+            .parameter(*param)
+            .ok()
+            .and_then(|param| meta.get_type(param.type_))
+            .is_some_and(|typ| *typ == TypeId::Ref(class)),
+        _ => false,
+    }
+}
+
+/// The resources of the test in progress, used by the natives of `TestContext`.
+#[derive(Debug, Clone)]
+struct TestScope {
+    rng: Rc<RefCell<StdRng>>,
+    temp_dirs: Rc<RefCell<Vec<PathBuf>>>,
+    /// The unmangled name of the test in progress.
+    test: Rc<RefCell<String>>,
+}
+
+impl Default for TestScope {
+    fn default() -> Self {
+        Self {
+            rng: Rc::new(RefCell::new(StdRng::seed_from_u64(0))),
+            temp_dirs: Rc::default(),
+            test: Rc::default(),
+        }
+    }
+}
+
+impl TestScope {
+    /// Seeds the random numbers with the seed of the run and the name of the test, so that they
+    /// don't depend on the order of tests.
+    fn enter(&self, seed: u64, pool: &ConstantPool, fun_idx: PoolIndex<Function>) -> anyhow::Result<()> {
+        let name = short_name(&pool.def_name(fun_idx)?).to_owned();
+        let mut hasher = DefaultHasher::new();
+        hasher.write(name.as_bytes());
+        *self.rng.borrow_mut() = StdRng::seed_from_u64(seed ^ hasher.finish());
+        *self.test.borrow_mut() = name;
+        Ok(())
+    }
+
+    fn exit(&self) {
+        let root = temp_root();
+        for dir in self.temp_dirs.borrow_mut().drain(..) {
+            if !dir.starts_with(&root) {
+                println!("Warning: not removing {} outside of {}", dir.display(), root.display());
+                continue;
+            }
+            if let Err(err) = fs::remove_dir_all(&dir) {
+                println!("Warning: failed to remove {}: {}", dir.display(), err);
+            }
+        }
+    }
+}
+
+fn register_context_natives(vm: &mut VM<'_>, scope: TestScope) {
+    let meta = vm.metadata_mut();
+
+    meta.register_native("TestLog", |test: String, message: String| {
+        println!("[{}] {}", test, message);
+    });
+    let copy = scope.clone();
+    meta.register_raw_native("TestTempDir", move |ctx| {
+        ctx.expect_args(0)?;
+        // the directory is named after the running test, scripts can't choose where it's created
+        let test = copy.test.borrow().clone();
+        if test.is_empty() || test.contains(['/', '\\']) || test.contains("..") {
+            return Err(RuntimeError::Thrown {
+                message: format!("{test:?} can't be used as the name of a temporary directory"),
+                location: None,
+            });
+        }
+        let dir = temp_root().join(test);
+        fs::create_dir_all(&dir).map_err(|err| RuntimeError::Thrown {
+            message: format!("failed to create {}: {}", dir.display(), err),
+            location: None,
+        })?;
+        let path = dir.display().to_string();
+        // every call returns the same directory for a test, it's removed once
+        let mut temp_dirs = copy.temp_dirs.borrow_mut();
+        if !temp_dirs.contains(&dir) {
+            temp_dirs.push(dir);
+        }
+        ctx.push(path)
+    });
+    let copy = scope.clone();
+    meta.register_native("TestRandRange", move |min: i32, max: i32| {
+        if min >= max {
+            return Ret(min);
+        }
+        Ret(copy.rng.borrow_mut().gen_range(min..max))
+    });
+    meta.register_native("TestRandF", move || Ret(scope.rng.borrow_mut().gen::<f32>()));
+}
+
+/// The directory holding the temporary directories of the tests run by this process.
+fn temp_root() -> PathBuf {
+    std::env::temp_dir().join(format!("redscript-test-{}", std::process::id()))
+}
+
+/// Natives replaced for the duration of a test, by their unmangled name.
+#[derive(Debug, Clone, Default)]
+struct NativeStubs {
+    stubs: Rc<RefCell<HashMap<String, Stub>>>,
+}
+
+impl NativeStubs {
+    fn clear(&self) {
+        self.stubs.borrow_mut().clear();
+    }
+}
+
+impl NativeInterceptor for NativeStubs {
+    fn before(&mut self, call: &NativeCall, ctx: &mut NativeCtx<'_, '_>) -> RuntimeResult<Intercept> {
+        let stubs = self.stubs.borrow();
+        let Some(stub) = stubs.get(call.short_name()) else {
+            return Ok(Intercept::Proceed);
+        };
+        ctx.discard_args()?;
+        match stub {
+            Stub::Fail(message) => Err(RuntimeError::Thrown {
+                message: message.clone(),
+                location: None,
+            }),
+            Stub::Return(_) if !ctx.returns() => Ok(Intercept::Handled),
+            Stub::Return(val) => {
+                let typ = ctx
+                    .pool()
+                    .function(call.function)
+                    .ok()
+                    .and_then(|function| function.return_type)
+                    .and_then(|typ| ctx.metadata().get_type(typ));
+                let Some(res) = typ.and_then(|typ| val.to_vm(typ, ctx)) else {
+                    let typ = typ.and_then(|typ| typ.name(ctx.pool()).ok()).unwrap_or_default();
+                    return Err(RuntimeError::Thrown {
+                        message: format!(
+                            "the stub of {} returns {:?}, which isn't a {}",
+                            call.short_name(),
+                            val,
+                            typ
+                        ),
+                        location: None,
+                    });
+                };
+                ctx.push_value(res).map(|()| Intercept::Handled)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Stub {
+    Return(StubValue),
+    Fail(String),
+}
+
+/// A value returned by a stub, kept outside of the VM heap.
+#[derive(Debug)]
+enum StubValue {
+    Bool(bool),
+    Int(i32),
+    Int64(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+}
+
+impl StubValue {
+    fn from_vm(val: &Value<'_>, pool: &ConstantPool) -> Option<Self> {
+        let res = match &*val.unpinned() {
+            Value::Variant(var) => return Self::from_vm(&var.value, pool),
+            Value::Bool(val) => StubValue::Bool(*val),
+            Value::I32(val) => StubValue::Int(*val),
+            Value::I64(val) => StubValue::Int64(*val),
+            Value::F32(val) => StubValue::Float(*val),
+            Value::F64(val) => StubValue::Double(*val),
+            val @ (Value::Str(_) | Value::InternStr(StringType::String, _)) => StubValue::String(val.to_string(pool)),
+            _ => return None,
+        };
+        Some(res)
+    }
+
+    /// Converts the value to the return type of the stubbed native, numbers are converted to other
+    /// numeric types when they fit.
+    fn to_vm<'gc>(&self, typ: &TypeId, ctx: &NativeCtx<'_, 'gc>) -> Option<Value<'gc>> {
+        let res = match (self, typ) {
+            (StubValue::Bool(val), TypeId::Bool) => Value::Bool(*val),
+            (StubValue::Int(val), _) => return int_to_vm(i64::from(*val), typ),
+            (StubValue::Int64(val), _) => return int_to_vm(*val, typ),
+            (StubValue::Float(val), TypeId::F32) => Value::F32(*val),
+            (StubValue::Float(val), TypeId::F64) => Value::F64(f64::from(*val)),
+            (StubValue::Double(val), TypeId::F32) => Value::F32(*val as f32),
+            (StubValue::Double(val), TypeId::F64) => Value::F64(*val),
+            (StubValue::String(val), TypeId::String) => ctx.intern(val),
+            _ => return None,
+        };
+        Some(res)
+    }
+}
+
+fn int_to_vm<'gc>(val: i64, typ: &TypeId) -> Option<Value<'gc>> {
+    let res = match typ {
+        TypeId::I8 => Value::I8(val.try_into().ok()?),
+        TypeId::I16 => Value::I16(val.try_into().ok()?),
+        TypeId::I32 => Value::I32(val.try_into().ok()?),
+        TypeId::I64 => Value::I64(val),
+        TypeId::U8 => Value::U8(val.try_into().ok()?),
+        TypeId::U16 => Value::U16(val.try_into().ok()?),
+        TypeId::U32 => Value::U32(val.try_into().ok()?),
+        TypeId::U64 => Value::U64(val.try_into().ok()?),
+        TypeId::F32 => Value::F32(val as f32),
+        TypeId::F64 => Value::F64(val as f64),
+        TypeId::Enum(_) => Value::EnumVal(val),
+        _ => return None,
+    };
+    Some(res)
+}
+
+fn register_stub_natives(vm: &mut VM<'_>, stubs: NativeStubs) {
+    let meta = vm.metadata_mut();
+
+    let copy = stubs.clone();
+    meta.register_raw_native("WithNativeStub", move |ctx| {
+        ctx.expect_args(2)?;
+        let val = ctx.pop_value()?;
+        let name: String = ctx.pop()?;
+        let val = StubValue::from_vm(&val, ctx.pool())
+            .ok_or(RuntimeError::InvalidNativeArgument("Unsupported stub value"))?;
+        copy.stubs.borrow_mut().insert(name, Stub::Return(val));
+        Ok(())
+    });
+    let copy = stubs.clone();
+    meta.register_native("WithNativeFailure", move |name: String, message: String| {
+        copy.stubs.borrow_mut().insert(name, Stub::Fail(message));
+    });
+    vm.add_interceptor(stubs);
+}
+
+fn register_recorder_natives(vm: &mut VM<'_>, recorder: CallRecorder, errors: Rc<RefCell<Vec<String>>>) {
+    let meta = vm.metadata_mut();
+
+    meta.register_native("AssertCalled", move |name: String, times: i32| {
+        let count = recorder.count(&name);
+        if count != times as usize {
+            let msg = format!(
+                "expected {} to be called {} times, but it was called {} times",
+                name, times, count
+            );
+            errors.borrow_mut().push(msg);
+        }
+    });
+}
@@ -1,35 +1,36 @@
+use std::cell::{Ref, RefMut};
+use std::cmp::Ordering;
+
 use crate::error::RuntimeResult;
 use crate::*;
 
 pub fn clear(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
-    vm.pop(|val, mc| val.unpinned().as_array().unwrap().borrow_mut(mc).clear());
-    Ok(())
+    vm.pop(|val, mc| {
+        dynamic(&val.unpinned())?.borrow_mut(mc).clear();
+        Ok(())
+    })
 }
 
 pub fn size(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
-    vm.unop(|val, _| Value::I32(val.unpinned().as_array().unwrap().borrow().len() as i32));
-    Ok(())
+    vm.unop(|val, _| Ok(Value::I32(elements(&val.unpinned())?.len() as i32)))
 }
 
 pub fn resize(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
     vm.exec(frame)?;
     vm.arena.mutate(|mc, root| {
-        let val = root.pop(mc).unwrap();
+        let size = index(&root.pop(mc)?)?;
+        let val = root.pop(mc)?;
         let val = val.unpinned();
-        let size = val
-            .as_i32()
-            .copied()
-            .map(|i| i as u64)
-            .or_else(|| val.as_u64().copied())
-            .unwrap();
-        let val = root.pop(mc).unwrap();
-        let val = val.unpinned();
-        let array = val.as_array().unwrap();
-        array.borrow_mut(mc).resize(size as usize, Value::Obj(Obj::Null));
-    });
+        let mut array = dynamic(&val)?.borrow_mut(mc);
+        let len = array.len();
+        let size = usize::try_from(size).map_err(|_| RuntimeError::IndexOutOfBounds { index: size, len })?;
+        array.resize(size, Value::Obj(Obj::Null));
+        Ok(())
+    })?;
+    vm.record_alloc(1)?;
     Ok(())
 }
 
@@ -38,14 +39,12 @@ pub fn find_first(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
     vm.binop(|array, needle, _| {
         let array = array.unpinned();
-        let array = array.as_array().unwrap();
-        if let Some(res) = array.borrow().iter().find(|el| el.equals(&needle)).cloned() {
-            res
+        if let Some(res) = elements(&array)?.iter().find(|el| el.equals(&needle)).cloned() {
+            Ok(res)
         } else {
-            Value::Obj(Obj::Null)
+            Ok(Value::Obj(Obj::Null))
         }
-    });
-    Ok(())
+    })
 }
 
 pub fn find_last(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
@@ -53,14 +52,12 @@ pub fn find_last(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
     vm.binop(|array, needle, _| {
         let array = array.unpinned();
-        let array = array.as_array().unwrap();
-        if let Some(res) = array.borrow().iter().rev().find(|el| el.equals(&needle)) {
-            res.clone()
+        if let Some(res) = elements(&array)?.iter().rev().find(|el| el.equals(&needle)) {
+            Ok(res.clone())
         } else {
-            Value::Obj(Obj::Null)
+            Ok(Value::Obj(Obj::Null))
         }
-    });
-    Ok(())
+    })
 }
 
 pub fn contains(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
@@ -68,11 +65,9 @@ pub fn contains(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
     vm.binop(|array, needle, _| {
         let array = array.unpinned();
-        let array = array.as_array().unwrap();
-        let exists = array.borrow().iter().any(|el| el.equals(&needle));
-        Value::Bool(exists)
-    });
-    Ok(())
+        let exists = elements(&array)?.iter().any(|el| el.equals(&needle));
+        Ok(Value::Bool(exists))
+    })
 }
 
 pub fn count(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
@@ -80,34 +75,32 @@ pub fn count(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
     vm.binop(|array, needle, _| {
         let array = array.unpinned();
-        let array = array.as_array().unwrap();
-        let count = array.borrow().iter().filter(|el| el.equals(&needle)).count();
-        Value::I32(count as i32)
-    });
-    Ok(())
+        let count = elements(&array)?.iter().filter(|el| el.equals(&needle)).count();
+        Ok(Value::I32(count as i32))
+    })
 }
 
 pub fn push(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
     vm.exec(frame)?;
     vm.arena.mutate(|mc, root| {
-        let val = root.pop(mc).unwrap();
-        let array = root.pop(mc).unwrap();
+        let val = root.pop(mc)?;
+        let array = root.pop(mc)?;
         let array = array.unpinned();
-        let array = array.as_array().unwrap();
-        array.borrow_mut(mc).push(val);
-    });
+        dynamic(&array)?.borrow_mut(mc).push(val.copied(mc));
+        Ok(())
+    })?;
+    vm.record_alloc(1)?;
     Ok(())
 }
 
 pub fn pop(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
     vm.unop(|array, mc| {
-        let binding = array.unpinned();
-        let array = binding.as_array().unwrap();
-        array.borrow_mut(mc).pop().unwrap()
-    });
-    Ok(())
+        let array = array.unpinned();
+        let res = dynamic(&array)?.borrow_mut(mc).pop();
+        res.ok_or(RuntimeError::IndexOutOfBounds { index: -1, len: 0 })
+    })
 }
 
 pub fn insert(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
@@ -115,15 +108,19 @@ pub fn insert(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
     vm.exec(frame)?;
     vm.arena.mutate(|mc, root| {
-        let value = root.pop(mc).unwrap();
-        let index = root.pop(mc).unwrap();
-        let index = index.unpinned();
-        let index = index.as_i32().unwrap();
-        let array = root.pop(mc).unwrap();
+        let value = root.pop(mc)?;
+        let index = index(&root.pop(mc)?)?;
+        let array = root.pop(mc)?;
         let array = array.unpinned();
-        let array = array.as_array().unwrap();
-        array.borrow_mut(mc).insert(*index as usize, value);
-    });
+        let mut array = dynamic(&array)?.borrow_mut(mc);
+        let len = array.len();
+        match usize::try_from(index) {
+            Ok(i) if i <= len => array.insert(i, value.copied(mc)),
+            _ => return Err(RuntimeError::IndexOutOfBounds { index, len }),
+        }
+        Ok(())
+    })?;
+    vm.record_alloc(1)?;
     Ok(())
 }
 
@@ -132,16 +129,14 @@ pub fn remove(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
     vm.binop(|array, needle, mc| {
         let array = array.unpinned();
-        let array = array.as_array().unwrap();
-        let mut array = array.borrow_mut(mc);
+        let mut array = dynamic(&array)?.borrow_mut(mc);
         if let Some(idx) = array.iter().position(|el| el.equals(&needle)) {
             array.remove(idx);
-            Value::Bool(true)
+            Ok(Value::Bool(true))
         } else {
-            Value::Bool(false)
+            Ok(Value::Bool(false))
         }
-    });
-    Ok(())
+    })
 }
 
 pub fn erase(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
@@ -149,39 +144,217 @@ pub fn erase(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
     vm.binop(|array, index, mc| {
         let array = array.unpinned();
-        let array = array.as_array().unwrap();
-        let mut array = array.borrow_mut(mc);
-        let index = index.unpinned();
-        let index = index.as_i32().unwrap();
-        if array.get(*index as usize).is_some() {
-            array.remove(*index as usize);
-            Value::Bool(true)
-        } else {
-            Value::Bool(false)
+        let mut array = dynamic(&array)?.borrow_mut(mc);
+        match usize::try_from(self::index(&index)?) {
+            Ok(i) if i < array.len() => {
+                array.remove(i);
+                Ok(Value::Bool(true))
+            }
+            _ => Ok(Value::Bool(false)),
         }
-    });
-    Ok(())
+    })
 }
 
 pub fn last(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
     vm.unop(|array, _| {
         let array = array.unpinned();
-        let array = array.as_array().unwrap();
-        array.borrow().last().unwrap().clone()
-    });
-    Ok(())
+        let res = elements(&array)?.last().cloned();
+        res.ok_or(RuntimeError::IndexOutOfBounds { index: -1, len: 0 })
+    })
 }
 
-pub fn element(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
+pub fn element(vm: &mut VM<'_>, frame: &mut Frame<'_>, access: Access) -> RuntimeResult<()> {
+    vm.exec_with(frame, access.enclosing())?;
     vm.exec(frame)?;
+    vm.binop(|array, index, mc| {
+        let array = array.unpinned();
+        let index = self::index(&index)?;
+        // elements aren't pinned for out parameters, only the structs of assigned elements are unshared
+        if access != Access::Write {
+            return get(&elements(&array)?, index).cloned();
+        }
+        let mut elems = elements_mut(&array, mc)?;
+        let elem = slot(&mut elems, index)?;
+        elem.unshare(mc);
+        Ok(elem.clone())
+    })
+}
+
+pub fn sort(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
     vm.exec(frame)?;
-    vm.binop(|array, index, _| {
+    let pool = vm.metadata.pool();
+    vm.pop(|array, mc| {
         let array = array.unpinned();
-        let array = array.as_array().unwrap();
-        let index = index.unpinned();
-        let index = index.as_i32().unwrap();
-        array.borrow().get(*index as usize).unwrap().clone()
-    });
+        let mut elems = dynamic(&array)?.borrow_mut(mc);
+        let mut order = (0..elems.len()).collect::<Vec<_>>();
+        merge_sort(&mut order, &mut |lhs, rhs| {
+            Ok(compare(&elems[lhs], &elems[rhs], pool)? == Ordering::Less)
+        })?;
+        *elems = order.iter().map(|&i| elems[i].clone()).collect();
+        Ok(())
+    })
+}
+
+pub fn sort_by_predicate(vm: &mut VM<'_>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
+    vm.exec(frame)?;
+    vm.exec(frame)?;
+    let pool = vm.metadata.pool();
+    let name = vm.pop(|val, _| Ok(val.to_string(pool)))?;
+    let predicate = vm
+        .metadata
+        .get_function(&name)
+        .ok_or_else(|| RuntimeError::UndefinedMethod(name.clone()))?;
+    let params = &pool
+        .function(predicate)
+        .map_err(|_| RuntimeError::missing(predicate))?
+        .parameters;
+    if params.len() != 2 {
+        return Err(RuntimeError::InvalidInteropParameters);
+    }
+
+    // the array stays on the stack while the predicate is evaluated
+    let (pos, len) = vm.arena.mutate(|_, root| {
+        let stack = root.stack.borrow();
+        let array = stack.last().ok_or(RuntimeError::InvalidStack)?.unpinned();
+        let len = dynamic(&array)?.borrow().len();
+        Ok((stack.len() - 1, len))
+    })?;
+    let mut order = (0..len).collect::<Vec<_>>();
+    merge_sort(&mut order, &mut |lhs, rhs| {
+        vm.arena.mutate(|mc, root| {
+            let array = {
+                let stack = root.stack.borrow();
+                let array = stack.get(pos).ok_or(RuntimeError::InvalidStack)?.unpinned();
+                dynamic(&array)?.clone()
+            };
+            // the predicate can modify the array, so the indexes have to be checked every time
+            let array = array.borrow();
+            root.push(get(&array, lhs as i64)?.clone(), mc);
+            root.push(get(&array, rhs as i64)?.clone(), mc);
+            Ok(())
+        })?;
+        vm.call_with_params(predicate, params)?;
+        vm.pop(|val, _| {
+            val.unpinned()
+                .as_bool()
+                .copied()
+                .ok_or(RuntimeError::TypeMismatch("sort predicate has to return a Bool"))
+        })
+    })?;
+
+    vm.pop(|array, mc| {
+        let array = array.unpinned();
+        let mut array = dynamic(&array)?.borrow_mut(mc);
+        let sorted = order
+            .iter()
+            .map(|&i| get(&array, i as i64).cloned())
+            .collect::<RuntimeResult<_>>()?;
+        *array = sorted;
+        Ok(())
+    })
+}
+
+/// Reads an array index, which can be either an Int32 or a Uint64.
+pub(crate) fn index(val: &Value<'_>) -> RuntimeResult<i64> {
+    match &*val.unpinned() {
+        Value::I32(i) => Ok((*i).into()),
+        Value::U64(i) => Ok(i64::try_from(*i).unwrap_or(i64::MAX)),
+        _ => Err(RuntimeError::TypeMismatch("expected an array index")),
+    }
+}
+
+/// Borrows an element of an array for reading.
+fn get<'a, 'gc>(elems: &'a [Value<'gc>], index: i64) -> RuntimeResult<&'a Value<'gc>> {
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| elems.get(i))
+        .ok_or(RuntimeError::IndexOutOfBounds {
+            index,
+            len: elems.len(),
+        })
+}
+
+/// Borrows an element of an array for writing.
+pub(crate) fn slot<'a, 'gc>(elems: &'a mut [Value<'gc>], index: i64) -> RuntimeResult<&'a mut Value<'gc>> {
+    let len = elems.len();
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| elems.get_mut(i))
+        .ok_or(RuntimeError::IndexOutOfBounds { index, len })
+}
+
+fn dynamic<'a, 'gc>(val: &'a Value<'gc>) -> RuntimeResult<&'a GcRefLock<'gc, Vec<Value<'gc>>>> {
+    val.as_array().ok_or(RuntimeError::TypeMismatch("expected an array"))
+}
+
+/// Borrows the elements of a dynamic or a static array.
+fn elements<'a, 'gc>(val: &'a Value<'gc>) -> RuntimeResult<Ref<'a, [Value<'gc>]>> {
+    match val {
+        Value::Array(arr) => Ok(Ref::map(arr.borrow(), Vec::as_slice)),
+        Value::StaticArray(arr) => Ok(Ref::map(arr.borrow(), AsRef::as_ref)),
+        _ => Err(RuntimeError::TypeMismatch("expected an array")),
+    }
+}
+
+/// Borrows the elements of a dynamic or a static array for writing.
+fn elements_mut<'a, 'gc>(val: &'a Value<'gc>, mc: &Mutation<'gc>) -> RuntimeResult<RefMut<'a, [Value<'gc>]>> {
+    match val {
+        Value::Array(arr) => Ok(RefMut::map(arr.borrow_mut(mc), Vec::as_mut_slice)),
+        Value::StaticArray(arr) => Ok(RefMut::map(arr.borrow_mut(mc), AsMut::as_mut)),
+        _ => Err(RuntimeError::TypeMismatch("expected an array")),
+    }
+}
+
+/// Orders two elements of the same primitive type, other elements can't be sorted without a predicate.
+fn compare(lhs: &Value<'_>, rhs: &Value<'_>, pool: &ConstantPool) -> RuntimeResult<Ordering> {
+    let ord = match (&*lhs.unpinned(), &*rhs.unpinned()) {
+        (Value::I8(lhs), Value::I8(rhs)) => lhs.cmp(rhs),
+        (Value::I16(lhs), Value::I16(rhs)) => lhs.cmp(rhs),
+        (Value::I32(lhs), Value::I32(rhs)) => lhs.cmp(rhs),
+        (Value::I64(lhs), Value::I64(rhs)) => lhs.cmp(rhs),
+        (Value::U8(lhs), Value::U8(rhs)) => lhs.cmp(rhs),
+        (Value::U16(lhs), Value::U16(rhs)) => lhs.cmp(rhs),
+        (Value::U32(lhs), Value::U32(rhs)) => lhs.cmp(rhs),
+        (Value::U64(lhs), Value::U64(rhs)) => lhs.cmp(rhs),
+        (Value::F32(lhs), Value::F32(rhs)) => lhs.total_cmp(rhs),
+        (Value::F64(lhs), Value::F64(rhs)) => lhs.total_cmp(rhs),
+        (Value::Bool(lhs), Value::Bool(rhs)) => lhs.cmp(rhs),
+        (Value::EnumVal(lhs), Value::EnumVal(rhs)) => lhs.cmp(rhs),
+        (Value::NodeRef(lhs), Value::NodeRef(rhs)) | (Value::Cruid(lhs), Value::Cruid(rhs)) => lhs.cmp(rhs),
+        (lhs @ (Value::Str(_) | Value::InternStr(_, _)), rhs @ (Value::Str(_) | Value::InternStr(_, _))) => {
+            lhs.to_string(pool).cmp(&rhs.to_string(pool))
+        }
+        _ => return Err(RuntimeError::TypeMismatch("array elements can't be compared")),
+    };
+    Ok(ord)
+}
+
+/// Stable merge sort with a fallible `less` comparator, predicates are script calls and elements may not be comparable.
+fn merge_sort<F>(items: &mut [usize], less: &mut F) -> RuntimeResult<()>
+where
+    F: FnMut(usize, usize) -> RuntimeResult<bool>,
+{
+    if items.len() <= 1 {
+        return Ok(());
+    }
+    let mid = items.len() / 2;
+    merge_sort(&mut items[..mid], less)?;
+    merge_sort(&mut items[mid..], less)?;
+
+    let mut merged = Vec::with_capacity(items.len());
+    let (mut i, mut j) = (0, mid);
+    while i < mid && j < items.len() {
+        if less(items[j], items[i])? {
+            merged.push(items[j]);
+            j += 1;
+        } else {
+            merged.push(items[i]);
+            i += 1;
+        }
+    }
+    merged.extend_from_slice(&items[i..mid]);
+    merged.extend_from_slice(&items[j..]);
+    items.copy_from_slice(&merged);
     Ok(())
 }
@@ -0,0 +1,107 @@
+use std::fmt;
+
+use redscript::bundle::PoolIndex;
+use redscript::bytecode::Instr;
+use redscript::definition::{AnyDefinition, Function};
+
+use crate::VM;
+
+/// A function of the pool that would fail when executed by the VM.
+#[derive(Debug, Clone)]
+pub enum CompatIssue {
+    /// A native function without an implementation registered in the VM.
+    MissingNative {
+        function: PoolIndex<Function>,
+        name: String,
+    },
+    /// A function calling a native that isn't implemented.
+    CallsMissingNative {
+        function: PoolIndex<Function>,
+        name: String,
+        native: String,
+        offset: u16,
+    },
+    /// A function using an instruction the VM doesn't implement.
+    UnsupportedInstr {
+        function: PoolIndex<Function>,
+        name: String,
+        instr: &'static str,
+        offset: u16,
+    },
+}
+
+impl CompatIssue {
+    pub fn function(&self) -> PoolIndex<Function> {
+        match self {
+            CompatIssue::MissingNative { function, .. }
+            | CompatIssue::CallsMissingNative { function, .. }
+            | CompatIssue::UnsupportedInstr { function, .. } => *function,
+        }
+    }
+}
+
+impl fmt::Display for CompatIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompatIssue::MissingNative { name, .. } => write!(f, "native {name} is not defined"),
+            CompatIssue::CallsMissingNative {
+                name, native, offset, ..
+            } => write!(f, "{name} calls undefined native {native} (offset {offset})"),
+            CompatIssue::UnsupportedInstr {
+                name, instr, offset, ..
+            } => {
+                write!(f, "{name} uses unsupported instruction {instr} (offset {offset})")
+            }
+        }
+    }
+}
+
+/// Finds the functions that would fail because of missing natives or unsupported instructions,
+/// in the order of definitions. Interceptors are not taken into account, since they can only
+/// handle natives at runtime.
+pub fn compat_report(vm: &VM<'_>) -> Vec<CompatIssue> {
+    let meta = vm.metadata();
+    let pool = meta.pool();
+    let mut issues = vec![];
+
+    for (idx, def) in pool.definitions() {
+        let AnyDefinition::Function(function) = &def.value else {
+            continue;
+        };
+        let idx = idx.cast();
+        if function.flags.is_native() {
+            if !vm.is_native_defined(idx) {
+                let name = meta.qualified_name(idx);
+                issues.push(CompatIssue::MissingNative { function: idx, name });
+            }
+            continue;
+        }
+        for (loc, instr) in function.code.iter() {
+            let unsupported = |instr| CompatIssue::UnsupportedInstr {
+                function: idx,
+                name: meta.qualified_name(idx),
+                instr,
+                offset: loc.value,
+            };
+            let issue = match instr {
+                Instr::InvokeStatic(_, _, callee, _) => {
+                    let is_native = pool.function(*callee).is_ok_and(|callee| callee.flags.is_native());
+                    if !is_native || vm.is_native_defined(*callee) {
+                        continue;
+                    }
+                    CompatIssue::CallsMissingNative {
+                        function: idx,
+                        name: meta.qualified_name(idx),
+                        native: meta.qualified_name(*callee),
+                        offset: loc.value,
+                    }
+                }
+                Instr::ExternalVar => unsupported("ExternalVar"),
+                Instr::Delete => unsupported("Delete"),
+                _ => continue,
+            };
+            issues.push(issue);
+        }
+    }
+    issues
+}
@@ -0,0 +1,312 @@
+use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::bytecode::Location;
+use redscript::definition::{Class, Field, Function, Local};
+use thiserror::Error;
+
+use crate::index_map::IndexMap;
+use crate::interop::CallerFrame;
+use crate::metadata::{Metadata, TypeId};
+use crate::value::{Obj, Value};
+use crate::VM;
+
+pub type PauseHandler<'pool> = dyn FnMut(&mut Paused<'_, 'pool>) -> StepMode;
+
+pub type BreakpointHandler = dyn FnMut(&Breakpoint);
+
+pub type UnwindHandler = dyn FnMut(&Unwound);
+
+/// The state of the script frame that hit a breakpoint instruction.
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub frame: CallerFrame,
+    pub offset: Option<u16>,
+    pub stack: Vec<String>,
+}
+
+/// A script frame discarded while a failed call was unwound.
+#[derive(Debug, Clone)]
+pub struct Unwound {
+    pub frame: CallerFrame,
+    /// The number of script frames below this one.
+    pub depth: usize,
+    /// The offset of the statement that was executing in the frame, if it's known.
+    pub offset: Option<u16>,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepMode {
+    Continue,
+    StepInto,
+    /// Pauses at the next statement of the current function or its callers, running calls to completion.
+    StepOver,
+    /// Pauses at the next statement after the current function returns.
+    StepOut,
+    RunUntil(PoolIndex<Function>, u16),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StepFilters {
+    pub skip_natives: bool,
+    pub skip_operators: bool,
+}
+
+pub struct Debugger<'pool> {
+    pub(crate) mode: StepMode,
+    pub(crate) filters: StepFilters,
+    /// The call depth at which the current step mode was chosen.
+    pub(crate) depth: usize,
+    pub(crate) handler: Box<PauseHandler<'pool>>,
+}
+
+impl<'pool> Debugger<'pool> {
+    pub fn new(handler: impl FnMut(&mut Paused<'_, 'pool>) -> StepMode + 'static) -> Self {
+        Self {
+            mode: StepMode::Continue,
+            filters: StepFilters::default(),
+            depth: 0,
+            handler: Box::new(handler),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: StepMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_filters(mut self, filters: StepFilters) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Natives are reported without a location.
+    pub(crate) fn should_pause(
+        &self,
+        function: PoolIndex<Function>,
+        location: Option<Location>,
+        depth: usize,
+        meta: &Metadata<'_>,
+    ) -> bool {
+        match self.mode {
+            StepMode::Continue => false,
+            StepMode::StepInto => {
+                !(self.filters.skip_natives && location.is_none())
+                    && !(self.filters.skip_operators && is_operator(function, meta.pool()))
+            }
+            StepMode::StepOver => location.is_some() && depth <= self.depth,
+            StepMode::StepOut => location.is_some() && depth < self.depth,
+            StepMode::RunUntil(target, offset) => target == function && location.is_some_and(|loc| loc.value == offset),
+        }
+    }
+}
+
+pub struct Paused<'a, 'pool> {
+    pub(crate) vm: &'a mut VM<'pool>,
+    pub(crate) function: PoolIndex<Function>,
+    pub(crate) location: Option<Location>,
+}
+
+impl<'a, 'pool> Paused<'a, 'pool> {
+    #[inline]
+    pub fn function(&self) -> PoolIndex<Function> {
+        self.function
+    }
+
+    #[inline]
+    pub fn location(&self) -> Option<Location> {
+        self.location
+    }
+
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.vm.calls.len()
+    }
+
+    /// Captures the parameters and locals of the paused script frame, natives have no frame to inspect.
+    pub fn frame(&self) -> Option<CallerFrame> {
+        self.location?;
+        let pool = self.vm.metadata.pool();
+        let floats = self.vm.float_format;
+        self.vm
+            .arena
+            .mutate(|_, root| CallerFrame::new(self.function, root.frames.borrow().last()?, pool, floats))
+    }
+
+    /// Describes the operands of the paused frame, the most recently pushed last.
+    pub fn stack(&self) -> Vec<String> {
+        let pool = self.vm.metadata.pool();
+        let floats = self.vm.float_format;
+        self.vm.arena.mutate(|_, root| {
+            let stack = root.stack.borrow();
+            stack
+                .region()
+                .iter()
+                .map(|val| val.to_string_with(pool, floats))
+                .collect()
+        })
+    }
+
+    #[inline]
+    pub fn vm(&mut self) -> &mut VM<'pool> {
+        self.vm
+    }
+
+    pub fn evaluate_watches(&mut self) -> Vec<WatchResult> {
+        self.vm.evaluate_watches()
+    }
+
+    /// Evaluates an expression against the paused frame, see [`VM::evaluate`].
+    pub fn evaluate(&mut self, expr: &str) -> Result<String, WatchError> {
+        self.vm.evaluate(expr)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WatchError {
+    #[error("no script frame is active")]
+    NoActiveFrame,
+    #[error("{0} is not a local or a parameter")]
+    UnknownLocal(String),
+    #[error("{0} is not a field")]
+    UnknownField(String),
+    #[error("{0} is not an object or a struct")]
+    NotAnAggregate(String),
+    #[error("{0} is null")]
+    NullReference(String),
+    #[error("{0} is not a function")]
+    UnknownFunction(String),
+    #[error("the call failed: {0}")]
+    CallFailed(String),
+}
+
+#[derive(Debug)]
+pub struct WatchResult {
+    pub expr: String,
+    pub value: Result<String, WatchError>,
+}
+
+/// A path expression like `local.field.field`, compiled lazily against the frame it's evaluated in.
+#[derive(Debug)]
+pub struct Watch {
+    source: String,
+    compiled: Option<CompiledWatch>,
+}
+
+impl Watch {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            compiled: None,
+        }
+    }
+
+    #[inline]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub(crate) fn evaluate(
+        &mut self,
+        function: PoolIndex<Function>,
+        locals: &IndexMap<Value<'_>>,
+        meta: &Metadata<'_>,
+    ) -> Result<String, WatchError> {
+        self.evaluate_with(function, locals, meta, |val| val.to_string(meta.pool()))
+    }
+
+    /// Evaluates the watch, rendering the value with `render`.
+    pub(crate) fn evaluate_with(
+        &mut self,
+        function: PoolIndex<Function>,
+        locals: &IndexMap<Value<'_>>,
+        meta: &Metadata<'_>,
+        render: impl FnOnce(&Value<'_>) -> String,
+    ) -> Result<String, WatchError> {
+        if !matches!(&self.compiled, Some(compiled) if compiled.function == function) {
+            self.compiled = Some(CompiledWatch::new(&self.source, function, meta)?);
+        }
+        let compiled = self.compiled.as_ref().ok_or(WatchError::NoActiveFrame)?;
+        Ok(render(&compiled.resolve(locals, meta.pool())?))
+    }
+}
+
+#[derive(Debug)]
+struct CompiledWatch {
+    function: PoolIndex<Function>,
+    local: PoolIndex<Local>,
+    fields: Vec<PoolIndex<Field>>,
+}
+
+impl CompiledWatch {
+    fn new(source: &str, function: PoolIndex<Function>, meta: &Metadata<'_>) -> Result<Self, WatchError> {
+        let pool = meta.pool();
+        let fun = pool.function(function).map_err(|_| WatchError::NoActiveFrame)?;
+        let mut parts = source.split('.').map(str::trim);
+        let head = parts.next().unwrap_or_default();
+
+        let params = fun
+            .parameters
+            .iter()
+            .filter_map(|&idx| Some((idx.cast::<Local>(), pool.parameter(idx).ok()?.type_)));
+        let locals = fun
+            .locals
+            .iter()
+            .filter_map(|&idx| Some((idx, pool.local(idx).ok()?.type_)));
+        let (local, mut typ) = params
+            .chain(locals)
+            .find(|(idx, _)| pool.def_name(*idx).is_ok_and(|name| &*name == head))
+            .ok_or_else(|| WatchError::UnknownLocal(head.to_owned()))?;
+
+        let mut fields = vec![];
+        let mut prev = head;
+        for name in parts {
+            let class = meta
+                .get_type(typ)
+                .and_then(class_of)
+                .ok_or_else(|| WatchError::NotAnAggregate(prev.to_owned()))?;
+            let field = meta
+                .get_field(class, name)
+                .ok_or_else(|| WatchError::UnknownField(name.to_owned()))?;
+            typ = pool
+                .field(field)
+                .map_err(|_| WatchError::UnknownField(name.to_owned()))?
+                .type_;
+            fields.push(field);
+            prev = name;
+        }
+
+        Ok(Self {
+            function,
+            local,
+            fields,
+        })
+    }
+
+    fn resolve<'gc>(&self, locals: &IndexMap<Value<'gc>>, pool: &ConstantPool) -> Result<Value<'gc>, WatchError> {
+        let mut current = locals.get(self.local).cloned().ok_or(WatchError::NoActiveFrame)?;
+        for &field in &self.fields {
+            let name = || pool.def_name(field).map(|name| name.to_string()).unwrap_or_default();
+            let next = match &*current.unpinned() {
+                Value::Obj(Obj::Instance(inst)) => inst.borrow().fields.get(field).cloned(),
+                Value::BoxedStruct(str) => str.borrow().get(field).cloned(),
+                Value::Obj(Obj::Null) => return Err(WatchError::NullReference(name())),
+                _ => None,
+            };
+            current = next.ok_or_else(|| WatchError::UnknownField(name()))?;
+        }
+        Ok(current)
+    }
+}
+
+fn is_operator(function: PoolIndex<Function>, pool: &ConstantPool) -> bool {
+    pool.def_name(function)
+        .is_ok_and(|name| name.starts_with("Operator") || name.starts_with("Cast;"))
+}
+
+fn class_of(typ: &TypeId) -> Option<PoolIndex<Class>> {
+    match typ {
+        TypeId::Ref(class) | TypeId::WRef(class) | TypeId::Struct(class) => Some(*class),
+        TypeId::ScriptRef(inner) => class_of(inner),
+        _ => None,
+    }
+}
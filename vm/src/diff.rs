@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::definition::{AnyDefinition, Class, Definition, Function};
+
+use crate::metadata::qualified_name;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefinitionKind {
+    Class,
+    Function,
+}
+
+impl fmt::Display for DefinitionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DefinitionKind::Class => write!(f, "class"),
+            DefinitionKind::Function => write!(f, "function"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PoolChange {
+    Added {
+        kind: DefinitionKind,
+        name: String,
+    },
+    Removed {
+        kind: DefinitionKind,
+        name: String,
+    },
+    SignatureChanged {
+        kind: DefinitionKind,
+        name: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl fmt::Display for PoolChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolChange::Added { kind, name } => write!(f, "+ {kind} {name}"),
+            PoolChange::Removed { kind, name } => write!(f, "- {kind} {name}"),
+            PoolChange::SignatureChanged { kind, name, old, new } => {
+                write!(f, "~ {kind} {name}: {old} => {new}")
+            }
+        }
+    }
+}
+
+/// Compares the classes and functions of two pools by their qualified names. Functions are matched
+/// without their signature suffix, so a changed parameter is reported as a changed signature. When
+/// a name is overloaded, its overloads are matched by signature instead. Changes are reported in the
+/// order of definitions in the new pool, followed by the definitions removed from the old one.
+pub fn diff_pools(old: &ConstantPool, new: &ConstantPool) -> Vec<PoolChange> {
+    let old_signatures = signatures(old);
+    let new_signatures = signatures(new);
+    let old_lookup = overloads(&old_signatures);
+    let new_lookup = overloads(&new_signatures);
+
+    let mut changes = vec![];
+    for (kind, name, sig) in &new_signatures {
+        let key = (*kind, name.as_str());
+        match (old_lookup.get(&key).map(Vec::as_slice), new_lookup[&key].len()) {
+            (Some([old_sig]), 1) if *old_sig != sig => changes.push(PoolChange::SignatureChanged {
+                kind: *kind,
+                name: name.clone(),
+                old: (*old_sig).clone(),
+                new: sig.clone(),
+            }),
+            (Some([_]), 1) => {}
+            (Some(old_sigs), _) if old_sigs.contains(&sig) => {}
+            _ => changes.push(PoolChange::Added {
+                kind: *kind,
+                name: name.clone(),
+            }),
+        }
+    }
+    for (kind, name, sig) in &old_signatures {
+        let key = (*kind, name.as_str());
+        let removed = match (new_lookup.get(&key).map(Vec::as_slice), old_lookup[&key].len()) {
+            (None, _) => true,
+            (Some([_]), 1) => false,
+            (Some(new_sigs), _) => !new_sigs.contains(&sig),
+        };
+        if removed {
+            changes.push(PoolChange::Removed {
+                kind: *kind,
+                name: name.clone(),
+            });
+        }
+    }
+    changes
+}
+
+fn signatures(pool: &ConstantPool) -> Vec<(DefinitionKind, String, String)> {
+    pool.definitions()
+        .filter_map(|(idx, def)| match &def.value {
+            AnyDefinition::Class(class) => {
+                Some((DefinitionKind::Class, def_name(pool, idx), class_signature(class, pool)))
+            }
+            AnyDefinition::Function(fun) => Some((
+                DefinitionKind::Function,
+                qualified_name(pool, idx),
+                function_signature(fun, pool),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Groups the signatures of the definitions sharing a name.
+fn overloads(signatures: &[(DefinitionKind, String, String)]) -> HashMap<(DefinitionKind, &str), Vec<&String>> {
+    let mut overloads: HashMap<_, Vec<_>> = HashMap::new();
+    for (kind, name, sig) in signatures {
+        overloads.entry((*kind, name.as_str())).or_default().push(sig);
+    }
+    overloads
+}
+
+fn class_signature(class: &Class, pool: &ConstantPool) -> String {
+    let kind = if class.flags.is_struct() { "struct" } else { "class" };
+    let base = if class.base.is_undefined() {
+        String::new()
+    } else {
+        format!(" extends {}", def_name(pool, class.base.cast()))
+    };
+    let fields = class
+        .fields
+        .iter()
+        .map(|&idx| {
+            let typ = pool.field(idx).map(|field| def_name(pool, field.type_.cast()));
+            format!("{}: {}", def_name(pool, idx.cast()), typ.unwrap_or_default())
+        })
+        .collect::<Vec<_>>();
+    format!("{kind}{base} {{{}}}", fields.join(", "))
+}
+
+fn function_signature(fun: &Function, pool: &ConstantPool) -> String {
+    let mut qualifiers = String::new();
+    if fun.flags.is_static() {
+        qualifiers.push_str("static ");
+    }
+    if fun.flags.is_final() {
+        qualifiers.push_str("final ");
+    }
+    if fun.flags.is_native() {
+        qualifiers.push_str("native ");
+    }
+    let params = fun
+        .parameters
+        .iter()
+        .map(|&idx| {
+            let Ok(param) = pool.parameter(idx) else {
+                return String::new();
+            };
+            let prefix = if param.flags.is_out() {
+                "out "
+            } else if param.flags.is_optional() {
+                "opt "
+            } else {
+                ""
+            };
+            format!(
+                "{prefix}{}: {}",
+                def_name(pool, idx.cast()),
+                def_name(pool, param.type_.cast())
+            )
+        })
+        .collect::<Vec<_>>();
+    match fun.return_type {
+        Some(ret) => format!("{qualifiers}({}) -> {}", params.join(", "), def_name(pool, ret.cast())),
+        None => format!("{qualifiers}({})", params.join(", ")),
+    }
+}
+
+fn def_name(pool: &ConstantPool, idx: PoolIndex<Definition>) -> String {
+    pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldChange {
+    Added { name: String, typ: String },
+    Removed { name: String },
+    Retyped { name: String, old: String, new: String },
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldChange::Added { name, typ } => write!(f, "+ {name}: {typ}"),
+            FieldChange::Removed { name } => write!(f, "- {name}"),
+            FieldChange::Retyped { name, old, new } => write!(f, "~ {name}: {old} => {new}"),
+        }
+    }
+}
+
+/// Changes to the fields of a class present in both pools, including the fields it inherits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutChange {
+    pub class: String,
+    pub fields: Vec<FieldChange>,
+}
+
+impl LayoutChange {
+    /// Whether existing instances can be migrated by filling the new fields with their default
+    /// values. Removed or retyped fields would lose data, so a reload has to be refused instead.
+    pub fn is_migratable(&self) -> bool {
+        self.fields
+            .iter()
+            .all(|change| matches!(change, FieldChange::Added { .. }))
+    }
+}
+
+/// Finds the classes whose instances created with the old pool don't match the layout of the new one.
+pub fn layout_changes(old: &ConstantPool, new: &ConstantPool) -> Vec<LayoutChange> {
+    let old_classes: HashMap<_, _> = old
+        .definitions()
+        .filter(|(_, def)| matches!(def.value, AnyDefinition::Class(_)))
+        .map(|(idx, _)| (def_name(old, idx), idx.cast::<Class>()))
+        .collect();
+
+    new.definitions()
+        .filter(|(_, def)| matches!(def.value, AnyDefinition::Class(_)))
+        .filter_map(|(idx, _)| {
+            let name = def_name(new, idx);
+            let old_layout = class_layout(old, *old_classes.get(&name)?);
+            let new_layout = class_layout(new, idx.cast());
+
+            let mut fields = vec![];
+            for (field, typ) in &new_layout {
+                match old_layout.iter().find(|(name, _)| name == field) {
+                    None => fields.push(FieldChange::Added {
+                        name: field.clone(),
+                        typ: typ.clone(),
+                    }),
+                    Some((_, old_typ)) if old_typ != typ => fields.push(FieldChange::Retyped {
+                        name: field.clone(),
+                        old: old_typ.clone(),
+                        new: typ.clone(),
+                    }),
+                    Some(_) => {}
+                }
+            }
+            for (field, _) in &old_layout {
+                if !new_layout.iter().any(|(name, _)| name == field) {
+                    fields.push(FieldChange::Removed { name: field.clone() });
+                }
+            }
+            (!fields.is_empty()).then_some(LayoutChange { class: name, fields })
+        })
+        .collect()
+}
+
+fn class_layout(pool: &ConstantPool, idx: PoolIndex<Class>) -> Vec<(String, String)> {
+    let mut fields = vec![];
+    let mut current = idx;
+    while let Ok(class) = pool.class(current) {
+        for &field_idx in &class.fields {
+            let typ = pool.field(field_idx).map(|field| def_name(pool, field.type_.cast()));
+            fields.push((def_name(pool, field_idx.cast()), typ.unwrap_or_default()));
+        }
+        if class.base.is_undefined() {
+            break;
+        }
+        current = class.base;
+    }
+    fields
+}
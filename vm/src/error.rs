@@ -1,16 +1,291 @@
+use std::fmt;
+use std::time::Duration;
+
+use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::definition::{Class, Field, Function};
 use redscript::Ref;
 use thiserror::Error;
 
+use crate::metadata::{self, Metadata};
+
 pub type RuntimeResult<A, E = RuntimeError> = Result<A, E>;
 
+/// Converts an error caught by the `Try` native into the message returned to the script.
+pub type ErrorHandler = dyn Fn(&RuntimeError, &Metadata<'_>) -> String;
+
 #[derive(Debug, Error)]
 pub enum RuntimeError {
-    #[error("null pointer dereference")]
-    NullPointer,
+    #[error("null pointer dereference accessing {member}{}", .class.as_ref().map(|class| format!(" of {class}")).unwrap_or_default())]
+    NullAccess { class: Option<String>, member: String },
+    #[error("cannot cast to {target}: {reason}")]
+    InvalidCast { target: String, reason: &'static str },
     #[error("native {0} is not defined")]
     UndefinedNative(Ref<str>),
+    #[error("method {0} is not defined")]
+    UndefinedMethod(String),
+    #[error("field {0} is not defined")]
+    UndefinedField(String),
     #[error("unsupported assingment operand")]
     UnsupportedAssignmentOperand,
     #[error("invalid parameters in interop call")]
     InvalidInteropParameters,
+    #[error("native expected {expected} arguments, got {actual}")]
+    NativeArgumentMismatch { expected: usize, actual: usize },
+    #[error("invalid native argument: {0}")]
+    InvalidNativeArgument(&'static str),
+    #[error("expected a pinned value for out parameter")]
+    ExpectedPinnedValue,
+    #[error("native {0} panicked: {1}")]
+    NativePanicked(String, String),
+    #[error("native accessed the stack outside of its arguments")]
+    InvalidNativeStackAccess,
+    #[error("another sliced call is already in progress")]
+    CallInProgress,
+    #[error("there is no suspended call to resume")]
+    NoSuspendedCall,
+    #[error("the call is waiting for a native to be completed")]
+    AwaitingNative,
+    #[error("script error: {message}{}", .location.as_ref().map(|loc| format!(" ({loc})")).unwrap_or_default())]
+    Thrown {
+        message: String,
+        location: Option<ThrowLocation>,
+    },
+    #[error("allocated {allocated} bytes, exceeding the budget of {budget} bytes")]
+    AllocationBudgetExceeded { budget: usize, allocated: usize },
+    #[error("executed {limit} instructions, exceeding the fuel limit")]
+    OutOfFuel { limit: u64 },
+    #[error("arithmetic overflow on {0}")]
+    ArithmeticOverflow(&'static str),
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("the call was cancelled")]
+    Cancelled,
+    #[error("the call did not complete within {0:?}")]
+    TimedOut(Duration),
+    #[error("attempted to pop a value from an empty stack")]
+    InvalidStack,
+    #[error("type mismatch: {0}")]
+    TypeMismatch(&'static str),
+    #[error("definition {0} is missing from the pool")]
+    MissingDefinition(u32),
+    #[error("invalid bytecode: {0}")]
+    InvalidBytecode(&'static str),
+    #[error("index {index} is out of bounds for an array of length {len}")]
+    IndexOutOfBounds { index: i64, len: usize },
+    #[error("{0} is backed by host objects and can't be constructed by scripts")]
+    HostClassConstruction(String),
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
+    #[error("{error}\n{backtrace}")]
+    Traced {
+        error: Box<RuntimeError>,
+        backtrace: Backtrace,
+    },
+}
+
+impl RuntimeError {
+    #[inline]
+    pub(crate) fn missing<A>(idx: PoolIndex<A>) -> Self {
+        Self::MissingDefinition(idx.into())
+    }
+
+    /// A null dereference while accessing a field, reported along with the class declaring it.
+    pub(crate) fn null_field(pool: &ConstantPool, idx: PoolIndex<Field>) -> Self {
+        let (class, member) = field_names(pool, idx);
+        Self::NullAccess { class, member }
+    }
+
+    pub(crate) fn invalid_cast(pool: &ConstantPool, target: PoolIndex<Class>, reason: &'static str) -> Self {
+        let target = pool.def_name(target).map(|str| str.to_string()).unwrap_or_default();
+        Self::InvalidCast { target, reason }
+    }
+
+    /// Returns the error without the script backtrace attached to it.
+    pub fn cause(&self) -> &RuntimeError {
+        match self {
+            RuntimeError::Traced { error, .. } => error,
+            other => other,
+        }
+    }
+
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self {
+            RuntimeError::Traced { backtrace, .. } => Some(backtrace),
+            _ => None,
+        }
+    }
+
+    /// Whether a script can catch the error, the limits imposed by the host can't be bypassed.
+    pub fn is_recoverable(&self) -> bool {
+        !matches!(
+            self.cause(),
+            RuntimeError::OutOfFuel { .. }
+                | RuntimeError::Cancelled
+                | RuntimeError::TimedOut(_)
+                | RuntimeError::AllocationBudgetExceeded { .. }
+                | RuntimeError::AwaitingNative
+        )
+    }
+
+    /// The function and offset of the instruction that failed, if the error was raised by a script.
+    pub fn location(&self) -> Option<&BacktraceFrame> {
+        self.backtrace()?.frames.first()
+    }
+
+    /// Formats the error along with its backtrace, with functions qualified by their classes.
+    pub fn display_with<'a>(&'a self, meta: &'a Metadata<'_>) -> ErrorReport<'a> {
+        ErrorReport {
+            error: self,
+            pool: meta.pool(),
+        }
+    }
+
+    /// Appends a caller frame to the backtrace, frames are added as the error unwinds.
+    pub(crate) fn with_frame(self, frame: BacktraceFrame) -> Self {
+        match self {
+            RuntimeError::Traced { error, mut backtrace } => {
+                backtrace.frames.push(frame);
+                RuntimeError::Traced { error, backtrace }
+            }
+            other => RuntimeError::Traced {
+                error: Box::new(other),
+                backtrace: Backtrace { frames: vec![frame] },
+            },
+        }
+    }
+}
+
+/// The names of a field and of the class declaring it.
+fn field_names(pool: &ConstantPool, idx: PoolIndex<Field>) -> (Option<String>, String) {
+    let class = pool
+        .definition(idx)
+        .ok()
+        .and_then(|def| pool.def_name(def.parent).ok())
+        .map(|str| str.to_string());
+    let field = pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default();
+    (class, field)
+}
+
+/// Script functions that were active when an error occurred, the innermost one first.
+#[derive(Debug, Clone, Default)]
+pub struct Backtrace {
+    pub frames: Vec<BacktraceFrame>,
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "  at {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BacktraceFrame {
+    pub index: PoolIndex<Function>,
+    pub function: String,
+    pub offset: Option<u16>,
+}
+
+impl BacktraceFrame {
+    /// Resolves the name of the function as `Class::Method`, without the signature suffix.
+    pub fn qualified_name(&self, pool: &ConstantPool) -> String {
+        let name = metadata::short_name(&self.function);
+        let class = pool
+            .definition(self.index)
+            .ok()
+            .filter(|def| !def.parent.is_undefined())
+            .and_then(|def| pool.def_name(def.parent).ok());
+        match class {
+            Some(class) => format!("{class}::{name}"),
+            None => name.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for BacktraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{} (offset {})", self.function, offset),
+            None => write!(f, "{}", self.function),
+        }
+    }
+}
+
+/// An error formatted with the names resolved from the pool, created by [`RuntimeError::display_with`].
+pub struct ErrorReport<'a> {
+    error: &'a RuntimeError,
+    pool: &'a ConstantPool,
+}
+
+impl fmt::Display for ErrorReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error.cause())?;
+        for frame in self.error.backtrace().into_iter().flat_map(|trace| &trace.frames) {
+            write!(f, "\n  at {}", frame.qualified_name(self.pool))?;
+            if let Some(offset) = frame.offset {
+                write!(f, " (offset {offset})")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A divergence from strict semantics tolerated by a lenient mode, collected by the VM during a
+/// top-level call.
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub location: BacktraceFrame,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in {}", self.kind, self.location)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum WarningKind {
+    /// A field was read through a null reference and produced its default value.
+    NullFieldRead { class: Option<String>, field: String },
+}
+
+impl WarningKind {
+    pub(crate) fn null_field_read(pool: &ConstantPool, idx: PoolIndex<Field>) -> Self {
+        let (class, field) = field_names(pool, idx);
+        Self::NullFieldRead { class, field }
+    }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WarningKind::NullFieldRead {
+                class: Some(class),
+                field,
+            } => {
+                write!(f, "read field {field} of {class} through a null reference")
+            }
+            WarningKind::NullFieldRead { class: None, field } => {
+                write!(f, "read field {field} through a null reference")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ThrowLocation {
+    pub function: String,
+    pub offset: u16,
+}
+
+impl fmt::Display for ThrowLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "thrown in {} at offset {}", self.function, self.offset)
+    }
 }
@@ -0,0 +1,135 @@
+use std::cmp::Reverse;
+use std::fmt;
+
+use redscript::bundle::PoolIndex;
+use redscript::definition::Function;
+
+use crate::error::{RuntimeError, RuntimeResult};
+use crate::index_map::IndexMap;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AllocationStats {
+    pub allocations: usize,
+    pub total_allocations: usize,
+    pub bytes: usize,
+    pub debt: f64,
+}
+
+/// Metrics of the arena along with the work done by the collector over the lifetime of the VM.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcStats {
+    /// Bytes currently allocated in the arena.
+    pub allocated: usize,
+    pub debt: f64,
+    pub full_collections: usize,
+    pub incremental_steps: usize,
+    /// Bytes reclaimed by all of the collections.
+    pub freed: usize,
+}
+
+impl fmt::Display for GcStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "allocated: {} bytes, debt: {:.0}, full collections: {}, incremental steps: {}, freed: {} bytes",
+            self.allocated, self.debt, self.full_collections, self.incremental_steps, self.freed
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CallAllocations {
+    pub budget: Option<usize>,
+    pub last_total: usize,
+    allocated: usize,
+    by_function: IndexMap<usize>,
+}
+
+impl CallAllocations {
+    pub fn reset(&mut self) {
+        self.allocated = 0;
+        self.by_function = IndexMap::new();
+    }
+
+    pub fn record(&mut self, function: PoolIndex<Function>, bytes: usize) -> RuntimeResult<()> {
+        self.allocated += bytes;
+        match self.by_function.get_mut(function) {
+            Some(total) => *total += bytes,
+            None => self.by_function.put(function, bytes),
+        }
+        match self.budget {
+            Some(budget) if self.allocated > budget => Err(RuntimeError::AllocationBudgetExceeded {
+                budget,
+                allocated: self.allocated,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn report(&self) -> Vec<(PoolIndex<Function>, usize)> {
+        let mut report = self
+            .by_function
+            .iter()
+            .map(|(idx, &bytes)| (idx, bytes))
+            .collect::<Vec<_>>();
+        report.sort_by_key(|&(_, bytes)| Reverse(bytes));
+        report
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcAction {
+    Skip,
+    CollectDebt,
+    CollectAll,
+}
+
+pub trait GcPolicy {
+    fn on_allocation(&mut self, stats: &AllocationStats) -> GcAction;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DebtPolicy {
+    pub threshold: f64,
+}
+
+impl Default for DebtPolicy {
+    fn default() -> Self {
+        Self { threshold: 64000. }
+    }
+}
+
+impl GcPolicy for DebtPolicy {
+    fn on_allocation(&mut self, stats: &AllocationStats) -> GcAction {
+        if stats.debt >= self.threshold {
+            GcAction::CollectDebt
+        } else {
+            GcAction::Skip
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AllocationCountPolicy {
+    pub limit: usize,
+}
+
+impl GcPolicy for AllocationCountPolicy {
+    fn on_allocation(&mut self, stats: &AllocationStats) -> GcAction {
+        if stats.allocations >= self.limit {
+            GcAction::CollectAll
+        } else {
+            GcAction::Skip
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ManualPolicy;
+
+impl GcPolicy for ManualPolicy {
+    #[inline]
+    fn on_allocation(&mut self, _stats: &AllocationStats) -> GcAction {
+        GcAction::Skip
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+
+use gc_arena::Gc;
+use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::definition::{Class, Field};
+
+use crate::index_map::IndexMap;
+use crate::value::{FloatFormat, Obj, Value};
+
+/// Renders the values reachable from a value as a Graphviz DOT graph. Objects, structs and arrays
+/// become nodes listing their simple fields, while references between them become edges labeled
+/// with the field or the index holding them. Every object is rendered once, so cycles are fine.
+pub fn value_graph(val: &Value<'_>, pool: &ConstantPool, floats: FloatFormat) -> String {
+    let mut nodes = vec![];
+    let mut edges = vec![];
+    let mut visited = HashSet::new();
+    let mut pending = vec![resolved(val)];
+
+    while let Some(val) = pending.pop() {
+        let Some(id) = node_id(&val) else {
+            nodes.push(format!(
+                "    value [shape=plaintext, label={:?}];",
+                val.to_string_with(pool, floats)
+            ));
+            continue;
+        };
+        if !visited.insert(id.clone()) {
+            continue;
+        }
+        let (title, entries) = node_contents(&val, pool);
+        let mut label = title;
+        for (name, child) in entries {
+            let child = resolved(&child);
+            match node_id(&child) {
+                Some(child_id) => {
+                    edges.push(format!("    {id} -> {child_id} [label={name:?}];"));
+                    pending.push(child);
+                }
+                None => label.push_str(&format!("\n{name}: {}", child.to_string_with(pool, floats))),
+            }
+        }
+        nodes.push(format!("    {id} [shape=box, label={label:?}];"));
+    }
+
+    let mut out = "digraph values {\n".to_owned();
+    for line in nodes.iter().chain(&edges) {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push('}');
+    out
+}
+
+/// Unwraps pinned values and variants, which are not rendered as nodes of their own.
+fn resolved<'gc>(val: &Value<'gc>) -> Value<'gc> {
+    match val {
+        Value::Pinned(cell) => resolved(&cell.borrow()),
+        Value::Variant(var) => resolved(&var.value),
+        other => other.clone(),
+    }
+}
+
+/// The identifier of the node of an aggregate value, or `None` for values rendered inline.
+fn node_id(val: &Value<'_>) -> Option<String> {
+    let id = match val {
+        Value::Obj(Obj::Instance(inst)) => format!("obj{}", inst.borrow().id),
+        Value::BoxedStruct(struct_) => format!("struct{:x}", Gc::as_ptr(*struct_) as usize),
+        Value::Array(arr) => format!("array{:x}", Gc::as_ptr(*arr) as usize),
+        Value::StaticArray(arr) => format!("array{:x}", Gc::as_ptr(*arr) as usize),
+        _ => return None,
+    };
+    Some(id)
+}
+
+fn node_contents<'gc>(val: &Value<'gc>, pool: &ConstantPool) -> (String, Vec<(String, Value<'gc>)>) {
+    let fields = |fields: &IndexMap<Value<'gc>>| {
+        fields
+            .iter::<Field>()
+            .map(|(idx, val)| (name(pool, idx), val.clone()))
+            .collect::<Vec<_>>()
+    };
+    let elements = |elems: &[Value<'gc>]| {
+        let entries = elems
+            .iter()
+            .enumerate()
+            .map(|(i, val)| (format!("[{i}]"), val.clone()))
+            .collect::<Vec<_>>();
+        (format!("array[{}]", elems.len()), entries)
+    };
+
+    match val {
+        Value::Obj(Obj::Instance(inst)) => {
+            let inst = inst.borrow();
+            let class = name(pool, inst.tag.to_pool::<Class>());
+            (format!("#{} {class}", inst.id), fields(&inst.fields))
+        }
+        Value::BoxedStruct(struct_) => {
+            let struct_ = struct_.borrow();
+            // structs don't carry their type, but it can be found through the parent of a field
+            let class = struct_
+                .iter::<Field>()
+                .next()
+                .and_then(|(idx, _)| pool.definition(idx).ok())
+                .map_or_else(|| "struct".to_owned(), |def| name(pool, def.parent));
+            (class, fields(&struct_))
+        }
+        Value::Array(arr) => elements(&arr.borrow()),
+        Value::StaticArray(arr) => elements(&arr.borrow()),
+        _ => (String::new(), vec![]),
+    }
+}
+
+fn name<A>(pool: &ConstantPool, idx: PoolIndex<A>) -> String {
+    pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default()
+}
@@ -3,14 +3,14 @@ use std::iter::FromIterator;
 use std::usize;
 
 use gc_arena::{Collect, Collection};
-use intmap::IntMap;
 use redscript::bundle::PoolIndex;
 
 use crate::value::Value;
 
+/// A map keyed by pool indexes, with the entries kept sorted by index.
 #[derive(Debug, Clone)]
 pub struct IndexMap<V> {
-    values: IntMap<V>,
+    values: Vec<(u32, V)>,
 }
 
 impl<V> IndexMap<V> {
@@ -22,45 +22,62 @@ impl<V> IndexMap<V> {
     #[inline]
     pub fn with_capacity(len: usize) -> Self {
         Self {
-            values: IntMap::with_capacity(len),
+            values: Vec::with_capacity(len),
         }
     }
 
     #[inline]
     pub fn get_mut<A>(&mut self, idx: PoolIndex<A>) -> Option<&mut V> {
-        let idx: u32 = idx.into();
-        self.values.get_mut(idx.into())
+        let pos = self.position(idx).ok()?;
+        Some(&mut self.values[pos].1)
     }
 
     #[inline]
     pub fn get<A>(&self, idx: PoolIndex<A>) -> Option<&V> {
-        let idx: u32 = idx.into();
-        self.values.get(idx.into())
+        let pos = self.position(idx).ok()?;
+        Some(&self.values[pos].1)
     }
 
+    /// Inserts or replaces an entry, entries are usually put in increasing order of their indexes,
+    /// which only appends them.
     #[inline]
     pub fn put<A>(&mut self, idx: PoolIndex<A>, val: V) {
-        let idx: u32 = idx.into();
-        self.values.insert(idx.into(), val);
+        match self.position(idx) {
+            Ok(pos) => self.values[pos].1 = val,
+            Err(pos) => self.values.insert(pos, (idx.into(), val)),
+        }
     }
 
-    #[inline]
+    /// Iterates in the order of pool indexes, which keeps the output deterministic and matches the
+    /// declaration order of fields.
     pub fn iter<A>(&self) -> impl Iterator<Item = (PoolIndex<A>, &V)> {
-        self.values.iter().map(|(&key, val)| (PoolIndex::new(key as u32), val))
+        self.values.iter().map(|(key, val)| (PoolIndex::new(*key), val))
+    }
+
+    #[inline]
+    fn position<A>(&self, idx: PoolIndex<A>) -> Result<usize, usize> {
+        let idx: u32 = idx.into();
+        match self.values.last() {
+            Some((last, _)) if *last < idx => Err(self.values.len()),
+            _ => self.values.binary_search_by_key(&idx, |(key, _)| *key),
+        }
     }
 }
 
 impl<V> Default for IndexMap<V> {
     #[inline]
     fn default() -> Self {
-        Self { values: IntMap::new() }
+        Self { values: Vec::new() }
     }
 }
 
 impl<A, V> FromIterator<(PoolIndex<A>, V)> for IndexMap<V> {
     fn from_iter<I: IntoIterator<Item = (PoolIndex<A>, V)>>(iter: I) -> Self {
-        let values = iter.into_iter().map(|(k, v)| (u32::from(k).into(), v)).collect();
-        Self { values }
+        let mut res = Self::new();
+        for (idx, val) in iter {
+            res.put(idx, val);
+        }
+        res
     }
 }
 
@@ -72,9 +89,65 @@ unsafe impl<V: Collect> Collect for IndexMap<V> {
 
     #[inline]
     fn trace(&self, cc: &Collection) {
-        for (k, v) in self.values.iter() {
-            k.trace(cc);
+        for (_, v) in &self.values {
             v.trace(cc);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use redscript::bundle::PoolIndex;
+    use redscript::definition::Field;
+
+    use super::IndexMap;
+
+    fn idx(i: u32) -> PoolIndex<Field> {
+        PoolIndex::new(i)
+    }
+
+    fn keys(map: &IndexMap<&str>) -> Vec<u32> {
+        map.iter::<Field>().map(|(idx, _)| idx.into()).collect()
+    }
+
+    #[test]
+    fn iterates_in_index_order() {
+        let mut map = IndexMap::new();
+        map.put(idx(5), "e");
+        map.put(idx(1), "a");
+        map.put(idx(9), "i");
+        map.put(idx(3), "c");
+        assert_eq!(keys(&map), [1, 3, 5, 9]);
+        let values = map.iter::<Field>().map(|(_, val)| *val).collect::<Vec<_>>();
+        assert_eq!(values, ["a", "c", "e", "i"]);
+    }
+
+    #[test]
+    fn put_replaces_existing_entries() {
+        let mut map = IndexMap::new();
+        map.put(idx(2), "old");
+        map.put(idx(4), "other");
+        map.put(idx(2), "new");
+        assert_eq!(keys(&map), [2, 4]);
+        assert_eq!(map.get(idx(2)), Some(&"new"));
+    }
+
+    #[test]
+    fn get_finds_only_present_entries() {
+        let map: IndexMap<&str> = [(idx(1), "a"), (idx(7), "g")].into_iter().collect();
+        assert_eq!(map.get(idx(1)), Some(&"a"));
+        assert_eq!(map.get(idx(7)), Some(&"g"));
+        assert_eq!(map.get(idx(0)), None);
+        assert_eq!(map.get(idx(4)), None);
+        assert_eq!(map.get(idx(8)), None);
+    }
+
+    #[test]
+    fn get_mut_updates_in_place() {
+        let mut map = IndexMap::new();
+        map.put(idx(3), 1);
+        *map.get_mut(idx(3)).unwrap() += 1;
+        assert_eq!(map.get(idx(3)), Some(&2));
+        assert!(map.get_mut(idx(4)).is_none());
+    }
+}
@@ -0,0 +1,20 @@
+use crate::error::RuntimeResult;
+use crate::interop::NativeCtx;
+use crate::recorder::NativeCall;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intercept {
+    /// Lets the call through to the next interceptor and eventually the native itself.
+    Proceed,
+    /// The interceptor has consumed the arguments and pushed the result, the native is skipped.
+    Handled,
+}
+
+/// Wraps every native invocation made by the VM, registered with [`VM::add_interceptor`](crate::VM::add_interceptor).
+pub trait NativeInterceptor {
+    fn before(&mut self, _call: &NativeCall, _ctx: &mut NativeCtx<'_, '_>) -> RuntimeResult<Intercept> {
+        Ok(Intercept::Proceed)
+    }
+
+    fn after(&mut self, _call: &NativeCall) {}
+}
@@ -1,41 +1,499 @@
+use std::any::{self, Any};
+use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
 use casey::lower;
-use gc_arena::Mutation;
-use redscript::bundle::ConstantPool;
+use gc_arena::lock::GcRefLock;
+pub use gc_arena::Mutation;
+pub use redscript::bundle::ConstantPool;
+use redscript::bundle::PoolIndex;
+use redscript::definition::{Class, Definition, Field, Function, Local};
+
+use crate::error::{RuntimeError, RuntimeResult};
+use crate::index_map::IndexMap;
+use crate::metadata::{find_field, Metadata};
+use crate::native::ScriptMaps;
+use crate::value::{self, FloatFormat, Instance, Obj, Value};
+use crate::{ArrayIter, VMRoot, VM};
 
-use crate::value::Value;
-use crate::VMRoot;
+pub type VMFunction = dyn for<'gc> Fn(&mut NativeCtx<'_, 'gc>) -> RuntimeResult<()>;
 
-pub type VMFunction = dyn for<'gc> Fn(&Mutation<'gc>, &VMRoot<'gc>, &ConstantPool) -> Option<Value<'gc>>;
+/// A native registered with [`VM::register_reentrant_native`].
+pub type ReentrantFunction<'pool> = dyn Fn(&mut ReentrantCtx<'_, 'pool>) -> RuntimeResult<()> + 'pool;
 
 pub struct Ret<A>(pub A);
 
 pub struct RetOut<A, B>(pub A, pub B);
 
+/// Returns a tuple from a native as the struct declared as its return type.
+pub struct RetStruct<A>(pub A);
+
+/// A script class that host code can hold objects of through a [`TypedRef`].
+pub trait ScriptClass {
+    const NAME: &'static str;
+}
+
+/// A script object of the class `T` or one of its subclasses, kept alive by the VM until it's released
+/// with [`VM::release_ref`](crate::VM::release_ref).
+#[derive(Debug)]
+pub struct TypedRef<T> {
+    pub(crate) handle: u64,
+    pub(crate) class: PoolIndex<Class>,
+    marker: PhantomData<T>,
+}
+
+impl<T> TypedRef<T> {
+    pub(crate) fn new(handle: u64, class: PoolIndex<Class>) -> Self {
+        Self {
+            handle,
+            class,
+            marker: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CallerFrame {
+    pub function: String,
+    pub locals: Vec<(String, String)>,
+}
+
+impl CallerFrame {
+    pub(crate) fn new(
+        idx: PoolIndex<Function>,
+        values: &IndexMap<Value<'_>>,
+        pool: &ConstantPool,
+        floats: FloatFormat,
+    ) -> Option<Self> {
+        let function = pool.function(idx).ok()?;
+        let name = |idx: PoolIndex<Definition>| pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default();
+
+        let params = function.parameters.iter().map(|idx| idx.cast::<Local>());
+        let locals = params
+            .chain(function.locals.iter().copied())
+            .filter_map(|idx| {
+                let val = values.get(idx)?.to_string_with(pool, floats);
+                Some((name(idx.cast()), val))
+            })
+            .collect();
+        Some(CallerFrame {
+            function: name(idx.cast()),
+            locals,
+        })
+    }
+}
+
 pub trait IntoVM<'gc> {
-    fn into_vm(self, mc: &Mutation<'gc>) -> Value<'gc>;
+    fn into_vm(self, mc: &Mutation<'gc>, meta: &Metadata<'_>) -> Value<'gc>;
 }
 
 pub trait FromVM<'gc>: Sized {
     fn from_vm<'pool>(val: Value<'gc>, pool: &'pool ConstantPool) -> Result<Self, &'static str>;
 }
 
+/// Converts a tuple into the values of the fields of a struct, in declaration order.
+pub trait IntoVMFields<'gc> {
+    fn into_vm_fields(self, mc: &Mutation<'gc>, meta: &Metadata<'_>) -> Vec<Value<'gc>>;
+}
+
+/// Implemented for closures taking and returning values convertible to and from the VM,
+/// they can be `FnMut` to keep state like counters between calls.
 pub trait IntoVMFunction<A, R> {
     fn into_vm_function(self) -> Box<VMFunction>;
 }
 
+/// Stack access for natives, limited to the arguments of the current call.
+pub struct NativeCtx<'ctx, 'gc> {
+    mc: &'ctx Mutation<'gc>,
+    root: &'ctx VMRoot<'gc>,
+    meta: &'ctx Metadata<'ctx>,
+    float_format: FloatFormat,
+    user_data: &'ctx mut UserData,
+    callee: PoolIndex<Function>,
+    caller: Option<PoolIndex<Function>>,
+    arity: usize,
+    remaining: usize,
+    suspended: bool,
+}
+
+impl<'ctx, 'gc> NativeCtx<'ctx, 'gc> {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        mc: &'ctx Mutation<'gc>,
+        root: &'ctx VMRoot<'gc>,
+        meta: &'ctx Metadata<'ctx>,
+        float_format: FloatFormat,
+        user_data: &'ctx mut UserData,
+        callee: PoolIndex<Function>,
+        caller: Option<PoolIndex<Function>>,
+        arity: usize,
+    ) -> Self {
+        Self {
+            mc,
+            root,
+            meta,
+            float_format,
+            user_data,
+            callee,
+            caller,
+            arity,
+            remaining: arity,
+            suspended: false,
+        }
+    }
+
+    #[inline]
+    pub fn mutation(&self) -> &'ctx Mutation<'gc> {
+        self.mc
+    }
+
+    /// Suspends the sliced call that invoked the native until the host provides its result with
+    /// [`VM::complete_native`], the native shouldn't push a result itself. Only natives called
+    /// directly by the function of a sliced call can suspend it, in statements that haven't called
+    /// any script functions yet.
+    pub fn suspend(&mut self) {
+        self.suspended = true;
+    }
+
+    #[inline]
+    pub(crate) fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    #[inline]
+    pub fn pool(&self) -> &'ctx ConstantPool {
+        self.meta.pool()
+    }
+
+    #[inline]
+    pub fn metadata(&self) -> &'ctx Metadata<'ctx> {
+        self.meta
+    }
+
+    #[inline]
+    pub fn float_format(&self) -> FloatFormat {
+        self.float_format
+    }
+
+    /// The host value of type `T` set with [`VM::set_context`].
+    #[inline]
+    pub fn context<T: 'static>(&self) -> Option<&T> {
+        self.user_data.get()
+    }
+
+    #[inline]
+    pub fn context_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.user_data.get_mut()
+    }
+
+    /// Whether the native is declared with a return type.
+    pub fn returns(&self) -> bool {
+        self.pool()
+            .function(self.callee)
+            .is_ok_and(|function| function.return_type.is_some())
+    }
+
+    #[inline]
+    pub fn arg_count(&self) -> usize {
+        self.arity
+    }
+
+    pub fn expect_args(&self, count: usize) -> RuntimeResult<()> {
+        if self.arity != count {
+            return Err(RuntimeError::NativeArgumentMismatch {
+                expected: count,
+                actual: self.arity,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn pop_value(&mut self) -> RuntimeResult<Value<'gc>> {
+        if self.remaining == 0 {
+            return Err(RuntimeError::InvalidNativeStackAccess);
+        }
+        self.remaining -= 1;
+        self.root
+            .pop(self.mc)
+            .map_err(|_| RuntimeError::InvalidInteropParameters)
+    }
+
+    /// Drops the remaining arguments, for natives that don't need them.
+    pub fn discard_args(&mut self) -> RuntimeResult<()> {
+        while self.remaining > 0 {
+            self.pop_value()?;
+        }
+        Ok(())
+    }
+
+    pub fn pop<A: FromVM<'gc>>(&mut self) -> RuntimeResult<A> {
+        let val = self.pop_value()?;
+        A::from_vm(val, self.pool()).map_err(RuntimeError::InvalidNativeArgument)
+    }
+
+    pub fn pop_pinned(&mut self) -> RuntimeResult<GcRefLock<'gc, Value<'gc>>> {
+        match self.pop_value()? {
+            Value::Pinned(pinned) => Ok(pinned),
+            _ => Err(RuntimeError::ExpectedPinnedValue),
+        }
+    }
+
+    pub fn read_pinned<A: FromVM<'gc>>(&self, pinned: GcRefLock<'gc, Value<'gc>>) -> RuntimeResult<A> {
+        A::from_vm(Value::Pinned(pinned), self.pool()).map_err(RuntimeError::InvalidNativeArgument)
+    }
+
+    pub fn write_pinned<A: IntoVM<'gc>>(&self, pinned: GcRefLock<'gc, Value<'gc>>, val: A) {
+        *pinned.borrow_mut(self.mc) = val.into_vm(self.mc, self.meta);
+    }
+
+    pub fn push_value(&mut self, val: Value<'gc>) -> RuntimeResult<()> {
+        if self.remaining != 0 {
+            return Err(RuntimeError::InvalidNativeStackAccess);
+        }
+        self.root.push(val, self.mc);
+        Ok(())
+    }
+
+    pub fn push<A: IntoVM<'gc>>(&mut self, val: A) -> RuntimeResult<()> {
+        let val = val.into_vm(self.mc, self.meta);
+        self.push_value(val)
+    }
+
+    /// Pushes an instance of the struct returned by the native, with the fields assigned in declaration order.
+    pub fn push_struct(&mut self, vals: Vec<Value<'gc>>) -> RuntimeResult<()> {
+        let function = self
+            .pool()
+            .function(self.callee)
+            .map_err(|_| RuntimeError::InvalidInteropParameters)?;
+        let name = function
+            .return_type
+            .and_then(|typ| self.pool().def_name(typ).ok())
+            .ok_or(RuntimeError::InvalidNativeArgument("Native does not return a struct"))?;
+        let res = value::build_positional_struct(&name, vals, self.mc, self.pool());
+        self.push_value(res)
+    }
+
+    /// The object a native method was called on, `None` for static methods and free functions.
+    pub fn this(&self) -> Option<GcRefLock<'gc, Instance<'gc>>> {
+        let def = self.pool().definition(self.callee).ok()?;
+        let function = self.pool().function(self.callee).ok()?;
+        if def.parent.is_undefined() || function.flags.is_static() {
+            return None;
+        }
+        self.root.contexts.borrow().last().and_then(Obj::as_instance).copied()
+    }
+
+    /// Reads a field of the object a native method was called on.
+    pub fn this_field<A: FromVM<'gc>>(&self, name: &str) -> RuntimeResult<A> {
+        let (this, field) = self.resolve_this_field(name)?;
+        let val = this.borrow().fields.get(field).cloned();
+        let val = val.ok_or_else(|| RuntimeError::UndefinedField(name.to_owned()))?;
+        A::from_vm(val, self.pool()).map_err(RuntimeError::TypeMismatch)
+    }
+
+    /// Assigns a field of the object a native method was called on.
+    pub fn set_this_field<A: IntoVM<'gc>>(&self, name: &str, val: A) -> RuntimeResult<()> {
+        let (this, field) = self.resolve_this_field(name)?;
+        let mut this = this.borrow_mut(self.mc);
+        let slot = this
+            .fields
+            .get_mut(field)
+            .ok_or_else(|| RuntimeError::UndefinedField(name.to_owned()))?;
+        *slot = val.into_vm(self.mc, self.meta);
+        Ok(())
+    }
+
+    fn resolve_this_field(&self, name: &str) -> RuntimeResult<(GcRefLock<'gc, Instance<'gc>>, PoolIndex<Field>)> {
+        let this = self.this().ok_or_else(|| RuntimeError::NullAccess {
+            class: None,
+            member: name.to_owned(),
+        })?;
+        let class = this.borrow().tag.to_pool();
+        let field =
+            find_field(self.pool(), class, name).ok_or_else(|| RuntimeError::UndefinedField(name.to_owned()))?;
+        Ok((this, field))
+    }
+
+    /// Renders the script function that invoked this native along with its parameters and locals.
+    pub fn caller_frame(&self) -> Option<CallerFrame> {
+        let frames = self.root.frames.borrow();
+        CallerFrame::new(self.caller?, frames.last()?, self.pool(), self.float_format)
+    }
+
+    /// Returns a string shared with every other interned string of the same contents.
+    #[inline]
+    pub fn intern(&self, str: &str) -> Value<'gc> {
+        Value::Str(self.root.intern(str, self.mc))
+    }
+
+    #[inline]
+    pub(crate) fn maps(&self) -> RefMut<'gc, ScriptMaps<'gc>> {
+        self.root.maps.borrow_mut(self.mc)
+    }
+
+    pub(crate) fn finish(self) -> RuntimeResult<()> {
+        if self.remaining != 0 {
+            return Err(RuntimeError::InvalidNativeStackAccess);
+        }
+        Ok(())
+    }
+}
+
+/// Host values shared with natives, at most one of every type.
+#[derive(Debug, Default)]
+pub(crate) struct UserData(HashMap<any::TypeId, Box<dyn Any>>);
+
+impl UserData {
+    pub(crate) fn insert<T: 'static>(&mut self, val: T) -> Option<T> {
+        let prev = self.0.insert(any::TypeId::of::<T>(), Box::new(val))?;
+        prev.downcast().ok().map(|prev| *prev)
+    }
+
+    pub(crate) fn remove<T: 'static>(&mut self) -> Option<T> {
+        let val = self.0.remove(&any::TypeId::of::<T>())?;
+        val.downcast().ok().map(|val| *val)
+    }
+
+    pub(crate) fn get<T: 'static>(&self) -> Option<&T> {
+        self.0.get(&any::TypeId::of::<T>())?.downcast_ref()
+    }
+
+    pub(crate) fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.0.get_mut(&any::TypeId::of::<T>())?.downcast_mut()
+    }
+}
+
+/// Access to the VM for natives that call back into scripts. The arguments have to be popped before
+/// the VM can be used, because script calls push their own values onto the stack.
+pub struct ReentrantCtx<'ctx, 'pool> {
+    pub(crate) vm: &'ctx mut VM<'pool>,
+    callee: PoolIndex<Function>,
+    arity: usize,
+    remaining: usize,
+}
+
+impl<'ctx, 'pool> ReentrantCtx<'ctx, 'pool> {
+    pub(crate) fn new(vm: &'ctx mut VM<'pool>, callee: PoolIndex<Function>, arity: usize) -> Self {
+        Self {
+            vm,
+            callee,
+            arity,
+            remaining: arity,
+        }
+    }
+
+    #[inline]
+    pub fn pool(&self) -> &'pool ConstantPool {
+        self.vm.metadata().pool()
+    }
+
+    /// Whether the native is declared with a return type.
+    pub fn returns(&self) -> bool {
+        self.pool()
+            .function(self.callee)
+            .is_ok_and(|function| function.return_type.is_some())
+    }
+
+    #[inline]
+    pub fn arg_count(&self) -> usize {
+        self.arity
+    }
+
+    pub fn expect_args(&self, count: usize) -> RuntimeResult<()> {
+        if self.arity != count {
+            return Err(RuntimeError::NativeArgumentMismatch {
+                expected: count,
+                actual: self.arity,
+            });
+        }
+        Ok(())
+    }
+
+    fn take_arg(&mut self) -> RuntimeResult<()> {
+        if self.remaining == 0 {
+            return Err(RuntimeError::InvalidNativeStackAccess);
+        }
+        self.remaining -= 1;
+        Ok(())
+    }
+
+    pub fn pop<A: for<'gc> FromVM<'gc>>(&mut self) -> RuntimeResult<A> {
+        self.take_arg()?;
+        let pool = self.pool();
+        self.vm.arena.mutate(|mc, root| {
+            let val = root.pop(mc).map_err(|_| RuntimeError::InvalidInteropParameters)?;
+            A::from_vm(val, pool).map_err(RuntimeError::InvalidNativeArgument)
+        })
+    }
+
+    /// Pops an array argument, which stays alive until all of its elements are read with
+    /// [`VM::next_element`] or it's released.
+    pub fn pop_array(&mut self) -> RuntimeResult<ArrayIter> {
+        self.take_arg()?;
+        self.vm.root_popped_array()
+    }
+
+    /// Pops an object argument, which stays alive until it's released with [`VM::release_ref`].
+    pub fn pop_ref<T: ScriptClass>(&mut self) -> RuntimeResult<TypedRef<T>> {
+        self.take_arg()?;
+        let class = self.vm.script_class::<T>()?;
+        self.vm.root_popped_ref(class)
+    }
+
+    /// Resolves the function a callback argument refers to, by its name or `Class::StaticMethod` path.
+    pub fn resolve_function(&self, path: &str) -> RuntimeResult<PoolIndex<Function>> {
+        self.vm
+            .metadata()
+            .resolve_function(path)
+            .ok_or_else(|| RuntimeError::UndefinedMethod(path.to_owned()))
+    }
+
+    /// Returns the VM for calling script functions, once all arguments have been popped.
+    pub fn vm(&mut self) -> RuntimeResult<&mut VM<'pool>> {
+        if self.remaining != 0 {
+            return Err(RuntimeError::InvalidNativeStackAccess);
+        }
+        Ok(&mut *self.vm)
+    }
+
+    pub fn push<A: for<'gc> IntoVM<'gc>>(&mut self, val: A) -> RuntimeResult<()> {
+        if self.remaining != 0 {
+            return Err(RuntimeError::InvalidNativeStackAccess);
+        }
+        let meta = &self.vm.metadata;
+        self.vm.arena.mutate(|mc, root| root.push(val.into_vm(mc, meta), mc));
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> RuntimeResult<()> {
+        if self.remaining != 0 {
+            return Err(RuntimeError::InvalidNativeStackAccess);
+        }
+        Ok(())
+    }
+}
+
+macro_rules! count {
+    () => { 0 };
+    ($head:ident $(, $tail:ident)*) => { 1 + count!($($tail),*) };
+}
+
 macro_rules! impl_function_unit {
     ( [$( $types:ident ),*], [$( $locals:ident ),*] ) => {
         #[allow(unused_variables)]
-        impl<$($types,)* F> IntoVMFunction<($($types,)*), ()> for F
+        impl<$($types,)* Fun> IntoVMFunction<($($types,)*), ()> for Fun
         where
-            F: Fn($($types,)*) + 'static,
+            Fun: FnMut($($types,)*) + 'static,
             $($types: for<'gc> FromVM<'gc>,)*
         {
             fn into_vm_function(self) -> Box<VMFunction> {
-                Box::new(move |mc, st, pool| {
-                    $(let lower!($locals) = FromVM::from_vm(st.pop(mc).unwrap(), pool).unwrap();)*
-                    self($(lower!($types),)*);
-                    None
+                let fun = RefCell::new(self);
+                Box::new(move |ctx: &mut NativeCtx<'_, '_>| -> RuntimeResult<()> {
+                    ctx.expect_args(count!($($types),*))?;
+                    $(let lower!($locals) = ctx.pop()?;)*
+                    (*fun.borrow_mut())($(lower!($types),)*);
+                    Ok(())
                 })
             }
         }
@@ -47,20 +505,26 @@ impl_function_unit!([A], [a]);
 impl_function_unit!([A, B], [b, a]);
 impl_function_unit!([A, B, C], [c, b, a]);
 impl_function_unit!([A, B, C, D], [d, c, b, a]);
+impl_function_unit!([A, B, C, D, E], [e, d, c, b, a]);
+impl_function_unit!([A, B, C, D, E, F], [f, e, d, c, b, a]);
+impl_function_unit!([A, B, C, D, E, F, G], [g, f, e, d, c, b, a]);
+impl_function_unit!([A, B, C, D, E, F, G, H], [h, g, f, e, d, c, b, a]);
 
 macro_rules! impl_function_ret {
     ( [$( $types:ident ),*], [$( $locals:ident ),*] ) => {
         #[allow(unused_variables)]
-        impl<$($types,)* R, F> IntoVMFunction<($($types,)*), Ret<R>> for F
+        impl<$($types,)* R, Fun> IntoVMFunction<($($types,)*), Ret<R>> for Fun
         where
-            F: Fn($($types,)*) -> Ret<R> + 'static,
+            Fun: FnMut($($types,)*) -> Ret<R> + 'static,
             $($types: for<'gc> FromVM<'gc>,)*
             R: for<'gc> IntoVM<'gc>,
         {
             fn into_vm_function(self) -> Box<VMFunction> {
-                Box::new(move |mc, st, pool| {
-                    $(let lower!($locals) = FromVM::from_vm(st.pop(mc).unwrap(), pool).unwrap();)*
-                    Some(self($(lower!($types),)*).0.into_vm(mc))
+                let fun = RefCell::new(self);
+                Box::new(move |ctx: &mut NativeCtx<'_, '_>| -> RuntimeResult<()> {
+                    ctx.expect_args(count!($($types),*))?;
+                    $(let lower!($locals) = ctx.pop()?;)*
+                    ctx.push((*fun.borrow_mut())($(lower!($types),)*).0)
                 })
             }
         }
@@ -72,28 +536,84 @@ impl_function_ret!([A], [a]);
 impl_function_ret!([A, B], [b, a]);
 impl_function_ret!([A, B, C], [c, b, a]);
 impl_function_ret!([A, B, C, D], [d, c, b, a]);
+impl_function_ret!([A, B, C, D, E], [e, d, c, b, a]);
+impl_function_ret!([A, B, C, D, E, F], [f, e, d, c, b, a]);
+impl_function_ret!([A, B, C, D, E, F, G], [g, f, e, d, c, b, a]);
+impl_function_ret!([A, B, C, D, E, F, G, H], [h, g, f, e, d, c, b, a]);
+
+macro_rules! impl_function_struct {
+    ( [$( $types:ident ),*], [$( $locals:ident ),*] ) => {
+        #[allow(unused_variables)]
+        impl<$($types,)* R, Fun> IntoVMFunction<($($types,)*), RetStruct<R>> for Fun
+        where
+            Fun: FnMut($($types,)*) -> RetStruct<R> + 'static,
+            $($types: for<'gc> FromVM<'gc>,)*
+            R: for<'gc> IntoVMFields<'gc>,
+        {
+            fn into_vm_function(self) -> Box<VMFunction> {
+                let fun = RefCell::new(self);
+                Box::new(move |ctx: &mut NativeCtx<'_, '_>| -> RuntimeResult<()> {
+                    ctx.expect_args(count!($($types),*))?;
+                    $(let lower!($locals) = ctx.pop()?;)*
+                    let fields = (*fun.borrow_mut())($(lower!($types),)*).0.into_vm_fields(ctx.mutation(), ctx.metadata());
+                    ctx.push_struct(fields)
+                })
+            }
+        }
+    };
+}
+
+impl_function_struct!([], []);
+impl_function_struct!([A], [a]);
+impl_function_struct!([A, B], [b, a]);
+impl_function_struct!([A, B, C], [c, b, a]);
+impl_function_struct!([A, B, C, D], [d, c, b, a]);
+impl_function_struct!([A, B, C, D, E], [e, d, c, b, a]);
+impl_function_struct!([A, B, C, D, E, F], [f, e, d, c, b, a]);
+impl_function_struct!([A, B, C, D, E, F, G], [g, f, e, d, c, b, a]);
+impl_function_struct!([A, B, C, D, E, F, G, H], [h, g, f, e, d, c, b, a]);
+
+macro_rules! impl_tuple_conversions {
+    ( $( $types:ident ),* ) => {
+        impl<'gc, $($types: IntoVM<'gc>,)*> IntoVMFields<'gc> for ($($types,)*) {
+            fn into_vm_fields(self, mc: &Mutation<'gc>, meta: &Metadata<'_>) -> Vec<Value<'gc>> {
+                let ($(lower!($types),)*) = self;
+                vec![$(lower!($types).into_vm(mc, meta)),*]
+            }
+        }
+
+        impl<'gc, $($types: FromVM<'gc>,)*> FromVM<'gc> for ($($types,)*) {
+            fn from_vm<'pool>(val: Value<'gc>, pool: &'pool ConstantPool) -> Result<Self, &'static str> {
+                let mut fields = value::struct_fields(&val)?.into_iter();
+                Ok(($($types::from_vm(fields.next().ok_or("Invalid argument, missing struct field")?, pool)?,)*))
+            }
+        }
+    };
+}
+
+impl_tuple_conversions!(A, B);
+impl_tuple_conversions!(A, B, C);
+impl_tuple_conversions!(A, B, C, D);
 
 macro_rules! impl_function_out {
     ( [ $type:ident $( ,$types:ident )*], [ $( $locals:ident ),*], $local:ident ) => {
         #[allow(unused_variables)]
-        impl<$type, $($types,)* R, F> IntoVMFunction<($type, $($types,)*), RetOut<R, $type>> for F
+        impl<$type, $($types,)* R, Fun> IntoVMFunction<($type, $($types,)*), RetOut<R, $type>> for Fun
         where
-            F: Fn($type, $($types,)*) -> RetOut<R, $type> + 'static,
+            Fun: FnMut($type, $($types,)*) -> RetOut<R, $type> + 'static,
             $type: for<'gc> FromVM<'gc> + for<'gc> IntoVM<'gc>,
             $($types: for<'gc> FromVM<'gc>,)*
             R: for<'gc> IntoVM<'gc>,
         {
             fn into_vm_function(self) -> Box<VMFunction> {
-                Box::new(move |mc, st, pool| {
-                    $(let lower!($locals) = st.pop(mc).unwrap();)*
-                    let $local = st.pop(mc).unwrap();
-                    if let Value::Pinned(pinned) = $local {
-                        let res = self(FromVM::from_vm($local, pool).unwrap(), $(FromVM::from_vm(lower!($types), pool).unwrap(),)*);
-                        *pinned.borrow_mut(mc) = res.1.into_vm(mc);
-                        Some(res.0.into_vm(mc))
-                    } else {
-                        panic!("expected a pinned value for out parameter")
-                    }
+                let fun = RefCell::new(self);
+                Box::new(move |ctx: &mut NativeCtx<'_, '_>| -> RuntimeResult<()> {
+                    ctx.expect_args(count!($type $(, $types)*))?;
+                    $(let lower!($locals) = ctx.pop()?;)*
+                    let pinned = ctx.pop_pinned()?;
+                    let res = (*fun.borrow_mut())(ctx.read_pinned(pinned)?, $(lower!($types),)*);
+                    ctx.write_pinned(pinned, res.1);
+                    ctx.push(res.0)
                 })
             }
         }
@@ -104,10 +624,48 @@ impl_function_out!([A], [], a);
 impl_function_out!([A, B], [b], a);
 impl_function_out!([A, B, C], [c, b], a);
 impl_function_out!([A, B, C, D], [d, c, b], a);
+impl_function_out!([A, B, C, D, E], [e, d, c, b], a);
+impl_function_out!([A, B, C, D, E, F], [f, e, d, c, b], a);
+impl_function_out!([A, B, C, D, E, F, G], [g, f, e, d, c, b], a);
+impl_function_out!([A, B, C, D, E, F, G, H], [h, g, f, e, d, c, b], a);
 
 #[macro_export]
 macro_rules! args {
     ( $( $exprs:expr ),* ) => {
-       |mc| vec![$($exprs.into_vm(mc)),*]
+       |mc, meta| vec![$($exprs.into_vm(mc, meta)),*]
+    };
+}
+
+/// Implements [`FromVM`] and [`IntoVM`] for a Rust struct mirroring a script struct, with the fields
+/// converted by name. Fields can be of any convertible type, including arrays and other such structs.
+/// ```ignore
+/// vm_struct!(Loadout as "Loadout" { name, items });
+/// ```
+#[macro_export]
+macro_rules! vm_struct {
+    ($typ:ident as $name:literal { $( $field:ident ),* $(,)? }) => {
+        impl<'gc> $crate::interop::FromVM<'gc> for $typ {
+            fn from_vm(
+                val: $crate::value::Value<'gc>,
+                pool: &$crate::interop::ConstantPool,
+            ) -> Result<Self, &'static str> {
+                Ok(Self {
+                    $($field: $crate::value::read_struct_field(&val, stringify!($field), pool)?,)*
+                })
+            }
+        }
+
+        impl<'gc> $crate::interop::IntoVM<'gc> for $typ {
+            fn into_vm(
+                self,
+                mc: &$crate::interop::Mutation<'gc>,
+                meta: &$crate::metadata::Metadata<'_>,
+            ) -> $crate::value::Value<'gc> {
+                let fields = vec![
+                    $((stringify!($field), $crate::interop::IntoVM::into_vm(self.$field, mc, meta)),)*
+                ];
+                $crate::value::build_struct($name, fields, mc, meta.pool())
+            }
+        }
     };
 }
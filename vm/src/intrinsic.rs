@@ -0,0 +1,97 @@
+use crate::error::{RuntimeError, RuntimeResult};
+use crate::value::Value;
+
+const NOT_A_NUMBER: &str = "Invalid argument, expected a number";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl NumType {
+    fn from_name(name: &str) -> Option<Self> {
+        let res = match name {
+            "Int8" => NumType::I8,
+            "Int16" => NumType::I16,
+            "Int32" => NumType::I32,
+            "Int64" => NumType::I64,
+            "Uint8" => NumType::U8,
+            "Uint16" => NumType::U16,
+            "Uint32" => NumType::U32,
+            "Uint64" => NumType::U64,
+            "Float" => NumType::F32,
+            "Double" => NumType::F64,
+            _ => return None,
+        };
+        Some(res)
+    }
+}
+
+/// Functions executed directly on the value stack instead of going through native dispatch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intrinsic {
+    Cast(NumType),
+}
+
+impl Intrinsic {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let mut parts = name.split(';');
+        match (parts.next()?, parts.next()?, parts.next()?, parts.next()) {
+            ("Cast", from, to, None) => {
+                NumType::from_name(from)?;
+                Some(Intrinsic::Cast(NumType::from_name(to)?))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn apply<'gc>(self, val: Value<'gc>) -> RuntimeResult<Value<'gc>> {
+        match self {
+            Intrinsic::Cast(to) => cast(&*val.unpinned(), to),
+        }
+    }
+}
+
+macro_rules! cast_as {
+    ($val:expr, $to:ty) => {
+        match $val {
+            Value::I8(x) => *x as $to,
+            Value::I16(x) => *x as $to,
+            Value::I32(x) => *x as $to,
+            Value::I64(x) => *x as $to,
+            Value::U8(x) => *x as $to,
+            Value::U16(x) => *x as $to,
+            Value::U32(x) => *x as $to,
+            Value::U64(x) => *x as $to,
+            Value::F32(x) => *x as $to,
+            Value::F64(x) => *x as $to,
+            _ => return Err(RuntimeError::InvalidNativeArgument(NOT_A_NUMBER)),
+        }
+    };
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn cast<'gc>(val: &Value<'gc>, to: NumType) -> RuntimeResult<Value<'gc>> {
+    let res = match to {
+        NumType::I8 => Value::I8(cast_as!(val, i8)),
+        NumType::I16 => Value::I16(cast_as!(val, i16)),
+        NumType::I32 => Value::I32(cast_as!(val, i32)),
+        NumType::I64 => Value::I64(cast_as!(val, i64)),
+        NumType::U8 => Value::U8(cast_as!(val, u8)),
+        NumType::U16 => Value::U16(cast_as!(val, u16)),
+        NumType::U32 => Value::U32(cast_as!(val, u32)),
+        NumType::U64 => Value::U64(cast_as!(val, u64)),
+        NumType::F32 => Value::F32(cast_as!(val, f32)),
+        NumType::F64 => Value::F64(cast_as!(val, f64)),
+    };
+    Ok(res)
+}
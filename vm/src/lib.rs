@@ -1,31 +1,129 @@
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::io;
+use std::panic::AssertUnwindSafe;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 use std::usize;
 
-use error::{RuntimeError, RuntimeResult};
+use cancel::CancellationToken;
+use debug::{
+    Breakpoint, BreakpointHandler, Debugger, Paused, StepFilters, StepMode, UnwindHandler, Unwound, Watch, WatchError,
+    WatchResult,
+};
+use error::{BacktraceFrame, ErrorHandler, RuntimeError, RuntimeResult, ThrowLocation, Warning, WarningKind};
+use gc::{AllocationStats, CallAllocations, DebtPolicy, GcAction, GcPolicy, GcStats};
 use gc_arena::lock::{GcRefLock, RefLock};
-use gc_arena::{Arena, Collect, Gc, Mutation, Rootable};
+use gc_arena::{Arena, Collect, Gc, GcWeak, Mutation, Rootable};
 use index_map::IndexMap;
-use interop::FromVM;
+use intercept::{Intercept, NativeInterceptor};
+use interop::{
+    CallerFrame, FromVM, IntoVM, NativeCtx, ReentrantCtx, ReentrantFunction, ScriptClass, TypedRef, UserData,
+};
 use metadata::Metadata;
+use native::ScriptMaps;
+use profiler::{CallDepths, CallEdge, FunctionProfile, ProfileOrder, Profiler, RecursionProfile, RegionProfile};
+use recorder::{CallRecorder, NativeCall};
 use redscript::bundle::{ConstantPool, PoolIndex};
 use redscript::bytecode::{Instr, Location, Offset};
-use redscript::definition::{Function, Parameter};
-use value::Value;
+use redscript::definition::{Class, Function, Parameter};
+use stack::OperandStack;
+use trace::{CallTracer, TraceEvent};
+use value::{FloatFormat, VMIndex, Value};
 
-use crate::value::{Instance, Obj, StringType};
+use crate::value::{CName, Instance, Obj, StringType, Variant};
 
 mod array;
+pub mod cancel;
+pub mod compat;
+pub mod debug;
+pub mod diff;
 pub mod error;
+pub mod gc;
+pub mod graph;
 mod index_map;
+pub mod intercept;
 pub mod interop;
+pub mod intrinsic;
 pub mod metadata;
 pub mod native;
+pub mod profiler;
+pub mod recorder;
+mod stack;
+pub mod trace;
 pub mod value;
 
+const DEADLINE_CHECK_INTERVAL: usize = 256;
+
+/// The version of the VM. Plugins exchange Rust values with their host, so hosts should only load
+/// plugins built against the same version, which they export with [`plugin_version!`].
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[doc(hidden)]
+pub const VERSION_CSTR: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// Exports the version of the VM a plugin is built against as `redscript_plugin_version`, a C function
+/// hosts can call to check a plugin before calling anything that takes Rust values.
+#[macro_export]
+macro_rules! plugin_version {
+    () => {
+        #[no_mangle]
+        pub extern "C" fn redscript_plugin_version() -> *const ::std::ffi::c_char {
+            $crate::VERSION_CSTR.as_ptr().cast()
+        }
+    };
+}
+
 pub struct VM<'pool> {
     arena: Arena<Rootable![VMRoot<'_>]>,
     metadata: Metadata<'pool>,
+    gc_policy: Box<dyn GcPolicy>,
+    alloc_stats: AllocationStats,
+    call_allocs: CallAllocations,
+    gc_stats: GcStats,
+    log_gc_stats: bool,
+    calls: Vec<PoolIndex<Function>>,
+    watches: Vec<Watch>,
+    debugger: Option<Debugger<'pool>>,
+    recorder: Option<CallRecorder>,
+    breakpoint_handler: Option<Box<BreakpointHandler>>,
+    breakpoints: HashSet<(u32, u16)>,
+    unwind_handler: Option<Box<UnwindHandler>>,
+    interceptors: Vec<Box<dyn NativeInterceptor>>,
+    tracer: Option<CallTracer>,
+    suspended: Option<(Frame<'pool>, Checkpoint)>,
+    /// The number of script calls active while a sliced call runs, natives called by its function
+    /// can suspend it.
+    slice_depth: Option<usize>,
+    awaited_native: Option<PoolIndex<Function>>,
+    /// The natives called by the function of a sliced call in the statement it's executing, with
+    /// their results in `VMRoot::native_results`. When a suspended statement is executed again,
+    /// they return the same results instead of being called again.
+    statement_natives: Vec<PoolIndex<Function>>,
+    /// The number of natives of `statement_natives` replayed by the current execution of the statement.
+    replayed_natives: usize,
+    /// Whether the statement called a script function or a native implemented with access to the VM,
+    /// which would run again if the statement was executed again, so it can no longer be suspended.
+    statement_calls: bool,
+    float_format: FloatFormat,
+    next_array_handle: u64,
+    next_ref_handle: u64,
+    last_object_id: u64,
+    profiler: Profiler,
+    depths: CallDepths,
+    lenient_nulls: bool,
+    warnings: Vec<Warning>,
+    catch_native_panics: bool,
+    fuel: Fuel,
+    cancellation: Option<CancellationToken>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    name_switches: HashMap<(u32, u16), Option<NameSwitch>>,
+    vm_natives: HashMap<u32, VMNative>,
+    reentrant_natives: HashMap<u32, Rc<ReentrantFunction<'pool>>>,
+    error_handler: Option<Box<ErrorHandler>>,
+    user_data: UserData,
 }
 
 impl<'pool> VM<'pool> {
@@ -35,8 +133,54 @@ impl<'pool> VM<'pool> {
             frames: GcRefLock::new(mc, RefLock::default()),
             stack: GcRefLock::new(mc, RefLock::default()),
             contexts: GcRefLock::new(mc, RefLock::default()),
+            interned: GcRefLock::new(mc, RefLock::default()),
+            maps: GcRefLock::new(mc, RefLock::default()),
+            rooted_arrays: GcRefLock::new(mc, RefLock::default()),
+            rooted_objects: GcRefLock::new(mc, RefLock::default()),
+            native_results: GcRefLock::new(mc, RefLock::default()),
         });
-        Self { arena, metadata }
+        Self {
+            arena,
+            metadata,
+            gc_policy: Box::new(DebtPolicy::default()),
+            alloc_stats: AllocationStats::default(),
+            call_allocs: CallAllocations::default(),
+            gc_stats: GcStats::default(),
+            log_gc_stats: false,
+            calls: vec![],
+            watches: vec![],
+            debugger: None,
+            recorder: None,
+            breakpoint_handler: None,
+            breakpoints: HashSet::new(),
+            unwind_handler: None,
+            interceptors: vec![],
+            tracer: None,
+            suspended: None,
+            slice_depth: None,
+            awaited_native: None,
+            statement_natives: vec![],
+            replayed_natives: 0,
+            statement_calls: false,
+            float_format: FloatFormat::default(),
+            next_array_handle: 0,
+            next_ref_handle: 0,
+            last_object_id: 0,
+            profiler: Profiler::default(),
+            depths: CallDepths::default(),
+            lenient_nulls: false,
+            warnings: vec![],
+            catch_native_panics: true,
+            fuel: Fuel::default(),
+            cancellation: None,
+            timeout: None,
+            deadline: None,
+            name_switches: HashMap::new(),
+            vm_natives: HashMap::new(),
+            reentrant_natives: HashMap::new(),
+            error_handler: None,
+            user_data: UserData::default(),
+        }
     }
 
     pub fn metadata(&self) -> &Metadata<'pool> {
@@ -47,6 +191,437 @@ impl<'pool> VM<'pool> {
         &mut self.metadata
     }
 
+    /// Shares a host value with natives, which can access it with [`NativeCtx::context`]. One value
+    /// of every type can be set, the value it replaces is returned.
+    pub fn set_context<T: 'static>(&mut self, val: T) -> Option<T> {
+        self.user_data.insert(val)
+    }
+
+    pub fn context<T: 'static>(&self) -> Option<&T> {
+        self.user_data.get()
+    }
+
+    pub fn context_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.user_data.get_mut()
+    }
+
+    pub fn take_context<T: 'static>(&mut self) -> Option<T> {
+        self.user_data.remove()
+    }
+
+    /// Sets how floats are formatted by `ToString` and `FloatToString`.
+    pub fn set_float_format(&mut self, format: FloatFormat) {
+        self.float_format = format;
+    }
+
+    /// Whether calls to a native function can be handled, by a registered native or by the VM itself.
+    pub fn is_native_defined(&self, idx: PoolIndex<Function>) -> bool {
+        self.metadata.get_native(idx).is_some()
+            || self.metadata.get_intrinsic(idx).is_some()
+            || self.vm_natives.contains_key(&u32::from(idx))
+            || self.reentrant_natives.contains_key(&u32::from(idx))
+    }
+
+    pub fn set_gc_policy<P: GcPolicy + 'static>(&mut self, policy: P) {
+        self.gc_policy = Box::new(policy);
+    }
+
+    pub fn allocation_stats(&self) -> AllocationStats {
+        self.alloc_stats
+    }
+
+    pub fn collect_garbage(&mut self) {
+        let before = self.arena.metrics().total_allocation();
+        self.arena.collect_all();
+        self.arena.mutate(|mc, root| {
+            root.interned
+                .borrow_mut(mc)
+                .retain(|_, weak| weak.upgrade(mc).is_some());
+        });
+        self.gc_stats.full_collections += 1;
+        self.gc_stats.freed += before.saturating_sub(self.arena.metrics().total_allocation());
+        self.alloc_stats.allocations = 0;
+        self.call_allocs.last_total = self.arena.metrics().total_allocation();
+    }
+
+    /// Performs incremental collection steps until the allocation debt is paid off or the budget runs
+    /// out, meant to be called once per frame by embedders that use a [`ManualPolicy`](gc::ManualPolicy)
+    /// while scripts run. The budget is checked between the steps, so the last one can overrun it.
+    pub fn collect_for(&mut self, budget: Duration) -> GcStats {
+        let start = Instant::now();
+        while self.arena.metrics().allocation_debt() > 0. && start.elapsed() < budget {
+            self.collect_debt();
+        }
+        self.call_allocs.last_total = self.arena.metrics().total_allocation();
+        self.gc_stats()
+    }
+
+    fn collect_debt(&mut self) {
+        let before = self.arena.metrics().total_allocation();
+        self.arena.collect_debt();
+        self.gc_stats.incremental_steps += 1;
+        self.gc_stats.freed += before.saturating_sub(self.arena.metrics().total_allocation());
+        self.alloc_stats.allocations = 0;
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        let metrics = self.arena.metrics();
+        GcStats {
+            allocated: metrics.total_allocation(),
+            debt: metrics.allocation_debt(),
+            ..self.gc_stats
+        }
+    }
+
+    /// Logs the GC stats at the info level after every top-level call.
+    pub fn set_gc_stats_logging(&mut self, enabled: bool) {
+        self.log_gc_stats = enabled;
+    }
+
+    pub fn set_allocation_budget(&mut self, budget: Option<usize>) {
+        self.call_allocs.budget = budget;
+    }
+
+    /// Returns the functions that allocated the most during the last call, in descending order.
+    pub fn allocation_report(&self) -> Vec<(PoolIndex<Function>, usize)> {
+        self.call_allocs.report()
+    }
+
+    /// Defines the value returned by the script native `name`, e.g. `native func DebugMode() -> Bool`.
+    pub fn set_global<A>(&mut self, name: &str, val: A) -> Option<()>
+    where
+        A: for<'gc> IntoVM<'gc> + Clone + 'static,
+    {
+        self.metadata.register_raw_native(name, move |ctx| {
+            ctx.expect_args(0)?;
+            ctx.push(val.clone())
+        })
+    }
+
+    pub fn set_call_recorder(&mut self, recorder: Option<CallRecorder>) {
+        self.recorder = recorder;
+    }
+
+    pub fn call_recorder(&self) -> Option<&CallRecorder> {
+        self.recorder.as_ref()
+    }
+
+    pub fn set_call_tracer(&mut self, tracer: Option<CallTracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn call_tracer(&self) -> Option<&CallTracer> {
+        self.tracer.as_ref()
+    }
+
+    /// Adds an interceptor wrapping every native call, interceptors run in the order they were added.
+    pub fn add_interceptor(&mut self, interceptor: impl NativeInterceptor + 'static) {
+        self.interceptors.push(Box::new(interceptor));
+    }
+
+    pub fn clear_interceptors(&mut self) {
+        self.interceptors.clear();
+    }
+
+    /// Makes field reads through a null reference produce the default value of the field with a
+    /// warning, instead of failing with a null pointer error.
+    pub fn set_lenient_nulls(&mut self, enabled: bool) {
+        self.lenient_nulls = enabled;
+    }
+
+    /// Returns the warnings reported by lenient modes during the last top-level call, in the order
+    /// they occurred.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Makes the VM catch panics raised by natives and fail the call with
+    /// [`RuntimeError::NativePanicked`] instead, which is the default.
+    pub fn set_catch_native_panics(&mut self, enabled: bool) {
+        self.catch_native_panics = enabled;
+    }
+
+    /// Limits the number of instructions a call can execute, exceeding it aborts the call with
+    /// [`RuntimeError::OutOfFuel`]. The budget is shared by all nested calls of a top-level call.
+    pub fn set_fuel_limit(&mut self, limit: Option<u64>) {
+        self.fuel.limit = limit;
+    }
+
+    /// Returns the number of instructions executed by the last top-level call.
+    pub fn fuel_used(&self) -> u64 {
+        self.fuel.used
+    }
+
+    /// Sets a token that aborts the running call with [`RuntimeError::Cancelled`] once cancelled.
+    pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        self.cancellation = token;
+    }
+
+    /// Limits the time a top-level call can take, exceeding it aborts the call with
+    /// [`RuntimeError::TimedOut`]. The time of sliced calls is measured from the moment they started.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Enables the `Try(function: String) -> String` native, which calls a parameterless function by
+    /// its name or `Class::StaticMethod` path and returns the message of the error it failed with, or
+    /// an empty string if it succeeded. The VM is restored to the state from before the call.
+    pub fn register_try(&mut self) {
+        self.register_vm_native("Try", VMNative::Try);
+    }
+
+    /// Enables the `ProfileBegin(name: String)` and `ProfileEnd(name: String)` natives, which time
+    /// named regions of scripts while profiling is enabled.
+    pub fn register_profiling_natives(&mut self) {
+        self.register_vm_native("ProfileBegin", VMNative::ProfileBegin);
+        self.register_vm_native("ProfileEnd", VMNative::ProfileEnd);
+    }
+
+    /// Registers a native that gets access to the VM, so that it can call back into script functions,
+    /// e.g. a `ForEach(values: array<Int32>, callback: String)` calling the function named by its
+    /// second argument for every element. Interceptors and the call recorder don't see these natives.
+    pub fn register_reentrant_native<F>(&mut self, name: &str, function: F) -> Option<()>
+    where
+        F: Fn(&mut ReentrantCtx<'_, 'pool>) -> RuntimeResult<()> + 'pool,
+    {
+        let idx = self.metadata.get_function(name)?;
+        self.reentrant_natives.insert(idx.into(), Rc::new(function));
+        Some(())
+    }
+
+    fn register_vm_native(&mut self, name: &str, native: VMNative) {
+        if let Some(idx) = self.metadata.get_function(name) {
+            self.vm_natives.insert(idx.into(), native);
+        }
+    }
+
+    /// Sets the handler producing the messages returned by `Try` for the errors it catches.
+    pub fn set_error_handler(&mut self, handler: Option<Box<ErrorHandler>>) {
+        self.error_handler = handler;
+    }
+
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler.enabled = enabled;
+    }
+
+    /// Makes the profiler count the time of natives only as the native time of the script functions
+    /// calling them, leaving natives out of the reports, the call graph and the call stacks.
+    pub fn set_fold_natives(&mut self, enabled: bool) {
+        self.profiler.fold_natives = enabled;
+    }
+
+    /// Returns the call counts and durations of the functions called while profiling was enabled, the
+    /// slowest first.
+    pub fn profiling_report(&self) -> Vec<(PoolIndex<Function>, FunctionProfile)> {
+        self.profiler.report(ProfileOrder::Total)
+    }
+
+    pub fn profiling_report_by(&self, order: ProfileOrder) -> Vec<(PoolIndex<Function>, FunctionProfile)> {
+        self.profiler.report(order)
+    }
+
+    /// Returns the timings of the regions delimited by `ProfileBegin` and `ProfileEnd`, the slowest first.
+    pub fn region_report(&self) -> Vec<(String, RegionProfile)> {
+        self.profiler.region_report()
+    }
+
+    /// Returns the deepest script call stack reached by the last top-level call.
+    pub fn max_call_depth(&self) -> usize {
+        self.depths.max_depth
+    }
+
+    /// Returns the functions that called themselves during the last top-level call, directly or
+    /// through other functions, the deepest first.
+    pub fn recursion_report(&self) -> Vec<(PoolIndex<Function>, RecursionProfile)> {
+        self.depths.recursion_report()
+    }
+
+    /// Makes the profiler collect the self time of every distinct call stack, for
+    /// [`VM::write_folded_stacks`].
+    pub fn set_stack_profiling(&mut self, enabled: bool) {
+        self.profiler.set_collect_stacks(enabled);
+    }
+
+    /// Writes the collected call stacks in the folded format used by flamegraph tools, one
+    /// `outer;inner microseconds` line per stack.
+    pub fn write_folded_stacks<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        for (stack, time) in self.profiler.stacks() {
+            let names = stack
+                .iter()
+                .map(|&idx| self.metadata.qualified_name(idx))
+                .collect::<Vec<_>>();
+            writeln!(out, "{} {}", names.join(";"), time.as_micros())?;
+        }
+        Ok(())
+    }
+
+    /// Returns how many times each function called another while profiling was enabled, the most
+    /// frequent calls first.
+    pub fn call_graph(&self) -> Vec<CallEdge> {
+        self.profiler.call_graph()
+    }
+
+    /// Writes the call graph in the DOT format of Graphviz, with edges labelled by their call counts.
+    pub fn write_call_graph<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "digraph calls {{")?;
+        for edge in self.profiler.call_graph() {
+            let caller = self.metadata.qualified_name(edge.caller);
+            let callee = self.metadata.qualified_name(edge.callee);
+            writeln!(out, "    {caller:?} -> {callee:?} [label=\"{}\"];", edge.calls)?;
+        }
+        writeln!(out, "}}")
+    }
+
+    pub fn reset_profiling(&mut self) {
+        self.profiler.reset();
+    }
+
+    /// Sets the handler invoked when a breakpoint instruction is hit. An attached debugger also pauses
+    /// right after the breakpoint.
+    pub fn set_breakpoint_handler(&mut self, handler: Option<Box<BreakpointHandler>>) {
+        self.breakpoint_handler = handler;
+    }
+
+    /// Sets the handler invoked for every script frame discarded when a failed call is unwound, the
+    /// innermost frame first.
+    pub fn set_unwind_handler(&mut self, handler: Option<Box<UnwindHandler>>) {
+        self.unwind_handler = handler;
+    }
+
+    /// Sets a breakpoint on the statement starting at `offset` in a function. Hitting it invokes the
+    /// breakpoint handler and pauses an attached debugger before the statement is executed.
+    /// Returns `false` if the breakpoint was already set.
+    pub fn set_breakpoint(&mut self, function: PoolIndex<Function>, offset: u16) -> bool {
+        self.breakpoints.insert((function.into(), offset))
+    }
+
+    pub fn clear_breakpoint(&mut self, function: PoolIndex<Function>, offset: u16) -> bool {
+        self.breakpoints.remove(&(function.into(), offset))
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn attach_debugger(&mut self, debugger: Debugger<'pool>) {
+        self.debugger = Some(debugger);
+    }
+
+    pub fn detach_debugger(&mut self) -> Option<Debugger<'pool>> {
+        self.debugger.take()
+    }
+
+    pub fn set_step_filters(&mut self, filters: StepFilters) {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.filters = filters;
+        }
+    }
+
+    /// Changes how the attached debugger pauses, steps over and out are relative to the current call.
+    pub fn set_step_mode(&mut self, mode: StepMode) {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.mode = mode;
+            debugger.depth = self.calls.len();
+        }
+    }
+
+    pub fn run_until(&mut self, function: PoolIndex<Function>, offset: u16) {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.mode = StepMode::RunUntil(function, offset);
+        }
+    }
+
+    pub fn add_watch(&mut self, expr: impl Into<String>) {
+        self.watches.push(Watch::new(expr));
+    }
+
+    pub fn remove_watch(&mut self, expr: &str) -> bool {
+        let len = self.watches.len();
+        self.watches.retain(|watch| watch.source() != expr);
+        self.watches.len() != len
+    }
+
+    pub fn watches(&self) -> impl Iterator<Item = &str> {
+        self.watches.iter().map(Watch::source)
+    }
+
+    pub fn evaluate_watches(&mut self) -> Vec<WatchResult> {
+        let function = self.calls.last().copied();
+        let meta = &self.metadata;
+        let watches = &mut self.watches;
+        self.arena.mutate(|_, root| {
+            let frames = root.frames.borrow();
+            watches
+                .iter_mut()
+                .map(|watch| {
+                    let value = match (function, frames.last()) {
+                        (Some(function), Some(locals)) => watch.evaluate(function, locals, meta),
+                        _ => Err(WatchError::NoActiveFrame),
+                    };
+                    WatchResult {
+                        expr: watch.source().to_owned(),
+                        value,
+                    }
+                })
+                .collect()
+        })
+    }
+
+    /// Evaluates an expression against the innermost script frame, either a path like `local.field`
+    /// or a call to a function without parameters like `GetVersion()` or `Class::Method()`.
+    pub fn evaluate(&mut self, expr: &str) -> Result<String, WatchError> {
+        self.evaluate_rendered(expr, Rendering::Display)
+    }
+
+    /// Evaluates an expression like [`VM::evaluate`], but renders objects with their fields grouped
+    /// by the classes of the hierarchy that declare them.
+    pub fn dump(&mut self, expr: &str) -> Result<String, WatchError> {
+        self.evaluate_rendered(expr, Rendering::Dump)
+    }
+
+    /// Evaluates an expression like [`VM::evaluate`], but renders the values reachable from the
+    /// result as a Graphviz DOT graph, see [`graph::value_graph`].
+    pub fn value_graph(&mut self, expr: &str) -> Result<String, WatchError> {
+        self.evaluate_rendered(expr, Rendering::Graph)
+    }
+
+    fn evaluate_rendered(&mut self, expr: &str, rendering: Rendering) -> Result<String, WatchError> {
+        let expr = expr.trim();
+        if let Some(path) = expr.strip_suffix("()") {
+            let idx = self
+                .metadata
+                .resolve_function(path)
+                .ok_or_else(|| WatchError::UnknownFunction(path.to_owned()))?;
+            let pool = self.metadata.pool();
+            let floats = self.float_format;
+            let failed = |err: RuntimeError| WatchError::CallFailed(err.cause().to_string());
+            let function = pool.function(idx).map_err(|_| failed(RuntimeError::missing(idx)))?;
+            if function.return_type.is_none() {
+                return self
+                    .call_void(idx, |_, _| vec![])
+                    .map(|()| String::new())
+                    .map_err(failed);
+            }
+            return self
+                .call_with_callback(
+                    idx,
+                    |_, _| vec![],
+                    |res| res.map(|val| rendering.render(&val, pool, floats)).unwrap_or_default(),
+                )
+                .map_err(failed);
+        }
+
+        let function = self.calls.last().copied().ok_or(WatchError::NoActiveFrame)?;
+        let mut watch = Watch::new(expr);
+        let meta = &self.metadata;
+        let floats = self.float_format;
+        self.arena.mutate(|_, root| {
+            let frames = root.frames.borrow();
+            let locals = frames.last().ok_or(WatchError::NoActiveFrame)?;
+            watch.evaluate_with(function, locals, meta, |val| rendering.render(val, meta.pool(), floats))
+        })
+    }
+
     #[inline]
     fn push<F>(&mut self, f: F)
     where
@@ -56,32 +631,32 @@ impl<'pool> VM<'pool> {
     }
 
     #[inline]
-    fn pop<F, A>(&mut self, f: F) -> A
+    fn pop<F, A>(&mut self, f: F) -> RuntimeResult<A>
     where
-        for<'gc> F: FnOnce(Value<'gc>, &Mutation<'gc>) -> A,
+        for<'gc> F: FnOnce(Value<'gc>, &Mutation<'gc>) -> RuntimeResult<A>,
     {
-        self.arena.mutate(|mc, root| f(root.pop(mc).unwrap(), mc))
+        self.arena.mutate(|mc, root| f(root.pop(mc)?, mc))
     }
 
     #[inline]
-    fn copy(&mut self, idx: usize) {
-        self.arena.mutate(|mc, root| root.copy(idx, mc));
+    fn copy(&mut self, idx: usize) -> RuntimeResult<()> {
+        self.arena.mutate(|mc, root| root.copy(idx, mc))
     }
 
     #[inline]
-    fn unop<F>(&mut self, f: F)
+    fn unop<F>(&mut self, f: F) -> RuntimeResult<()>
     where
-        for<'gc> F: FnOnce(Value<'gc>, &Mutation<'gc>) -> Value<'gc>,
+        for<'gc> F: FnOnce(Value<'gc>, &Mutation<'gc>) -> RuntimeResult<Value<'gc>>,
     {
-        self.arena.mutate(|mc, root| root.unop(f, mc));
+        self.arena.mutate(|mc, root| root.unop(f, mc))
     }
 
     #[inline]
-    fn binop<F>(&mut self, f: F)
+    fn binop<F>(&mut self, f: F) -> RuntimeResult<()>
     where
-        for<'gc> F: FnOnce(Value<'gc>, Value<'gc>, &Mutation<'gc>) -> Value<'gc>,
+        for<'gc> F: FnOnce(Value<'gc>, Value<'gc>, &Mutation<'gc>) -> RuntimeResult<Value<'gc>>,
     {
-        self.arena.mutate(|mc, root| root.binop(f, mc));
+        self.arena.mutate(|mc, root| root.binop(f, mc))
     }
 
     #[inline]
@@ -91,25 +666,62 @@ impl<'pool> VM<'pool> {
 
     fn run(&mut self, frame: &mut Frame<'_>) -> Result<bool, RuntimeError> {
         loop {
-            match self.exec(frame)? {
-                Action::Continue => {}
-                Action::Exit => return Ok(false),
-                Action::Return => return Ok(true),
+            if let Some(returns) = self.step(frame)? {
+                return Ok(returns);
+            }
+        }
+    }
+
+    #[inline]
+    fn step(&mut self, frame: &mut Frame<'_>) -> RuntimeResult<Option<bool>> {
+        if !self.breakpoints.is_empty() {
+            let location = frame.location();
+            if location.is_some_and(|loc| self.breakpoints.contains(&(frame.idx.into(), loc.value))) {
+                self.breakpoint(location);
+            }
+        }
+        if self.debugger.is_some() {
+            if let Some(&function) = self.calls.last() {
+                self.debug_step(function, frame.location());
+            }
+        }
+        let location = frame.location();
+        let action = match self.exec(frame) {
+            Ok(action) => action,
+            Err(RuntimeError::Thrown {
+                message,
+                location: None,
+            }) => {
+                let err = RuntimeError::Thrown {
+                    message,
+                    location: self.throw_location(location),
+                };
+                return Err(self.trace_error(err, frame, location));
             }
+            Err(err) => return Err(self.trace_error(err, frame, location)),
+        };
+        match action {
+            Action::Continue => Ok(None),
+            Action::Exit => Ok(Some(false)),
+            Action::Return => Ok(Some(true)),
         }
     }
 
     #[inline]
     fn exec(&mut self, frame: &mut Frame<'_>) -> RuntimeResult<Action> {
-        self.exec_with(frame, false)
+        self.exec_with(frame, Access::Read)
     }
 
-    fn exec_with(&mut self, frame: &mut Frame<'_>, pin: bool) -> RuntimeResult<Action> {
+    fn exec_with(&mut self, frame: &mut Frame<'_>, access: Access) -> RuntimeResult<Action> {
         let location = frame.location();
         let instr = match frame.next_instr() {
             Some(i) => i,
             None => return Ok(Action::Exit),
         };
+        self.fuel.consume()?;
+        if self.fuel.used % DEADLINE_CHECK_INTERVAL as u64 == 0 {
+            self.check_interrupts()?;
+        }
         match instr {
             Instr::Nop => {}
             Instr::Null => {
@@ -155,7 +767,11 @@ impl<'pool> VM<'pool> {
                 self.push(|_| Value::InternStr(StringType::Name, idx.into()));
             }
             Instr::EnumConst(_, member) => {
-                let val = self.metadata.pool().enum_value(member).expect("Enum member not found");
+                let val = self
+                    .metadata
+                    .pool()
+                    .enum_value(member)
+                    .map_err(|_| RuntimeError::missing(member))?;
                 self.push(|_| Value::EnumVal(val));
             }
             Instr::StringConst(str) => {
@@ -173,116 +789,141 @@ impl<'pool> VM<'pool> {
             Instr::FalseConst => {
                 self.push(|_| Value::Bool(false));
             }
-            Instr::Breakpoint(_) => todo!(),
+            Instr::Breakpoint(_) => {
+                self.breakpoint(location);
+            }
             Instr::Assign => {
                 self.assignment(frame)?;
             }
-            Instr::Target(_) => todo!(),
+            // marks the destination of a jump, jumps already carry their own offsets so there's nothing to do
+            Instr::Target(_) => {}
             Instr::Local(idx) => {
                 self.with_local(idx, |local, mc, root| {
-                    if pin {
-                        local.pin(mc);
-                    }
-                    root.push(local.copied(mc), mc);
-                });
+                    access.prepare(local, mc);
+                    root.push(local.clone(), mc);
+                    Ok(())
+                })?;
+                if access == Access::Pin {
+                    self.record_alloc(1)?;
+                }
             }
             Instr::Param(idx) => {
                 self.with_local(idx, |local, mc, root| {
-                    if pin {
-                        local.pin(mc);
-                    }
-                    root.push(local.copied(mc), mc);
-                });
+                    access.prepare(local, mc);
+                    root.push(local.clone(), mc);
+                    Ok(())
+                })?;
+                if access == Access::Pin {
+                    self.record_alloc(1)?;
+                }
             }
             Instr::ObjectField(idx) => {
-                self.arena.mutate(|mc, root| {
+                let lenient = self.lenient_nulls;
+                let meta = &self.metadata;
+                let warning = self.arena.mutate(|mc, root| {
                     let contexts = root.contexts.borrow_mut(mc);
-                    let context = contexts
-                        .last()
-                        .and_then(Obj::as_instance)
-                        .ok_or(RuntimeError::NullPointer)?;
+                    let Some(context) = contexts.last().and_then(Obj::as_instance) else {
+                        if !lenient {
+                            return Err(RuntimeError::null_field(meta.pool(), idx));
+                        }
+                        let field = meta.pool().field(idx).map_err(|_| RuntimeError::missing(idx))?;
+                        let typ = meta
+                            .get_type(field.type_)
+                            .ok_or_else(|| RuntimeError::missing(field.type_))?;
+                        root.push(typ.default_value(mc, meta)?, mc);
+                        return Ok(Some(WarningKind::null_field_read(meta.pool(), idx)));
+                    };
                     let mut context = context.borrow_mut(mc);
-                    let val = context.fields.get_mut(idx).unwrap();
-                    if pin {
-                        val.pin(mc);
-                    }
-                    root.push(val.copied(mc), mc);
-                    Ok(())
+                    let val = context.fields.get_mut(idx).ok_or(UNKNOWN_FIELD)?;
+                    access.prepare(val, mc);
+                    root.push(val.clone(), mc);
+                    Ok(None)
                 })?;
+                if let Some(kind) = warning {
+                    self.warn(kind, frame, location);
+                }
             }
             Instr::StructField(idx) => {
-                self.exec(frame)?;
+                self.exec_with(frame, access.enclosing())?;
                 self.unop(|val, mc| match &*val.unpinned() {
                     Value::BoxedStruct(cell) => {
                         let mut val = cell.borrow_mut(mc);
-                        let val = val.get_mut(idx).unwrap();
-                        if pin {
-                            val.pin(mc);
-                        }
-                        val.copied(mc)
+                        let val = val.get_mut(idx).ok_or(UNKNOWN_FIELD)?;
+                        access.prepare(val, mc);
+                        Ok(val.clone())
                     }
-                    Value::PackedStruct(_) => todo!(),
-                    _ => panic!("invalid bytecode"),
-                });
+                    Value::PackedStruct(_) => Err(RuntimeError::Unsupported("reading fields of packed structs")),
+                    _ => Err(RuntimeError::TypeMismatch("expected a struct")),
+                })?;
             }
-            Instr::ExternalVar => todo!(),
+            Instr::ExternalVar => return Err(RuntimeError::Unsupported("ExternalVar")),
             Instr::Switch(_, _) => {
                 let sp = self.arena.mutate(|_, root| root.stack.borrow().len());
                 self.exec(frame)?;
-                let mut pos = frame.location().unwrap();
+                if let Some(target) = self.name_switch_target(frame, location) {
+                    self.adjust_stack(sp);
+                    frame.seek(target)?;
+                    return Ok(Action::Continue);
+                }
                 while let Some(Instr::SwitchLabel(next, body)) = frame.current_instr() {
+                    let pos = frame.location();
                     frame.next_instr();
 
-                    self.copy(sp);
+                    self.copy(sp)?;
                     self.exec(frame)?;
-                    self.binop(|lhs, rhs, _| Value::Bool(lhs.equals(&rhs)));
+                    self.binop(|lhs, rhs, _| Ok(Value::Bool(lhs.equals(&rhs))))?;
 
-                    let equal = self.pop(|val, _| *val.unpinned().as_bool().unwrap());
-                    if equal {
-                        frame.seek(body.absolute(pos));
+                    if self.pop(|val, _| as_bool(&val))? {
+                        frame.jump(body, pos)?;
                         break;
                     }
-                    pos = next.absolute(pos);
-                    frame.seek(pos);
+                    frame.jump(next, pos)?;
                 }
                 self.adjust_stack(sp);
             }
             Instr::SwitchLabel(_, _) => {}
             Instr::SwitchDefault => {}
             Instr::Jump(offset) => {
-                frame.seek(offset.absolute(location.unwrap()));
+                frame.jump(offset, location)?;
             }
             Instr::JumpIfFalse(offset) => {
                 self.exec(frame)?;
-                let cond: bool = self.pop(|val, _| *val.unpinned().as_bool().unwrap());
+                let cond: bool = self.pop(|val, _| as_bool(&val))?;
                 if !cond {
-                    frame.seek(offset.absolute(location.unwrap()));
+                    frame.jump(offset, location)?;
                 }
             }
-            Instr::Skip(_) => todo!(),
+            Instr::Skip(offset) => {
+                frame.jump(offset, location)?;
+            }
             Instr::Conditional(when_false, exit) => {
                 self.exec(frame)?;
-                let cond: bool = self.pop(|val, _| *val.unpinned().as_bool().unwrap());
+                let cond: bool = self.pop(|val, _| as_bool(&val))?;
                 if !cond {
-                    frame.seek(when_false.absolute(location.unwrap()));
+                    frame.jump(when_false, location)?;
                 }
                 self.exec(frame)?;
-                frame.seek(exit.absolute(location.unwrap()));
+                frame.jump(exit, location)?;
             }
             Instr::Construct(args, class_idx) => {
                 for _ in 0..args {
                     self.exec(frame)?;
                 }
-                let class = self.metadata.pool().class(class_idx).unwrap();
+                let class = self
+                    .metadata
+                    .pool()
+                    .class(class_idx)
+                    .map_err(|_| RuntimeError::missing(class_idx))?;
                 let fields = class.fields.iter();
 
                 self.arena.mutate(|mc, root| {
                     let mut stack = root.stack.borrow_mut(mc);
-                    let range = (stack.len() - args as usize)..;
-                    let args = stack.drain(range);
-                    let data = fields.copied().zip(args).collect();
+                    let args = stack.pop_many(args as usize)?;
+                    let data = fields.copied().zip(args.map(|arg| arg.copied(mc))).collect();
                     stack.push(Value::BoxedStruct(Gc::new(mc, RefLock::new(data))));
-                });
+                    Ok(())
+                })?;
+                self.record_alloc(1)?;
             }
             Instr::InvokeStatic(_, _, idx, _) => {
                 self.call_static(idx, frame)?;
@@ -290,11 +931,26 @@ impl<'pool> VM<'pool> {
             Instr::InvokeVirtual(_, _, name, _) => {
                 let tag = self.arena.mutate(|_, root| {
                     let ctx = root.contexts.borrow();
-                    let inst = ctx.last().and_then(Obj::as_instance).ok_or(RuntimeError::NullPointer)?;
-                    Ok(inst.borrow().tag)
+                    Ok(ctx.last().and_then(Obj::as_instance).map(|inst| inst.borrow().tag))
                 })?;
-                let vtable = self.metadata.get_vtable(tag.to_pool()).unwrap();
-                let idx = vtable.get(name).unwrap().to_pool();
+                let Some(tag) = tag else {
+                    let member = self.metadata.pool().names.get(name).map(|str| str.to_string());
+                    return Err(RuntimeError::NullAccess {
+                        class: None,
+                        member: member.unwrap_or_default(),
+                    });
+                };
+                let vtable = self
+                    .metadata
+                    .get_vtable(tag.to_pool())
+                    .ok_or_else(|| RuntimeError::missing(tag.to_pool::<Class>()))?;
+                let idx = match vtable.get(name) {
+                    Some(idx) => idx.to_pool(),
+                    None => {
+                        let name = self.metadata.pool().names.get(name).map(|str| str.to_string());
+                        return Err(RuntimeError::UndefinedMethod(name.unwrap_or_default()));
+                    }
+                };
                 self.call_static(idx, frame)?;
             }
             Instr::ParamEnd => {}
@@ -308,12 +964,13 @@ impl<'pool> VM<'pool> {
             Instr::Context(_) => {
                 self.exec(frame)?;
                 self.arena.mutate(|mc, root| {
-                    let val = root.pop(mc).unwrap();
+                    let val = root.pop(mc)?;
                     let val = val.unpinned();
-                    let obj = val.as_obj().unwrap();
+                    let obj = val.as_obj().ok_or(RuntimeError::TypeMismatch("expected an object"))?;
                     root.contexts.borrow_mut(mc).push(obj.clone());
-                });
-                self.exec(frame)?;
+                    Ok(())
+                })?;
+                self.exec_with(frame, access.enclosing())?;
                 self.arena.mutate(|mc, root| {
                     root.contexts.borrow_mut(mc).pop();
                 });
@@ -321,29 +978,54 @@ impl<'pool> VM<'pool> {
             Instr::Equals(_) => {
                 self.exec(frame)?;
                 self.exec(frame)?;
-                self.binop(|lhs, rhs, _| Value::Bool(lhs.equals(&rhs)));
+                self.binop(|lhs, rhs, _| Ok(Value::Bool(lhs.equals(&rhs))))?;
+            }
+            Instr::RefStringEqualsString(_) | Instr::StringEqualsRefString(_) => {
+                self.exec(frame)?;
+                self.exec(frame)?;
+                let pool = self.metadata.pool();
+                self.binop(|lhs, rhs, _| Ok(Value::Bool(lhs.string_equals(&rhs, pool))))?;
             }
-            Instr::RefStringEqualsString(_) | Instr::StringEqualsRefString(_) => todo!(),
             Instr::NotEquals(_) => {
                 self.exec(frame)?;
                 self.exec(frame)?;
-                self.binop(|lhs, rhs, _| Value::Bool(!lhs.equals(&rhs)));
+                self.binop(|lhs, rhs, _| Ok(Value::Bool(!lhs.equals(&rhs))))?;
+            }
+            Instr::RefStringNotEqualsString(_) | Instr::StringNotEqualsRefString(_) => {
+                self.exec(frame)?;
+                self.exec(frame)?;
+                let pool = self.metadata.pool();
+                self.binop(|lhs, rhs, _| Ok(Value::Bool(!lhs.string_equals(&rhs, pool))))?;
             }
-            Instr::RefStringNotEqualsString(_) | Instr::StringNotEqualsRefString(_) => todo!(),
             Instr::New(class) => {
+                if self.metadata.host_type(class).is_some() {
+                    let pool = self.metadata.pool();
+                    let name = pool.def_name(class).map(|str| str.to_string()).unwrap_or_default();
+                    return Err(RuntimeError::HostClassConstruction(name));
+                }
+                let id = self.next_object_id();
                 let meta = &mut self.metadata;
                 self.arena.mutate(|mc, root| {
-                    let instance = Instance::new(class, meta, mc);
+                    let instance = Instance::new(class, id, meta, mc)?;
                     root.push(Value::Obj(Obj::Instance(Gc::new(mc, RefLock::new(instance)))), mc);
-                });
-                self.check_gc();
+                    Ok(())
+                })?;
+                self.record_alloc(1)?;
             }
-            Instr::Delete => todo!(),
+            Instr::Delete => return Err(RuntimeError::Unsupported("Delete")),
             Instr::This => {
-                self.arena
-                    .mutate(|mc, root| root.push(Value::Obj(root.contexts.borrow().last().unwrap().clone()), mc));
+                self.arena.mutate(|mc, root| {
+                    let this = root.contexts.borrow().last().cloned();
+                    let this = this.ok_or(RuntimeError::InvalidBytecode("this used outside of a method"))?;
+                    root.push(Value::Obj(this), mc);
+                    Ok(())
+                })?;
+            }
+            // the operand configures a profiling scope of the game's own profiler, which has no
+            // counterpart here, so the instruction only toggles the per-function profiler
+            Instr::StartProfiling(_) => {
+                self.profiler.enabled = !self.profiler.enabled;
             }
-            Instr::StartProfiling(_) => todo!(),
             Instr::ArrayClear(_) => {
                 array::clear(self, frame)?;
             }
@@ -405,41 +1087,68 @@ impl<'pool> VM<'pool> {
                 array::last(self, frame)?;
             }
             Instr::ArrayElement(_) => {
-                array::element(self, frame)?;
-            }
-            Instr::ArraySort(_) | Instr::ArraySortByPredicate(_) => todo!(),
-            Instr::StaticArraySize(_) => todo!(),
-            Instr::StaticArrayFindFirst(_) => todo!(),
-            Instr::StaticArrayFindFirstFast(_) => todo!(),
-            Instr::StaticArrayFindLast(_) => todo!(),
-            Instr::StaticArrayFindLastFast(_) => todo!(),
-            Instr::StaticArrayContains(_) => todo!(),
-            Instr::StaticArrayContainsFast(_) => todo!(),
-            Instr::StaticArrayCount(_) => todo!(),
-            Instr::StaticArrayCountFast(_) => todo!(),
-            Instr::StaticArrayLast(_) => todo!(),
-            Instr::StaticArrayElement(_) => todo!(),
+                array::element(self, frame, access)?;
+            }
+            Instr::ArraySort(_) => {
+                array::sort(self, frame)?;
+            }
+            Instr::ArraySortByPredicate(_) => {
+                array::sort_by_predicate(self, frame)?;
+            }
+            Instr::StaticArraySize(_) => {
+                array::size(self, frame)?;
+            }
+            Instr::StaticArrayFindFirst(_) => {
+                array::find_first(self, frame)?;
+            }
+            Instr::StaticArrayFindFirstFast(_) => {
+                array::find_first(self, frame)?;
+            }
+            Instr::StaticArrayFindLast(_) => {
+                array::find_last(self, frame)?;
+            }
+            Instr::StaticArrayFindLastFast(_) => {
+                array::find_last(self, frame)?;
+            }
+            Instr::StaticArrayContains(_) => {
+                array::contains(self, frame)?;
+            }
+            Instr::StaticArrayContainsFast(_) => {
+                array::contains(self, frame)?;
+            }
+            Instr::StaticArrayCount(_) => {
+                array::count(self, frame)?;
+            }
+            Instr::StaticArrayCountFast(_) => {
+                array::count(self, frame)?;
+            }
+            Instr::StaticArrayLast(_) => {
+                array::last(self, frame)?;
+            }
+            Instr::StaticArrayElement(_) => {
+                array::element(self, frame, access)?;
+            }
             Instr::RefToBool => {
                 self.exec(frame)?;
-                self.unop(|val, _| match val {
-                    Value::Obj(Obj::Null) => Value::Bool(false),
-                    _ => Value::Bool(true),
-                });
+                self.unop(|val, _| Ok(Value::Bool(!matches!(val, Value::Obj(Obj::Null)))))?;
             }
             Instr::WeakRefToBool => {
                 self.exec(frame)?;
-                self.unop(|val, _| match val {
-                    Value::Obj(Obj::Null) => Value::Bool(false),
-                    _ => Value::Bool(true),
-                });
+                self.unop(|val, _| Ok(Value::Bool(!matches!(val, Value::Obj(Obj::Null)))))?;
             }
             Instr::EnumToI32(_, _) => {
                 self.exec(frame)?;
-                self.unop(|val, _| Value::I32(*val.unpinned().as_enum_val().unwrap() as i32));
+                self.unop(|val, _| match &*val.unpinned() {
+                    Value::EnumVal(i) => Ok(Value::I32(*i as i32)),
+                    _ => Err(RuntimeError::TypeMismatch("expected an enum")),
+                })?;
             }
             Instr::I32ToEnum(_, _) => {
                 self.exec(frame)?;
-                self.unop(|val, _| Value::EnumVal((*val.unpinned().as_i32().unwrap()).into()));
+                self.unop(|val, _| match &*val.unpinned() {
+                    Value::I32(i) => Ok(Value::EnumVal((*i).into())),
+                    _ => Err(RuntimeError::TypeMismatch("expected an Int32")),
+                })?;
             }
             Instr::DynamicCast(expected, _) => {
                 self.exec(frame)?;
@@ -447,16 +1156,26 @@ impl<'pool> VM<'pool> {
                 let meta = &self.metadata;
                 self.arena.mutate(|mc, root| {
                     let mut stack = root.stack.borrow_mut(mc);
-                    let val = stack.pop().unwrap();
+                    let val = stack.pop()?;
                     let val = val.unpinned();
-                    let obj = val.as_obj().unwrap();
+                    if let Value::UserData(obj) = &*val {
+                        let res = match meta.host_type(expected) {
+                            Some(typ) if typ == obj.type_id() => Value::UserData(obj.clone()),
+                            _ => Value::Obj(Obj::Null),
+                        };
+                        stack.push(res);
+                        return Ok(());
+                    }
+                    let obj = val
+                        .as_obj()
+                        .ok_or_else(|| RuntimeError::invalid_cast(meta.pool(), expected, "value is not an object"))?;
                     let tag = obj
                         .as_instance()
-                        .ok_or(RuntimeError::NullPointer)?
+                        .ok_or_else(|| RuntimeError::invalid_cast(meta.pool(), expected, "value is null"))?
                         .borrow()
                         .tag
                         .to_pool();
-                    let obj = if meta.is_instance_of(tag, expected) {
+                    let obj = if meta.is_instance_of(tag, expected)? {
                         obj.clone()
                     } else {
                         Obj::Null
@@ -468,41 +1187,96 @@ impl<'pool> VM<'pool> {
             Instr::ToString(_) | Instr::VariantToString => {
                 self.exec(frame)?;
                 let pool = self.metadata.pool();
-                self.unop(|val, mc| Value::Str(Gc::new(mc, val.to_string(pool).into_boxed_str())));
-            }
-            Instr::ToVariant(_) => {
+                let floats = self.float_format;
+                self.unop(|val, mc| {
+                    let str = val.to_string_with(pool, floats).into_boxed_str();
+                    Ok(Value::Str(Gc::new(mc, str)))
+                })?;
+                self.record_alloc(1)?;
+            }
+            Instr::ToVariant(typ) => {
+                let typ = self
+                    .metadata
+                    .get_type(typ)
+                    .ok_or_else(|| RuntimeError::missing(typ))?
+                    .clone();
                 self.exec(frame)?;
+                self.unop(|val, mc| {
+                    let value = val.unpinned().copied(mc);
+                    Ok(Value::Variant(Gc::new(mc, Variant { typ, value })))
+                })?;
+                self.record_alloc(1)?;
             }
             Instr::FromVariant(typ) => {
-                let typ = self.metadata.get_type(typ).unwrap().clone();
+                let typ = self
+                    .metadata
+                    .get_type(typ)
+                    .ok_or_else(|| RuntimeError::missing(typ))?
+                    .clone();
                 self.exec(frame)?;
-                self.unop(|val, _| if val.has_type(&typ) { val } else { Value::Obj(Obj::Null) });
-            }
-            Instr::VariantIsDefined => {
-                // TODO: actually do something
+                let meta = &self.metadata;
+                self.arena.mutate(|mc, root| {
+                    let mut stack = root.stack.borrow_mut(mc);
+                    let val = stack.pop()?;
+                    let res = match &*val.unpinned() {
+                        Value::Variant(var) => var.read_as(&typ),
+                        _ => None,
+                    };
+                    let res = match res {
+                        Some(res) => res,
+                        None => typ.default_value(mc, meta)?,
+                    };
+                    stack.push(res);
+                    Ok(())
+                })?;
+            }
+            Instr::VariantIsDefined => {
                 self.exec(frame)?;
-                self.unop(|_, _| Value::Bool(true));
+                self.unop(|val, _| Ok(Value::Bool(matches!(&*val.unpinned(), Value::Variant(_)))))?;
             }
             Instr::VariantIsRef => {
                 self.exec(frame)?;
-                self.unop(|val, _| Value::Bool(matches!(val, Value::Obj(_))));
+                self.unop(|val, _| {
+                    Ok(Value::Bool(
+                        matches!(&*val.unpinned(), Value::Variant(var) if var.is_ref()),
+                    ))
+                })?;
             }
             Instr::VariantIsArray => {
                 self.exec(frame)?;
-                self.unop(|val, _| Value::Bool(matches!(val, Value::Array(_))));
+                self.unop(|val, _| {
+                    Ok(Value::Bool(
+                        matches!(&*val.unpinned(), Value::Variant(var) if var.is_array()),
+                    ))
+                })?;
+            }
+            Instr::VariantTypeName => {
+                self.exec(frame)?;
+                let meta = &self.metadata;
+                self.arena.mutate(|mc, root| {
+                    let mut stack = root.stack.borrow_mut(mc);
+                    let val = stack.pop()?;
+                    // undefined variants have no type, the game names them with the empty name
+                    let name = match &*val.unpinned() {
+                        Value::Variant(var) => var.typ.name(meta.pool())?,
+                        _ => String::new(),
+                    };
+                    stack.push(CName(name).into_vm(mc, meta));
+                    Ok(())
+                })?;
             }
-            Instr::VariantTypeName => todo!(),
             Instr::WeakRefToRef | Instr::RefToWeakRef => {}
             Instr::WeakRefNull => {
                 self.push(|_| Value::Obj(Obj::Null));
             }
             Instr::AsRef(_) => {
                 self.exec(frame)?;
-                self.unop(|val, mc| Value::Pinned(Gc::new(mc, RefLock::new(val))));
+                self.unop(|val, mc| Ok(Value::Pinned(Gc::new(mc, RefLock::new(val)))))?;
+                self.record_alloc(1)?;
             }
             Instr::Deref(_) => {
                 self.exec(frame)?;
-                self.unop(|val, _| val.unpinned().clone());
+                self.unop(|val, _| Ok(val.unpinned().clone()))?;
             }
         };
         Ok(Action::Continue)
@@ -511,51 +1285,597 @@ impl<'pool> VM<'pool> {
     #[inline]
     pub fn call<F, A>(&mut self, idx: PoolIndex<Function>, args: F) -> RuntimeResult<A>
     where
-        F: for<'gc> Fn(&Mutation<'gc>) -> Vec<Value<'gc>>,
+        F: for<'gc> Fn(&Mutation<'gc>, &Metadata<'_>) -> Vec<Value<'gc>>,
         A: for<'gc> FromVM<'gc>,
     {
         let pool = self.metadata.pool();
-        self.call_with_callback(idx, args, |res| FromVM::from_vm(res.unwrap(), pool).unwrap())
+        self.call_with_callback(idx, args, |res| {
+            let val = res.ok_or(RuntimeError::InvalidStack)?;
+            FromVM::from_vm(val, pool).map_err(RuntimeError::TypeMismatch)
+        })?
     }
 
     #[inline]
     pub fn call_with_callback<F, C, A>(&mut self, idx: PoolIndex<Function>, args: F, cb: C) -> RuntimeResult<A>
     where
-        F: for<'gc> Fn(&Mutation<'gc>) -> Vec<Value<'gc>>,
+        F: for<'gc> Fn(&Mutation<'gc>, &Metadata<'_>) -> Vec<Value<'gc>>,
         C: for<'gc> Fn(Option<Value<'gc>>) -> A,
     {
         self.call_void(idx, args)?;
-        Ok(self.arena.mutate(|mc, root| cb(root.pop(mc))))
+        Ok(self.arena.mutate(|mc, root| cb(root.pop(mc).ok())))
     }
 
+    /// Calls a function, discarding its return value. If the call fails, the VM is unwound to the
+    /// state from before the call, so that it can be used for further calls.
     pub fn call_void<F>(&mut self, idx: PoolIndex<Function>, args: F) -> RuntimeResult<()>
     where
-        F: for<'gc> Fn(&Mutation<'gc>) -> Vec<Value<'gc>>,
+        F: for<'gc> Fn(&Mutation<'gc>, &Metadata<'_>) -> Vec<Value<'gc>>,
     {
-        let function = self.metadata.pool().function(idx).unwrap();
+        self.begin_call();
+        let checkpoint = self.checkpoint();
+        let res = self
+            .push_args(idx, &[], args)
+            .and_then(|function| self.call_with_params(idx, &function.parameters));
+        if let Err(err) = &res {
+            self.unwind(checkpoint, err);
+        }
+        if self.log_gc_stats && self.calls.is_empty() {
+            log::info!("GC stats after a call, {}", self.gc_stats());
+        }
+        res
+    }
+
+    /// Invokes the implementation of `name` provided by `class` or its nearest base, bypassing virtual
+    /// dispatch. The first argument is the receiver.
+    pub fn call_base_method<F>(&mut self, class: PoolIndex<Class>, name: &str, args: F) -> RuntimeResult<()>
+    where
+        F: for<'gc> Fn(&Mutation<'gc>, &Metadata<'_>) -> Vec<Value<'gc>>,
+    {
+        let idx = self
+            .metadata
+            .get_method(class, name)
+            .ok_or_else(|| RuntimeError::UndefinedMethod(name.to_owned()))?;
+        let pool = self.metadata.pool();
+        let function = pool.function(idx).map_err(|_| RuntimeError::missing(idx))?;
+        self.begin_call();
+        let checkpoint = self.checkpoint();
+        let meta = &self.metadata;
         self.arena.mutate(|mc, root| {
-            let args = args(mc);
+            let mut args = args(mc, meta).into_iter();
+            let this = args.next().ok_or(RuntimeError::InvalidInteropParameters)?;
+            let this = this
+                .unpinned()
+                .as_obj()
+                .cloned()
+                .ok_or(RuntimeError::InvalidInteropParameters)?;
+            if this.as_instance().is_none() {
+                return Err(RuntimeError::NullAccess {
+                    class: pool.def_name(class).ok().map(|str| str.to_string()),
+                    member: name.to_owned(),
+                });
+            }
             if args.len() != function.parameters.len() {
                 return Err(RuntimeError::InvalidInteropParameters);
             }
+            root.contexts.borrow_mut(mc).push(this);
+            for arg in args {
+                root.push(arg, mc);
+            }
+            Ok(())
+        })?;
+        let res = self.call_with_params(idx, &function.parameters);
+        match &res {
+            Ok(()) => self.arena.mutate(|mc, root| {
+                root.contexts.borrow_mut(mc).pop();
+            }),
+            Err(err) => self.unwind(checkpoint, err),
+        }
+        res
+    }
+
+    /// Calls an instance method without parameters on a new instance of `class`, with the default
+    /// values of all fields. The implementation is resolved starting from `class`.
+    pub fn call_on_default_instance<C, A>(&mut self, class: PoolIndex<Class>, name: &str, cb: C) -> RuntimeResult<A>
+    where
+        C: for<'gc> Fn(Option<Value<'gc>>) -> A,
+    {
+        let idx = self
+            .metadata
+            .get_method(class, name)
+            .ok_or_else(|| RuntimeError::UndefinedMethod(name.to_owned()))?;
+        let function = self
+            .metadata
+            .pool()
+            .function(idx)
+            .map_err(|_| RuntimeError::missing(idx))?;
+        if !function.parameters.is_empty() {
+            return Err(RuntimeError::InvalidInteropParameters);
+        }
+        self.begin_call();
+        let checkpoint = self.checkpoint();
+        let id = self.next_object_id();
+        let meta = &mut self.metadata;
+        self.arena.mutate(|mc, root| {
+            let instance = Instance::new(class, id, meta, mc)?;
+            let this = Obj::Instance(Gc::new(mc, RefLock::new(instance)));
+            root.contexts.borrow_mut(mc).push(this);
+            Ok(())
+        })?;
+        let res = self
+            .record_alloc(1)
+            .and_then(|()| self.call_with_params(idx, &function.parameters));
+        match &res {
+            Ok(()) => self.arena.mutate(|mc, root| {
+                root.contexts.borrow_mut(mc).pop();
+            }),
+            Err(err) => self.unwind(checkpoint, err),
+        }
+        res?;
+        let returns = function.return_type.is_some();
+        Ok(self
+            .arena
+            .mutate(|mc, root| cb(returns.then(|| root.pop(mc).ok()).flatten())))
+    }
+
+    /// Calls an instance method on the object, the implementation is resolved starting from the class
+    /// of the object like in a virtual call. The callback receives the return value of the method.
+    pub fn call_method<T, F, C, A>(&mut self, obj: &TypedRef<T>, name: &str, args: F, cb: C) -> RuntimeResult<A>
+    where
+        F: for<'gc> Fn(&Mutation<'gc>, &Metadata<'_>) -> Vec<Value<'gc>>,
+        C: for<'gc> Fn(Option<Value<'gc>>) -> A,
+    {
+        let class = self.arena.mutate(|_, root| {
+            let this = root.rooted_object(obj.handle)?;
+            let class = this.borrow().tag.to_pool();
+            Ok::<_, RuntimeError>(class)
+        })?;
+        let idx = self
+            .metadata
+            .get_method(class, name)
+            .ok_or_else(|| RuntimeError::UndefinedMethod(name.to_owned()))?;
+        self.call_method_by_index(obj, idx, args, cb)
+    }
+
+    /// Calls a method on the object with the object as `this`, without resolving overrides.
+    pub fn call_method_by_index<T, F, C, A>(
+        &mut self,
+        obj: &TypedRef<T>,
+        idx: PoolIndex<Function>,
+        args: F,
+        cb: C,
+    ) -> RuntimeResult<A>
+    where
+        F: for<'gc> Fn(&Mutation<'gc>, &Metadata<'_>) -> Vec<Value<'gc>>,
+        C: for<'gc> Fn(Option<Value<'gc>>) -> A,
+    {
+        self.begin_call();
+        let checkpoint = self.checkpoint();
+        self.arena.mutate(|mc, root| {
+            let this = root.rooted_object(obj.handle)?;
+            root.contexts.borrow_mut(mc).push(Obj::Instance(this));
+            Ok(())
+        })?;
+        let res = self
+            .push_args(idx, &[], args)
+            .and_then(|function| self.call_with_params(idx, &function.parameters).map(|()| function));
+        let function = match res {
+            Ok(function) => {
+                self.arena.mutate(|mc, root| {
+                    root.contexts.borrow_mut(mc).pop();
+                });
+                function
+            }
+            Err(err) => {
+                self.unwind(checkpoint, &err);
+                return Err(err);
+            }
+        };
+        let returns = function.return_type.is_some();
+        Ok(self
+            .arena
+            .mutate(|mc, root| cb(returns.then(|| root.pop(mc).ok()).flatten())))
+    }
+
+    /// Starts a call that returns [`CallStatus::Pending`] once it has run for `slice`, to be continued
+    /// with [`VM::resume`]. The call is only suspended between the top-level statements of the called
+    /// function: script calls are executed on the native stack, so a statement runs to completion with
+    /// all the calls it makes and a slice is overrun by as long as the statement takes. Work meant to
+    /// be spread across slices has to be done by loops in the body of the called function.
+    /// Once the call completes, its result can be retrieved with [`VM::take_result_with_callback`].
+    pub fn call_sliced_by_statement<F>(
+        &mut self,
+        idx: PoolIndex<Function>,
+        args: F,
+        slice: Duration,
+    ) -> RuntimeResult<CallStatus>
+    where
+        F: for<'gc> Fn(&Mutation<'gc>, &Metadata<'_>) -> Vec<Value<'gc>>,
+    {
+        if self.suspended.is_some() {
+            return Err(RuntimeError::CallInProgress);
+        }
+        self.begin_call();
+        let checkpoint = self.checkpoint();
+        let entered = self
+            .push_args(idx, &[], args)
+            .and_then(|function| self.enter(idx, &function.parameters));
+        match entered {
+            Ok(Some(frame)) => {
+                self.suspended = Some((frame, checkpoint));
+                self.resume(slice)
+            }
+            Ok(None) => Ok(CallStatus::Complete),
+            Err(err) => {
+                self.unwind(checkpoint, &err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Continues a suspended call, a failed call is unwound like in [`VM::call_void`].
+    pub fn resume(&mut self, slice: Duration) -> RuntimeResult<CallStatus> {
+        if self.awaited_native.is_some() {
+            return Err(RuntimeError::AwaitingNative);
+        }
+        let Some((mut frame, checkpoint)) = self.suspended.take() else {
+            return Err(RuntimeError::NoSuspendedCall);
+        };
+        self.slice_depth = Some(self.calls.len());
+        let res = self.run_slice(&mut frame, checkpoint, slice);
+        self.slice_depth = None;
+        match res {
+            Ok(CallStatus::Pending | CallStatus::Awaiting) => self.suspended = Some((frame, checkpoint)),
+            _ => self.clear_statement_natives(),
+        }
+        res
+    }
+
+    /// Completes the native that suspended the current sliced call and continues the call like
+    /// [`VM::resume`]. The statement that invoked the native is executed again, with the natives it
+    /// already called returning their earlier results instead of being called again and this one
+    /// returning `result`, like `let data = FetchData(url) + FetchData(other);`. Script functions
+    /// would run again, so natives can't suspend statements that called any before them. The result
+    /// is `None` for natives without a return type.
+    pub fn complete_native<F>(&mut self, result: F, slice: Duration) -> RuntimeResult<CallStatus>
+    where
+        F: for<'gc> FnOnce(&Mutation<'gc>, &Metadata<'_>) -> Option<Value<'gc>>,
+    {
+        let idx = self.awaited_native.take().ok_or(RuntimeError::NoSuspendedCall)?;
+        let meta = &self.metadata;
+        self.arena.mutate(|mc, root| {
+            root.native_results.borrow_mut(mc).push(result(mc, meta));
+        });
+        self.statement_natives.push(idx);
+        self.resume(slice)
+    }
+
+    /// The native that suspended the current sliced call, if it's waiting for one.
+    #[inline]
+    pub fn awaited_native(&self) -> Option<PoolIndex<Function>> {
+        self.awaited_native
+    }
+
+    fn clear_statement_natives(&mut self) {
+        if !self.statement_natives.is_empty() {
+            self.statement_natives.clear();
+            self.arena.mutate(|mc, root| root.native_results.borrow_mut(mc).clear());
+        }
+    }
+
+    fn run_slice(
+        &mut self,
+        frame: &mut Frame<'_>,
+        checkpoint: Checkpoint,
+        slice: Duration,
+    ) -> RuntimeResult<CallStatus> {
+        let deadline = Instant::now() + slice;
+        let mut steps = 0usize;
+        loop {
+            let (restart, ip) = (self.checkpoint(), frame.ip);
+            self.replayed_natives = 0;
+            self.statement_calls = false;
+            let returns = match self.step(frame) {
+                Ok(returns) => {
+                    self.clear_statement_natives();
+                    returns
+                }
+                // the statement is executed again once the native is completed
+                Err(err) if self.awaited_native.is_some() => {
+                    self.unwind(restart, &err);
+                    frame.ip = ip;
+                    return Ok(CallStatus::Awaiting);
+                }
+                Err(err) => {
+                    self.unwind(checkpoint, &err);
+                    return Err(err);
+                }
+            };
+            if let Some(returns) = returns {
+                self.exit(returns)?;
+                return Ok(CallStatus::Complete);
+            }
+            steps += 1;
+            if steps % DEADLINE_CHECK_INTERVAL == 0 && Instant::now() >= deadline {
+                return Ok(CallStatus::Pending);
+            }
+        }
+    }
+
+    #[inline]
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.is_some()
+    }
+
+    pub fn take_result_with_callback<C, A>(&mut self, cb: C) -> A
+    where
+        C: for<'gc> Fn(Option<Value<'gc>>) -> A,
+    {
+        self.arena.mutate(|mc, root| cb(root.pop(mc).ok()))
+    }
+
+    /// Calls a function returning an array and keeps the array alive until all of its elements are read
+    /// with [`VM::next_element`] or it's released, so that it can be processed across many mutations.
+    pub fn call_iter<F>(&mut self, idx: PoolIndex<Function>, args: F) -> RuntimeResult<ArrayIter>
+    where
+        F: for<'gc> Fn(&Mutation<'gc>, &Metadata<'_>) -> Vec<Value<'gc>>,
+    {
+        self.call_void(idx, args)?;
+        self.root_popped_array()
+    }
+
+    /// Pops an array off the stack and roots it.
+    fn root_popped_array(&mut self) -> RuntimeResult<ArrayIter> {
+        let handle = self.next_array_handle;
+        let len = self.arena.mutate(|mc, root| {
+            let array = root.pop(mc).map_err(|_| RuntimeError::InvalidInteropParameters)?;
+            let len = match &*array.unpinned() {
+                Value::Array(arr) => arr.borrow().len(),
+                Value::StaticArray(arr) => arr.borrow().len(),
+                _ => return Err(RuntimeError::InvalidInteropParameters),
+            };
+            root.rooted_arrays.borrow_mut(mc).insert(handle, array);
+            Ok(len)
+        })?;
+        self.next_array_handle += 1;
+        Ok(ArrayIter { handle, index: 0, len })
+    }
+
+    /// Reads the next element of a rooted array, the array is released after the last element.
+    pub fn next_element<A>(&mut self, iter: &mut ArrayIter) -> Option<Result<A, &'static str>>
+    where
+        A: for<'gc> FromVM<'gc>,
+    {
+        if iter.index >= iter.len {
+            self.release_iter(iter);
+            return None;
+        }
+        let pool = self.metadata.pool();
+        let index = iter.index;
+        let res = self.arena.mutate(|_, root| {
+            let arrays = root.rooted_arrays.borrow();
+            let array = arrays.get(&iter.handle)?.unpinned();
+            let elem = match &*array {
+                Value::Array(arr) => arr.borrow().get(index).cloned(),
+                Value::StaticArray(arr) => arr.borrow().get(index).cloned(),
+                _ => None,
+            };
+            elem.map(|elem| A::from_vm(elem, pool))
+        });
+        iter.index += 1;
+        if res.is_none() {
+            self.release_iter(iter);
+        }
+        res
+    }
+
+    /// Releases a rooted array before all of its elements were read.
+    pub fn release_iter(&mut self, iter: &mut ArrayIter) {
+        iter.index = iter.len;
+        self.arena.mutate(|mc, root| {
+            root.rooted_arrays.borrow_mut(mc).remove(&iter.handle);
+        });
+    }
+
+    /// Creates an instance of the class `T` with its fields set to default values.
+    pub fn new_ref<T: ScriptClass>(&mut self) -> RuntimeResult<TypedRef<T>> {
+        let class = self.script_class::<T>()?;
+        let id = self.next_object_id();
+        let handle = self.next_ref_handle;
+        let meta = &mut self.metadata;
+        self.arena.mutate(|mc, root| {
+            let instance = Instance::new(class, id, meta, mc)?;
+            root.rooted_objects
+                .borrow_mut(mc)
+                .insert(handle, Gc::new(mc, RefLock::new(instance)));
+            Ok(())
+        })?;
+        self.next_ref_handle += 1;
+        self.record_alloc(1)?;
+        Ok(TypedRef::new(handle, class))
+    }
+
+    /// Calls a function returning an object and keeps the object alive as a `TypedRef`. Fails if the
+    /// function returns null or an object that isn't an instance of `T`.
+    pub fn call_ref<T, F>(&mut self, idx: PoolIndex<Function>, args: F) -> RuntimeResult<TypedRef<T>>
+    where
+        T: ScriptClass,
+        F: for<'gc> Fn(&Mutation<'gc>, &Metadata<'_>) -> Vec<Value<'gc>>,
+    {
+        let class = self.script_class::<T>()?;
+        self.call_void(idx, args)?;
+        self.root_popped_ref(class)
+    }
+
+    /// Pops an object off the stack and roots it, the object has to be an instance of `class`.
+    fn root_popped_ref<T>(&mut self, class: PoolIndex<Class>) -> RuntimeResult<TypedRef<T>> {
+        let handle = self.next_ref_handle;
+        let meta = &self.metadata;
+        self.arena.mutate(|mc, root| {
+            let invalid = |reason| RuntimeError::invalid_cast(meta.pool(), class, reason);
+            let obj = match &*root.pop(mc)?.unpinned() {
+                Value::Obj(Obj::Instance(obj)) => *obj,
+                Value::Obj(Obj::Null) => return Err(invalid("the object is null")),
+                _ => return Err(invalid("the value is not an object")),
+            };
+            if !meta.derives_from(obj.borrow().tag.to_pool(), class) {
+                return Err(invalid("the object is an instance of another class"));
+            }
+            root.rooted_objects.borrow_mut(mc).insert(handle, obj);
+            Ok(())
+        })?;
+        self.next_ref_handle += 1;
+        Ok(TypedRef::new(handle, class))
+    }
+
+    /// Calls a function with the object as its first argument, followed by `args`.
+    pub fn call_with_ref<T, F>(&mut self, idx: PoolIndex<Function>, obj: &TypedRef<T>, args: F) -> RuntimeResult<()>
+    where
+        F: for<'gc> Fn(&Mutation<'gc>, &Metadata<'_>) -> Vec<Value<'gc>>,
+    {
+        self.begin_call();
+        let checkpoint = self.checkpoint();
+        let res = self
+            .push_args(idx, &[obj.handle], args)
+            .and_then(|function| self.call_with_params(idx, &function.parameters));
+        if let Err(err) = &res {
+            self.unwind(checkpoint, err);
+        }
+        res
+    }
+
+    /// Reads a field of the object, the field can be declared by `T` or by one of its bases.
+    pub fn read_field<T, A>(&self, obj: &TypedRef<T>, name: &str) -> RuntimeResult<A>
+    where
+        A: for<'gc> FromVM<'gc>,
+    {
+        let field = self
+            .metadata
+            .get_field(obj.class, name)
+            .ok_or_else(|| RuntimeError::UndefinedField(name.to_owned()))?;
+        let pool = self.metadata.pool();
+        self.arena.mutate(|_, root| {
+            let instance = root.rooted_object(obj.handle)?;
+            let val = instance.borrow().fields.get(field).cloned().ok_or(UNKNOWN_FIELD)?;
+            A::from_vm(val, pool).map_err(RuntimeError::TypeMismatch)
+        })
+    }
+
+    pub fn write_field<T, A>(&mut self, obj: &TypedRef<T>, name: &str, val: A) -> RuntimeResult<()>
+    where
+        A: for<'gc> IntoVM<'gc>,
+    {
+        let field = self
+            .metadata
+            .get_field(obj.class, name)
+            .ok_or_else(|| RuntimeError::UndefinedField(name.to_owned()))?;
+        let meta = &self.metadata;
+        self.arena.mutate(|mc, root| {
+            let instance = root.rooted_object(obj.handle)?;
+            let mut instance = instance.borrow_mut(mc);
+            let slot = instance.fields.get_mut(field).ok_or(UNKNOWN_FIELD)?;
+            *slot = val.into_vm(mc, meta);
+            Ok(())
+        })
+    }
+
+    /// Stops keeping the object alive, it can still be collected once scripts no longer refer to it.
+    pub fn release_ref<T>(&mut self, obj: TypedRef<T>) {
+        self.arena.mutate(|mc, root| {
+            root.rooted_objects.borrow_mut(mc).remove(&obj.handle);
+        });
+    }
+
+    fn script_class<T: ScriptClass>(&self) -> RuntimeResult<PoolIndex<Class>> {
+        self.metadata
+            .get_class(T::NAME)
+            .ok_or_else(|| RuntimeError::InvalidCast {
+                target: T::NAME.to_owned(),
+                reason: "the class is not defined",
+            })
+    }
+
+    /// Resets the limits and diagnostics of a call made by the host. Calls made by natives while
+    /// another call runs keep counting towards that call.
+    fn begin_call(&mut self) {
+        if self.calls.is_empty() {
+            self.warnings.clear();
+            self.call_allocs.reset();
+            self.fuel.used = 0;
+            self.depths.reset();
+            self.deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        }
+    }
+
+    /// Pushes the arguments of a call, starting with the rooted objects of `refs`.
+    fn push_args<F>(&mut self, idx: PoolIndex<Function>, refs: &[u64], args: F) -> RuntimeResult<&'pool Function>
+    where
+        F: for<'gc> Fn(&Mutation<'gc>, &Metadata<'_>) -> Vec<Value<'gc>>,
+    {
+        let meta = &self.metadata;
+        let function = meta.pool().function(idx).map_err(|_| RuntimeError::missing(idx))?;
+        self.arena.mutate(|mc, root| {
+            let args = args(mc, meta);
+            if refs.len() + args.len() != function.parameters.len() {
+                return Err(RuntimeError::InvalidInteropParameters);
+            }
+            for handle in refs {
+                let obj = root.rooted_object(*handle)?;
+                root.push(Value::Obj(Obj::Instance(obj)), mc);
+            }
             for arg in args {
                 root.push(arg, mc);
             }
             Ok(())
         })?;
-        self.call_with_params(idx, &function.parameters)
+        self.record_alloc(function.parameters.len())?;
+        Ok(function)
     }
 
     fn call_static(&mut self, idx: PoolIndex<Function>, frame: &mut Frame<'_>) -> RuntimeResult<()> {
-        let function = self.metadata.pool().function(idx).unwrap();
+        let pool = self.metadata.pool();
+        let function = pool.function(idx).map_err(|_| RuntimeError::missing(idx))?;
         let mut indexes = Vec::with_capacity(function.parameters.len());
 
         for param_idx in &function.parameters {
-            let param = self.metadata.pool().parameter(*param_idx).unwrap();
-            if !matches!(frame.current_instr(), Some(Instr::Nop)) {
-                indexes.push(*param_idx);
+            let param = pool
+                .parameter(*param_idx)
+                .map_err(|_| RuntimeError::missing(*param_idx))?;
+            match frame.current_instr() {
+                // short-circuited arguments are skipped when the argument before them decides the result
+                Some(Instr::Skip(offset)) if param.flags.is_short_circuit() => {
+                    let location = frame.location();
+                    frame.skip(1);
+                    match self.short_circuit(idx)? {
+                        Some(decided) => {
+                            frame.jump(offset, location)?;
+                            // the deciding value stands in for the skipped one, which leaves the result as is
+                            self.arena.mutate(|mc, root| root.push(Value::Bool(decided), mc));
+                        }
+                        None => self.exec_with(frame, Access::of_param(param))?,
+                    }
+                    indexes.push(*param_idx);
+                }
+                // natives only receive the arguments that were passed
+                Some(Instr::Nop | Instr::Skip(_)) if function.flags.is_native() => {
+                    self.exec(frame)?;
+                }
+                Some(Instr::Nop | Instr::Skip(_)) => {
+                    self.exec(frame)?;
+                    let meta = &self.metadata;
+                    let typ = meta
+                        .get_type(param.type_)
+                        .ok_or_else(|| RuntimeError::missing(param.type_))?;
+                    self.arena.mutate(|mc, root| {
+                        root.push(typ.default_value(mc, meta)?, mc);
+                        Ok::<_, RuntimeError>(())
+                    })?;
+                    indexes.push(*param_idx);
+                }
+                _ => {
+                    self.exec_with(frame, Access::of_param(param))?;
+                    indexes.push(*param_idx);
+                }
             }
-            self.exec_with(frame, param.flags.is_out())?;
         }
         if matches!(frame.current_instr(), Some(Instr::ParamEnd)) {
             frame.skip(1);
@@ -563,12 +1883,101 @@ impl<'pool> VM<'pool> {
         self.call_with_params(idx, &indexes)
     }
 
+    /// The result of a logical operator that's already decided by its first operand, which is on top
+    /// of the stack when the second one is about to be evaluated.
+    fn short_circuit(&self, idx: PoolIndex<Function>) -> RuntimeResult<Option<bool>> {
+        let name = self
+            .metadata
+            .pool()
+            .def_name(idx)
+            .map_err(|_| RuntimeError::missing(idx))?;
+        let decisive = match metadata::short_name(&name) {
+            "OperatorLogicAnd" => false,
+            "OperatorLogicOr" => true,
+            _ => return Ok(None),
+        };
+        let first = self.arena.mutate(|_, root| {
+            let stack = root.stack.borrow();
+            stack.top(1).first().ok_or(RuntimeError::InvalidStack).and_then(as_bool)
+        })?;
+        Ok((first == decisive).then_some(decisive))
+    }
+
     fn call_with_params(&mut self, idx: PoolIndex<Function>, params: &[PoolIndex<Parameter>]) -> RuntimeResult<()> {
-        let function = self.metadata.pool().function(idx).unwrap();
+        let start = self.profiler.enabled.then(|| self.profiler.enter());
+        let res = self.run_call(idx, params);
+        if let Some(start) = start {
+            let native = self
+                .metadata
+                .pool()
+                .function(idx)
+                .is_ok_and(|function| function.flags.is_native());
+            self.profiler.record(idx, native, start, &self.calls);
+        }
+        res
+    }
+
+    fn run_call(&mut self, idx: PoolIndex<Function>, params: &[PoolIndex<Parameter>]) -> RuntimeResult<()> {
+        if let Some(mut frame) = self.enter(idx, params)? {
+            let returns = self.run(&mut frame)?;
+            self.exit(returns)?;
+        }
+        Ok(())
+    }
+
+    fn enter(
+        &mut self,
+        idx: PoolIndex<Function>,
+        params: &[PoolIndex<Parameter>],
+    ) -> RuntimeResult<Option<Frame<'pool>>> {
+        let function = self
+            .metadata
+            .pool()
+            .function(idx)
+            .map_err(|_| RuntimeError::missing(idx))?;
 
+        if let Some(intrinsic) = self.metadata.get_intrinsic(idx) {
+            self.arena.mutate(|mc, root| {
+                let mut stack = root.stack.borrow_mut(mc);
+                let val = stack.pop().map_err(|_| RuntimeError::InvalidInteropParameters)?;
+                stack.push(intrinsic.apply(val)?);
+                Ok(())
+            })?;
+            return Ok(None);
+        }
+        let replayable = function.flags.is_native()
+            && !self.vm_natives.contains_key(&u32::from(idx))
+            && !self.reentrant_natives.contains_key(&u32::from(idx));
+        if !replayable && self.slice_depth == Some(self.calls.len()) {
+            self.statement_calls = true;
+        }
+        if let Some(&native) = self.vm_natives.get(&u32::from(idx)) {
+            match native {
+                VMNative::Try => self.call_try(params.len())?,
+                VMNative::ProfileBegin => {
+                    let name = self.pop_string_arg(params.len())?;
+                    self.profiler.begin_region(name);
+                }
+                VMNative::ProfileEnd => {
+                    let name = self.pop_string_arg(params.len())?;
+                    self.profiler.end_region(&name);
+                }
+            }
+            return Ok(None);
+        }
+        if let Some(native) = self.reentrant_natives.get(&u32::from(idx)).cloned() {
+            self.call_reentrant(idx, &*native, params.len())?;
+            return Ok(None);
+        }
         if function.flags.is_native() {
-            self.call_native(idx)?;
-            return Ok(());
+            self.call_native(idx, params.len())?;
+            return Ok(None);
+        }
+
+        if let Some(tracer) = &self.tracer {
+            let name = self.function_name(idx);
+            let args = self.describe_stack_top(params.len());
+            tracer.record(self.calls.len(), TraceEvent::Enter { name, args });
         }
 
         let meta = &self.metadata;
@@ -577,146 +1986,552 @@ impl<'pool> VM<'pool> {
             let mut locals = IndexMap::with_capacity(function.locals.len() + params.len());
 
             for idx in params.iter().rev() {
-                let value = stack.pop().unwrap();
-                locals.put(*idx, value);
+                let value = stack.pop()?;
+                locals.put(*idx, value.copied(mc));
             }
             for idx in &function.locals {
-                let local = meta.pool().local(*idx).unwrap();
-                let typ = meta.get_type(local.type_).unwrap();
-                locals.put(*idx, typ.default_value(mc, meta));
+                let local = meta.pool().local(*idx).map_err(|_| RuntimeError::missing(*idx))?;
+                let typ = meta
+                    .get_type(local.type_)
+                    .ok_or_else(|| RuntimeError::missing(local.type_))?;
+                locals.put(*idx, typ.default_value(mc, meta)?);
             }
             root.frames.borrow_mut(mc).push(locals);
-        });
+            stack.enter_frame();
+            Ok(())
+        })?;
 
-        let sp = self.arena.mutate(|_, root| root.stack.borrow().len());
-        let offsets = self.metadata.get_code_offsets(idx).unwrap();
+        let offsets = self
+            .metadata
+            .get_code_offsets(idx)
+            .ok_or_else(|| RuntimeError::missing(idx))?;
 
-        let mut frame = Frame::new(function, offsets, sp);
-        let returns = self.run(&mut frame)?;
-        self.exit(&frame, returns);
-        Ok(())
+        self.calls.push(idx);
+        self.depths.enter(idx, self.calls.len());
+        Ok(Some(Frame::new(idx, function, offsets)))
     }
 
-    fn call_native(&mut self, idx: PoolIndex<Function>) -> RuntimeResult<()> {
-        let Some(call) = self.metadata.get_native(idx) else {
-            let name = self.metadata.pool().def_name(idx).unwrap();
-            return Err(RuntimeError::UndefinedNative(name));
+    /// Takes the only argument of a native implemented by the VM.
+    fn pop_string_arg(&mut self, arity: usize) -> RuntimeResult<String> {
+        if arity != 1 {
+            return Err(RuntimeError::NativeArgumentMismatch {
+                expected: 1,
+                actual: arity,
+            });
+        }
+        let pool = self.metadata.pool();
+        self.arena
+            .mutate(|mc, root| String::from_vm(root.pop(mc)?, pool).map_err(RuntimeError::InvalidNativeArgument))
+    }
+
+    /// Runs the function named by the argument of `Try`, the VM is unwound by `call_void` if it fails.
+    fn call_try(&mut self, arity: usize) -> RuntimeResult<()> {
+        let path = self.pop_string_arg(arity)?;
+        let checkpoint = self.checkpoint();
+
+        let res = match self.metadata.resolve_function(&path) {
+            Some(idx) => self.call_void(idx, |_, _| vec![]),
+            None => Err(RuntimeError::UndefinedMethod(path)),
         };
+        let message = match res {
+            Ok(()) => String::new(),
+            Err(err) if err.is_recoverable() => match (&self.error_handler, err.cause()) {
+                (Some(handler), _) => handler(&err, &self.metadata),
+                (None, RuntimeError::Thrown { message, .. }) => message.clone(),
+                (None, cause) => cause.to_string(),
+            },
+            Err(err) => return Err(err),
+        };
+
+        let meta = &self.metadata;
+        self.arena.mutate(|mc, root| {
+            let mut stack = root.stack.borrow_mut(mc);
+            // discards the return value of the function
+            stack.truncate(checkpoint.stack_len);
+            stack.push(message.into_vm(mc, meta));
+        });
+        Ok(())
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        self.arena.mutate(|_, root| {
+            let stack = root.stack.borrow();
+            Checkpoint {
+                calls: self.calls.len(),
+                frames: root.frames.borrow().len(),
+                contexts: root.contexts.borrow().len(),
+                stack_depth: stack.depth(),
+                stack_len: stack.len(),
+            }
+        })
+    }
+
+    /// Discards the frames, contexts and stack values created since the checkpoint. Script frames are
+    /// discarded one by one, innermost first, and reported to the tracer and the unwind handler.
+    fn unwind(&mut self, checkpoint: Checkpoint, error: &RuntimeError) {
         let pool = self.metadata.pool();
+        let floats = self.float_format;
+        let report = self.tracer.is_some() || self.unwind_handler.is_some();
+        let cause = report.then(|| error.cause().to_string()).unwrap_or_default();
+        let mut trace = error.backtrace().into_iter().flat_map(|trace| &trace.frames);
 
+        while self.calls.len() > checkpoint.calls {
+            let Some(idx) = self.calls.pop() else {
+                break;
+            };
+            self.depths.exit(idx);
+            let frame = self.arena.mutate(|mc, root| {
+                let locals = root.frames.borrow_mut(mc).pop();
+                root.stack.borrow_mut(mc).discard_frame();
+                locals
+                    .filter(|_| report)
+                    .and_then(|locals| CallerFrame::new(idx, &locals, pool, floats))
+            });
+            let offset = trace.next().filter(|loc| loc.index == idx).and_then(|loc| loc.offset);
+            let Some(frame) = frame else {
+                continue;
+            };
+            let unwound = Unwound {
+                frame,
+                depth: self.calls.len(),
+                offset,
+                error: cause.clone(),
+            };
+            if let Some(tracer) = &self.tracer {
+                let event = TraceEvent::Unwind {
+                    name: self.function_name(idx),
+                    error: cause.clone(),
+                };
+                tracer.record(unwound.depth, event);
+            }
+            if let Some(handler) = &mut self.unwind_handler {
+                handler(&unwound);
+            }
+        }
         self.arena.mutate(|mc, root| {
-            if let Some(res) = call(mc, root, pool) {
-                root.push(res, mc);
+            root.frames.borrow_mut(mc).truncate(checkpoint.frames);
+            root.contexts.borrow_mut(mc).truncate(checkpoint.contexts);
+            root.stack
+                .borrow_mut(mc)
+                .unwind(checkpoint.stack_depth, checkpoint.stack_len);
+        });
+    }
+
+    /// Resolves the label of a switch over a `CName` with a lookup by the name index, the labels are
+    /// collected once per switch. Returns `None` if the switch has to be evaluated label by label.
+    fn name_switch_target(&mut self, frame: &Frame<'_>, location: Option<Location>) -> Option<Location> {
+        let location = location?;
+        let name = self.arena.mutate(
+            |_, root| match root.stack.borrow().last().map(|val| val.unpinned().clone()) {
+                Some(Value::InternStr(StringType::Name, idx)) => Some(idx),
+                _ => None,
+            },
+        )?;
+        let table = self
+            .name_switches
+            .entry((frame.idx.into(), location.value))
+            .or_insert_with(|| frame.name_switch());
+        Some(table.as_ref()?.target(name))
+    }
+
+    fn check_interrupts(&self) -> RuntimeResult<()> {
+        if self.cancellation.as_ref().is_some_and(CancellationToken::is_cancelled) {
+            return Err(RuntimeError::Cancelled);
+        }
+        match (self.timeout, self.deadline) {
+            (Some(timeout), Some(deadline)) if Instant::now() >= deadline => Err(RuntimeError::TimedOut(timeout)),
+            _ => Ok(()),
+        }
+    }
+
+    fn trace_error(&self, err: RuntimeError, frame: &Frame<'_>, location: Option<Location>) -> RuntimeError {
+        err.with_frame(BacktraceFrame {
+            index: frame.idx,
+            function: self.function_name(frame.idx),
+            offset: location.map(|loc| loc.value),
+        })
+    }
+
+    fn warn(&mut self, kind: WarningKind, frame: &Frame<'_>, location: Option<Location>) {
+        let warning = Warning {
+            kind,
+            location: BacktraceFrame {
+                index: frame.idx,
+                function: self.function_name(frame.idx),
+                offset: location.map(|loc| loc.value),
+            },
+        };
+        log::warn!("{warning}");
+        self.warnings.push(warning);
+    }
+
+    fn throw_location(&self, location: Option<Location>) -> Option<ThrowLocation> {
+        let function = self.metadata.pool().def_name(*self.calls.last()?).ok()?;
+        Some(ThrowLocation {
+            function: function.to_string(),
+            offset: location?.value,
+        })
+    }
+
+    fn breakpoint(&mut self, location: Option<Location>) {
+        if self.breakpoint_handler.is_some() {
+            let pool = self.metadata.pool();
+            let floats = self.float_format;
+            let function = self.calls.last().copied();
+            let hit = self.arena.mutate(|_, root| {
+                let frames = root.frames.borrow();
+                let frame = CallerFrame::new(function?, frames.last()?, pool, floats)?;
+                let stack = root.stack.borrow();
+                let stack = stack
+                    .region()
+                    .iter()
+                    .map(|val| val.to_string_with(pool, floats))
+                    .collect();
+                Some(Breakpoint {
+                    frame,
+                    offset: location.map(|loc| loc.value),
+                    stack,
+                })
+            });
+            if let (Some(handler), Some(hit)) = (&mut self.breakpoint_handler, hit) {
+                handler(&hit);
             }
+        }
+        if let Some(debugger) = &mut self.debugger {
+            debugger.mode = StepMode::StepInto;
+        }
+    }
+
+    fn debug_step(&mut self, function: PoolIndex<Function>, location: Option<Location>) {
+        let depth = self.calls.len();
+        let meta = &self.metadata;
+        if !matches!(&self.debugger, Some(debugger) if debugger.should_pause(function, location, depth, meta)) {
+            return;
+        }
+        let Some(mut debugger) = self.debugger.take() else {
+            return;
+        };
+        let mut paused = Paused {
+            vm: self,
+            function,
+            location,
+        };
+        debugger.mode = (debugger.handler)(&mut paused);
+        debugger.depth = depth;
+        if self.debugger.is_none() {
+            self.debugger = Some(debugger);
+        }
+    }
+
+    fn call_native(&mut self, idx: PoolIndex<Function>, arity: usize) -> RuntimeResult<()> {
+        let sliced = self.slice_depth == Some(self.calls.len());
+        if let Some(&called) = self.statement_natives.get(self.replayed_natives).filter(|_| sliced) {
+            if called != idx {
+                return Err(RuntimeError::Unsupported(
+                    "resuming a statement that calls other natives than before it was suspended",
+                ));
+            }
+            let pos = self.replayed_natives;
+            self.replayed_natives += 1;
+            return self.arena.mutate(|mc, root| {
+                for _ in 0..arity {
+                    root.pop(mc)?;
+                }
+                if let Some(val) = root.native_results.borrow().get(pos).cloned().flatten() {
+                    root.push(val, mc);
+                }
+                Ok(())
+            });
+        }
+        if self.debugger.is_some() {
+            self.debug_step(idx, None);
+        }
+
+        let call = self.metadata.get_native(idx);
+        if call.is_none() && self.interceptors.is_empty() {
+            let name = self
+                .metadata
+                .pool()
+                .def_name(idx)
+                .map_err(|_| RuntimeError::missing(idx))?;
+            return Err(RuntimeError::UndefinedNative(name));
+        }
+        let pool = self.metadata.pool();
+        // the arguments are only rendered once, for whichever of the observers are installed
+        let observed = !self.interceptors.is_empty() || self.recorder.is_some() || self.tracer.is_some();
+        let invocation = observed.then(|| NativeCall {
+            function: idx,
+            name: self.function_name(idx),
+            args: self.describe_stack_top(arity),
         });
+
+        if let (Some(recorder), Some(invocation)) = (&self.recorder, &invocation) {
+            recorder.record(invocation.clone());
+        }
+        if let (Some(tracer), Some(invocation)) = (&self.tracer, &invocation) {
+            let name = invocation.name.clone();
+            let args = invocation.args.clone();
+            tracer.record(self.calls.len(), TraceEvent::Enter { name, args });
+        }
+
+        let interceptors = &mut self.interceptors;
+        let user_data = &mut self.user_data;
+        let catch_panics = self.catch_native_panics;
+        let meta = &self.metadata;
+        let allocated_before = self.arena.metrics().total_allocation();
+        let suspended = self.arena.mutate(|mc, root| {
+            let caller = self.calls.last().copied();
+            let mut ctx = NativeCtx::new(mc, root, meta, self.float_format, user_data, idx, caller, arity);
+            let mut handled = false;
+            if let Some(invocation) = &invocation {
+                for interceptor in interceptors.iter_mut() {
+                    if interceptor.before(invocation, &mut ctx)? == Intercept::Handled {
+                        handled = true;
+                        break;
+                    }
+                }
+            }
+            if !handled {
+                match call {
+                    Some(call) if catch_panics => {
+                        std::panic::catch_unwind(AssertUnwindSafe(|| call(&mut ctx))).map_err(|payload| {
+                            let name = pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default();
+                            RuntimeError::NativePanicked(name, panic_message(&*payload))
+                        })??;
+                    }
+                    Some(call) => call(&mut ctx)?,
+                    None => {
+                        let name = pool.def_name(idx).map_err(|_| RuntimeError::missing(idx))?;
+                        return Err(RuntimeError::UndefinedNative(name));
+                    }
+                }
+            }
+            let suspended = ctx.is_suspended();
+            ctx.finish().map(|()| suspended)
+        })?;
+        if suspended {
+            if self.slice_depth != Some(self.calls.len()) {
+                return Err(RuntimeError::Unsupported(
+                    "suspending a call from a native not invoked by the function of a sliced call",
+                ));
+            }
+            if self.statement_calls {
+                return Err(RuntimeError::Unsupported(
+                    "suspending a statement that called script functions, which would run again once it's resumed",
+                ));
+            }
+            self.awaited_native = Some(idx);
+            return Err(RuntimeError::AwaitingNative);
+        }
+        if sliced {
+            let returns = pool.function(idx).is_ok_and(|function| function.return_type.is_some());
+            self.arena.mutate(|mc, root| {
+                let result = returns.then(|| root.stack.borrow().last().cloned()).flatten();
+                root.native_results.borrow_mut(mc).push(result);
+            });
+            self.statement_natives.push(idx);
+            self.replayed_natives += 1;
+        }
+        // natives allocate through the mutation handle, so they're only accounted for when they did
+        if self.arena.metrics().total_allocation() > allocated_before {
+            self.record_alloc(1)?;
+        }
+
+        if let Some(invocation) = &invocation {
+            for interceptor in &mut self.interceptors {
+                interceptor.after(invocation);
+            }
+        }
+
+        if let Some(tracer) = &self.tracer {
+            let function = pool.function(idx).map_err(|_| RuntimeError::missing(idx))?;
+            self.trace_exit(tracer, idx, self.calls.len(), function.return_type.is_some());
+        }
         Ok(())
     }
 
-    fn exit(&mut self, frame: &Frame<'_>, returns: bool) {
-        self.arena.mutate(|mc, root| {
+    fn call_reentrant(
+        &mut self,
+        idx: PoolIndex<Function>,
+        native: &ReentrantFunction<'pool>,
+        arity: usize,
+    ) -> RuntimeResult<()> {
+        let catch_panics = self.catch_native_panics;
+        let mut ctx = ReentrantCtx::new(self, idx, arity);
+        if catch_panics {
+            std::panic::catch_unwind(AssertUnwindSafe(|| native(&mut ctx))).map_err(|payload| {
+                let name = ctx.vm.function_name(idx);
+                RuntimeError::NativePanicked(name, panic_message(&*payload))
+            })??;
+        } else {
+            native(&mut ctx)?;
+        }
+        ctx.finish()
+    }
+
+    fn trace_exit(&self, tracer: &CallTracer, idx: PoolIndex<Function>, depth: usize, returns: bool) {
+        let name = self.function_name(idx);
+        let result = returns.then(|| self.describe_stack_top(1).concat());
+        tracer.record(depth, TraceEvent::Exit { name, result });
+    }
+
+    fn function_name(&self, idx: PoolIndex<Function>) -> String {
+        let name = self.metadata.pool().def_name(idx);
+        name.map(|str| str.to_string()).unwrap_or_default()
+    }
+
+    fn describe_stack_top(&self, count: usize) -> Vec<String> {
+        let pool = self.metadata.pool();
+        self.arena.mutate(|_, root| {
+            let stack = root.stack.borrow();
+            stack.top(count).iter().map(|val| val.to_string(pool)).collect()
+        })
+    }
+
+    fn exit(&mut self, returns: bool) -> RuntimeResult<()> {
+        if let (Some(tracer), Some(&idx)) = (&self.tracer, self.calls.last()) {
+            self.trace_exit(tracer, idx, self.calls.len() - 1, returns);
+        }
+        let res = self.arena.mutate(|mc, root| {
+            let mut frames = root.frames.borrow_mut(mc);
             let mut stack = root.stack.borrow_mut(mc);
-            if returns {
-                let val = stack.pop().unwrap();
-                stack.resize(frame.sp, Value::Obj(Obj::Null));
-                stack.push(val);
-            } else {
-                stack.resize(frame.sp, Value::Obj(Obj::Null));
-            }
-            root.frames.borrow_mut(mc).pop();
+            frames.pop();
+            let res = stack.exit_frame(returns);
+            debug_assert_eq!(frames.len(), stack.depth(), "frames and stack regions are out of sync");
+            res
         });
+        if let Some(idx) = self.calls.pop() {
+            self.depths.exit(idx);
+        }
+        res
+    }
+
+    /// Instances are numbered from 1 in the order they're allocated.
+    fn next_object_id(&mut self) -> u64 {
+        self.last_object_id += 1;
+        self.last_object_id
     }
 
-    fn check_gc(&mut self) {
-        if self.arena.metrics().allocation_debt() >= 64000. {
-            log::debug!("GC incremental step, debt: {}", self.arena.metrics().allocation_debt());
-            self.arena.collect_debt();
+    fn record_alloc(&mut self, count: usize) -> RuntimeResult<()> {
+        let total = self.arena.metrics().total_allocation();
+        let bytes = total.saturating_sub(self.call_allocs.last_total);
+        self.call_allocs.last_total = total;
+
+        self.alloc_stats.allocations += count;
+        self.alloc_stats.total_allocations += count;
+        self.alloc_stats.bytes += bytes;
+        self.alloc_stats.debt = self.arena.metrics().allocation_debt();
+        if let Some(&function) = self.calls.last() {
+            self.call_allocs.record(function, bytes)?;
+        }
+
+        match self.gc_policy.on_allocation(&self.alloc_stats) {
+            GcAction::Skip => {}
+            GcAction::CollectDebt => {
+                log::debug!("GC incremental step, debt: {}", self.alloc_stats.debt);
+                self.collect_debt();
+            }
+            GcAction::CollectAll => {
+                log::debug!("GC full collection, allocations: {}", self.alloc_stats.allocations);
+                self.collect_garbage();
+            }
         }
+        self.call_allocs.last_total = self.arena.metrics().total_allocation();
+        Ok(())
     }
 
     fn assignment(&mut self, frame: &mut Frame<'_>) -> RuntimeResult<()> {
-        match frame.next_instr().unwrap() {
+        match frame.next_instr().ok_or(INCOMPLETE_ASSIGNMENT)? {
             Instr::Local(idx) => {
                 self.exec(frame)?;
-                self.with_local(idx, |local, mc, root| match local {
-                    Value::Pinned(inner) => *inner.borrow_mut(mc) = root.pop(mc).unwrap(),
-                    val => *val = root.pop(mc).unwrap(),
-                });
+                self.with_local(idx, |local, mc, root| {
+                    match local {
+                        Value::Pinned(inner) => *inner.borrow_mut(mc) = root.pop(mc)?.copied(mc),
+                        val => *val = root.pop(mc)?.copied(mc),
+                    };
+                    Ok(())
+                })?;
             }
             Instr::Param(idx) => {
                 self.exec(frame)?;
-                self.with_local(idx, |local, mc, root| match local {
-                    Value::Pinned(inner) => *inner.borrow_mut(mc) = root.pop(mc).unwrap(),
-                    val => *val = root.pop(mc).unwrap(),
-                });
+                self.with_local(idx, |local, mc, root| {
+                    match local {
+                        Value::Pinned(inner) => *inner.borrow_mut(mc) = root.pop(mc)?.copied(mc),
+                        val => *val = root.pop(mc)?.copied(mc),
+                    };
+                    Ok(())
+                })?;
             }
             Instr::ObjectField(idx) => {
                 self.exec(frame)?;
 
+                let pool = self.metadata.pool();
                 self.arena.mutate(|mc, root| {
                     let instance = root.contexts.borrow_mut(mc);
                     let mut instance = instance
                         .last()
                         .and_then(Obj::as_instance)
-                        .ok_or(RuntimeError::NullPointer)?
+                        .ok_or_else(|| RuntimeError::null_field(pool, idx))?
                         .borrow_mut(mc);
-                    let field = instance.fields.get_mut(idx).unwrap();
-                    let value = root.pop(mc).unwrap();
-                    *field = value;
+                    let field = instance.fields.get_mut(idx).ok_or(UNKNOWN_FIELD)?;
+                    let value = root.pop(mc)?;
+                    *field = value.copied(mc);
                     Ok(())
                 })?;
             }
             Instr::StructField(idx) => {
-                self.exec(frame)?;
+                self.exec_with(frame, Access::Write)?;
                 self.exec(frame)?;
 
                 self.arena.mutate(|mc, root| {
-                    let val = root.pop(mc).unwrap();
-                    let str = root.pop(mc).unwrap();
+                    let val = root.pop(mc)?.copied(mc);
+                    let str = root.pop(mc)?;
                     match &*str.unpinned() {
                         Value::BoxedStruct(str) => str.borrow_mut(mc).put(idx, val),
-                        Value::PackedStruct(_) => todo!(),
-                        _ => panic!("invalid bytecode"),
+                        Value::PackedStruct(_) => {
+                            return Err(RuntimeError::Unsupported("assigning fields of packed structs"))
+                        }
+                        _ => return Err(RuntimeError::TypeMismatch("expected a struct")),
                     };
-                });
+                    Ok(())
+                })?;
             }
-            Instr::ArrayElement(_) => {
-                self.exec(frame)?;
+            Instr::ArrayElement(_) | Instr::StaticArrayElement(_) => {
+                self.exec_with(frame, Access::Write)?;
                 self.exec(frame)?;
                 self.exec(frame)?;
 
                 self.arena.mutate(|mc, root| {
-                    let val = root.pop(mc).unwrap();
-                    let idx = root.pop(mc).unwrap();
-                    let idx = idx
-                        .as_i32()
-                        .copied()
-                        .map(|i| i as u64)
-                        .or_else(|| idx.as_u64().copied())
-                        .unwrap();
-                    let array = root.pop(mc).unwrap();
-                    let array = array.unpinned();
-                    let array = array.as_array().unwrap();
-                    array.borrow_mut(mc)[idx as usize] = val;
-                });
+                    let val = root.pop(mc)?;
+                    let idx = array::index(&root.pop(mc)?)?;
+                    let array = root.pop(mc)?;
+                    match &*array.unpinned() {
+                        Value::Array(arr) => *array::slot(&mut arr.borrow_mut(mc), idx)? = val.copied(mc),
+                        Value::StaticArray(arr) => *array::slot(&mut arr.borrow_mut(mc), idx)? = val.copied(mc),
+                        _ => return Err(RuntimeError::TypeMismatch("expected an array")),
+                    }
+                    Ok(())
+                })?;
             }
             Instr::Context(_) => {
                 self.exec(frame)?;
 
-                match frame.next_instr().unwrap() {
+                match frame.next_instr().ok_or(INCOMPLETE_ASSIGNMENT)? {
                     Instr::ObjectField(idx) => {
                         self.exec(frame)?;
 
+                        let pool = self.metadata.pool();
                         self.arena.mutate(|mc, root| {
-                            let val = root.pop(mc).unwrap();
-                            let obj = root.pop(mc).unwrap();
+                            let val = root.pop(mc)?;
+                            let obj = root.pop(mc)?;
                             let mut instance = obj
                                 .as_obj()
-                                .unwrap()
+                                .ok_or(RuntimeError::TypeMismatch("expected an object"))?
                                 .as_instance()
-                                .ok_or(RuntimeError::NullPointer)?
+                                .ok_or_else(|| RuntimeError::null_field(pool, idx))?
                                 .borrow_mut(mc);
-                            let field = instance.fields.get_mut(idx).unwrap();
-                            *field = val;
+                            let field = instance.fields.get_mut(idx).ok_or(UNKNOWN_FIELD)?;
+                            *field = val.copied(mc);
                             Ok(())
                         })?;
                     }
@@ -728,40 +2543,102 @@ impl<'pool> VM<'pool> {
         Ok(())
     }
 
-    fn with_local<F, A>(&mut self, idx: PoolIndex<A>, f: F)
+    fn with_local<F, A>(&mut self, idx: PoolIndex<A>, f: F) -> RuntimeResult<()>
     where
-        F: for<'gc> FnOnce(&mut Value<'gc>, &Mutation<'gc>, &VMRoot<'gc>),
+        F: for<'gc> FnOnce(&mut Value<'gc>, &Mutation<'gc>, &VMRoot<'gc>) -> RuntimeResult<()>,
     {
         self.arena.mutate(|mc, root| {
-            let mut local = root.frames.borrow_mut(mc);
-            let local = local.last_mut().unwrap().get_mut(idx).unwrap();
-            f(local, mc, root);
-        });
+            let mut frames = root.frames.borrow_mut(mc);
+            let local =
+                frames
+                    .last_mut()
+                    .and_then(|locals| locals.get_mut(idx))
+                    .ok_or(RuntimeError::InvalidBytecode(
+                        "local is not defined in the current frame",
+                    ))?;
+            f(local, mc, root)
+        })
+    }
+}
+
+const UNKNOWN_FIELD: RuntimeError = RuntimeError::InvalidBytecode("field is not defined on the instance");
+const INCOMPLETE_ASSIGNMENT: RuntimeError = RuntimeError::InvalidBytecode("assignment without a target");
+
+#[inline]
+fn as_bool(val: &Value<'_>) -> RuntimeResult<bool> {
+    let val = val.unpinned();
+    val.as_bool()
+        .copied()
+        .ok_or(RuntimeError::TypeMismatch("expected a Bool"))
+}
+
+/// Extracts the message of a panic, panics raised with `panic!` carry either a `&str` or a `String`.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(str), _) => (*str).to_owned(),
+        (_, Some(str)) => str.clone(),
+        _ => "unknown panic".to_owned(),
+    }
+}
+
+/// A cursor over an array rooted in the VM, created by [`VM::call_iter`].
+#[derive(Debug)]
+pub struct ArrayIter {
+    handle: u64,
+    index: usize,
+    len: usize,
+}
+
+impl ArrayIter {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.len - self.index
     }
 }
 
 #[derive(Debug)]
 pub struct Frame<'pool> {
+    idx: PoolIndex<Function>,
     function: &'pool Function,
     offsets: Rc<[u16]>,
     ip: usize,
-    sp: usize,
 }
 
 impl<'pool> Frame<'pool> {
-    fn new(function: &'pool Function, offsets: Rc<[u16]>, sp: usize) -> Self {
+    fn new(idx: PoolIndex<Function>, function: &'pool Function, offsets: Rc<[u16]>) -> Self {
         Self {
+            idx,
             function,
             offsets,
             ip: 0,
-            sp,
         }
     }
 
     #[inline]
-    fn seek(&mut self, location: Location) {
-        let index = self.offsets.binary_search(&location.value).unwrap();
+    fn seek(&mut self, location: Location) -> RuntimeResult<()> {
+        let index = self
+            .offsets
+            .binary_search(&location.value)
+            .map_err(|_| RuntimeError::InvalidBytecode("jump to an offset between instructions"))?;
         self.ip = index;
+        Ok(())
+    }
+
+    /// Jumps to an offset relative to the instruction at `from`.
+    #[inline]
+    fn jump(&mut self, offset: Offset, from: Option<Location>) -> RuntimeResult<()> {
+        let from = from.ok_or(RuntimeError::InvalidBytecode("jump outside of the function body"))?;
+        self.seek(offset.absolute(from))
     }
 
     #[inline]
@@ -785,6 +2662,90 @@ impl<'pool> Frame<'pool> {
         self.ip += 1;
         instr
     }
+
+    /// Collects the labels of the switch starting at the current instruction, if all of them are
+    /// name constants.
+    fn name_switch(&self) -> Option<NameSwitch> {
+        let code = self.function.code.as_ref();
+        let mut targets = HashMap::new();
+        let mut ip = self.ip;
+        let mut default = None;
+        while let Some(Instr::SwitchLabel(next, body)) = code.get(ip) {
+            let Some(Instr::NameConst(name)) = code.get(ip + 1) else {
+                return None;
+            };
+            let label = Location::new(*self.offsets.get(ip)?);
+            // the first matching label wins, like with the sequential comparisons
+            targets.entry(VMIndex::from(*name)).or_insert(body.absolute(label));
+            let next = next.absolute(label);
+            default = Some(next);
+            ip = self.offsets.binary_search(&next.value).ok()?;
+        }
+        Some(NameSwitch {
+            targets,
+            default: default?,
+        })
+    }
+}
+
+/// The depths of the VM stacks before a call.
+#[derive(Debug, Clone, Copy)]
+struct Checkpoint {
+    calls: usize,
+    frames: usize,
+    contexts: usize,
+    stack_depth: usize,
+    stack_len: usize,
+}
+
+/// Natives implemented by the VM itself, because they need access to its state.
+#[derive(Debug, Clone, Copy)]
+enum VMNative {
+    Try,
+    ProfileBegin,
+    ProfileEnd,
+}
+
+/// Locations of the bodies of a switch over names, by the name index.
+#[derive(Debug)]
+struct NameSwitch {
+    targets: HashMap<VMIndex, Location>,
+    default: Location,
+}
+
+impl NameSwitch {
+    #[inline]
+    fn target(&self, name: VMIndex) -> Location {
+        self.targets.get(&name).copied().unwrap_or(self.default)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Fuel {
+    limit: Option<u64>,
+    used: u64,
+}
+
+impl Fuel {
+    #[inline]
+    fn consume(&mut self) -> RuntimeResult<()> {
+        self.used += 1;
+        match self.limit {
+            Some(limit) if self.used > limit => Err(RuntimeError::OutOfFuel { limit }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallStatus {
+    Complete,
+    /// The time slice of the call ran out after one of the top-level statements of its function, it
+    /// continues with [`VM::resume`].
+    Pending,
+    /// A native suspended the call, it continues once the native is completed with
+    /// [`VM::complete_native`].
+    Awaiting,
 }
 
 enum Action {
@@ -793,17 +2754,82 @@ enum Action {
     Return,
 }
 
+/// How the value read from a place, like a local or a field, is going to be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    /// A field of the struct held by the place is assigned, so the struct is unshared first.
+    Write,
+    /// The place is passed to an out parameter, so it's replaced with a reference to its value.
+    Pin,
+}
+
+impl Access {
+    #[inline]
+    fn of_param(param: &Parameter) -> Self {
+        if param.flags.is_out() {
+            Access::Pin
+        } else {
+            Access::Read
+        }
+    }
+
+    /// The access to the value enclosing the place, like the struct of a field. The enclosing value
+    /// is written to whenever the place is.
+    #[inline]
+    fn enclosing(self) -> Self {
+        match self {
+            Access::Read => Access::Read,
+            Access::Write | Access::Pin => Access::Write,
+        }
+    }
+
+    #[inline]
+    fn prepare<'gc>(self, val: &mut Value<'gc>, mc: &Mutation<'gc>) {
+        match self {
+            Access::Read => {}
+            Access::Write => val.unshare(mc),
+            Access::Pin => val.pin(mc),
+        }
+    }
+}
+
+/// How [`VM::evaluate_rendered`] formats the value of an expression.
+#[derive(Debug, Clone, Copy)]
+enum Rendering {
+    Display,
+    Dump,
+    Graph,
+}
+
+impl Rendering {
+    fn render(self, val: &Value<'_>, pool: &ConstantPool, floats: FloatFormat) -> String {
+        match self {
+            Rendering::Display => val.to_string_with(pool, floats),
+            Rendering::Dump => val.dump_with(pool, floats),
+            Rendering::Graph => graph::value_graph(val, pool, floats),
+        }
+    }
+}
+
 #[derive(Collect)]
 #[collect(no_drop)]
 pub struct VMRoot<'gc> {
     frames: GcRefLock<'gc, Vec<IndexMap<Value<'gc>>>>,
-    stack: GcRefLock<'gc, Vec<Value<'gc>>>,
+    stack: GcRefLock<'gc, OperandStack<'gc>>,
     contexts: GcRefLock<'gc, Vec<Obj<'gc>>>,
+    /// Held weakly so that strings no longer referenced can be collected, see [`VM::collect_garbage`].
+    interned: GcRefLock<'gc, HashMap<Box<str>, GcWeak<'gc, Box<str>>>>,
+    maps: GcRefLock<'gc, ScriptMaps<'gc>>,
+    rooted_arrays: GcRefLock<'gc, HashMap<u64, Value<'gc>>>,
+    rooted_objects: GcRefLock<'gc, HashMap<u64, GcRefLock<'gc, Instance<'gc>>>>,
+    /// The results of `VM::statement_natives`, `None` for natives without a return type.
+    native_results: GcRefLock<'gc, Vec<Option<Value<'gc>>>>,
 }
 
 impl<'gc> VMRoot<'gc> {
     #[inline]
-    fn pop(&self, mc: &Mutation<'gc>) -> Option<Value<'gc>> {
+    fn pop(&self, mc: &Mutation<'gc>) -> RuntimeResult<Value<'gc>> {
         self.stack.borrow_mut(mc).pop()
     }
 
@@ -813,36 +2839,55 @@ impl<'gc> VMRoot<'gc> {
     }
 
     #[inline]
-    fn copy(&self, idx: usize, mc: &Mutation<'gc>) {
+    fn copy(&self, idx: usize, mc: &Mutation<'gc>) -> RuntimeResult<()> {
         let mut stack = self.stack.borrow_mut(mc);
-        let val = stack[idx].clone();
+        let val = stack.get(idx).cloned().ok_or(RuntimeError::InvalidStack)?;
         stack.push(val);
+        Ok(())
     }
 
     #[inline]
-    fn unop<F>(&self, fun: F, mc: &Mutation<'gc>)
+    fn unop<F>(&self, fun: F, mc: &Mutation<'gc>) -> RuntimeResult<()>
     where
-        F: FnOnce(Value<'gc>, &Mutation<'gc>) -> Value<'gc>,
+        F: FnOnce(Value<'gc>, &Mutation<'gc>) -> RuntimeResult<Value<'gc>>,
     {
         let mut stack = self.stack.borrow_mut(mc);
-        let val = stack.pop().unwrap();
-        stack.push(fun(val, mc));
+        let val = stack.pop()?;
+        stack.push(fun(val, mc)?);
+        Ok(())
     }
 
     #[inline]
-    fn binop<F>(&self, fun: F, mc: &Mutation<'gc>)
+    fn binop<F>(&self, fun: F, mc: &Mutation<'gc>) -> RuntimeResult<()>
     where
-        F: FnOnce(Value<'gc>, Value<'gc>, &Mutation<'gc>) -> Value<'gc>,
+        F: FnOnce(Value<'gc>, Value<'gc>, &Mutation<'gc>) -> RuntimeResult<Value<'gc>>,
     {
         let mut stack = self.stack.borrow_mut(mc);
-        let rhs = stack.pop().unwrap();
-        let lhs = stack.pop().unwrap();
-        stack.push(fun(lhs, rhs, mc));
+        let rhs = stack.pop()?;
+        let lhs = stack.pop()?;
+        stack.push(fun(lhs, rhs, mc)?);
+        Ok(())
+    }
+
+    fn rooted_object(&self, handle: u64) -> RuntimeResult<GcRefLock<'gc, Instance<'gc>>> {
+        self.rooted_objects
+            .borrow()
+            .get(&handle)
+            .copied()
+            .ok_or(RuntimeError::InvalidInteropParameters)
+    }
+
+    fn intern(&self, str: &str, mc: &Mutation<'gc>) -> Gc<'gc, Box<str>> {
+        if let Some(interned) = self.interned.borrow().get(str).and_then(|weak| weak.upgrade(mc)) {
+            return interned;
+        }
+        let interned = Gc::new(mc, Box::from(str));
+        self.interned.borrow_mut(mc).insert(str.into(), Gc::downgrade(interned));
+        interned
     }
 
     #[inline]
     fn adjust_stack(&self, size: usize, mc: &Mutation<'gc>) {
-        let mut stack = self.stack.borrow_mut(mc);
-        stack.resize(size, Value::Obj(Obj::Null));
+        self.stack.borrow_mut(mc).truncate(size);
     }
 }
@@ -1,14 +1,17 @@
+use std::any::{self, Any};
 use std::collections::HashMap;
 use std::rc::Rc;
 
 use gc_arena::lock::RefLock;
-use gc_arena::{Gc, Mutation};
+use gc_arena::{Collect, Gc, Mutation};
 use redscript::bundle::{ConstantPool, PoolIndex};
-use redscript::definition::{AnyDefinition, Class, Enum, Function, Type};
+use redscript::definition::{AnyDefinition, Class, Definition, Enum, Field, Function, Parameter, Type};
 use redscript::Ref;
 
+use crate::error::{RuntimeError, RuntimeResult};
 use crate::index_map::IndexMap;
-use crate::interop::{IntoVMFunction, VMFunction};
+use crate::interop::{IntoVMFunction, NativeCtx, VMFunction};
+use crate::intrinsic::Intrinsic;
 use crate::value::{Obj, StringType, VMIndex, Value};
 
 pub struct Metadata<'pool> {
@@ -17,6 +20,10 @@ pub struct Metadata<'pool> {
     types: IndexMap<TypeId>,
     function_meta: IndexMap<FunctionMetadata>,
     class_meta: IndexMap<ClassMetadata>,
+    vtable_checks: VTableChecks,
+    vtable_warnings: Vec<VTableWarning>,
+    empty: EmptyStrings,
+    constants: Constants,
 }
 
 impl<'pool> Metadata<'pool> {
@@ -28,12 +35,25 @@ impl<'pool> Metadata<'pool> {
 
         for (idx, def) in pool.definitions() {
             match def.value {
+                // types that don't resolve are left out and reported as missing where they're used
                 AnyDefinition::Type(_) => {
-                    let id = TypeId::from(idx.cast(), pool, &symbols).expect("should resolve types");
-                    types.put(idx, id);
+                    if let Some(id) = TypeId::from(idx.cast(), pool, &symbols) {
+                        types.put(idx, id);
+                    }
                 }
-                AnyDefinition::Function(_) => {
-                    function_meta.put(idx, FunctionMetadata::default());
+                AnyDefinition::Function(ref fun) => {
+                    // scripts can declare functions with the same names, their bodies are kept
+                    let intrinsic = pool
+                        .names
+                        .get(def.name)
+                        .ok()
+                        .filter(|_| fun.flags.is_native())
+                        .and_then(|name| Intrinsic::from_name(&name));
+                    let meta = FunctionMetadata {
+                        intrinsic,
+                        ..Default::default()
+                    };
+                    function_meta.put(idx, meta);
                 }
                 AnyDefinition::Class(ref class) => {
                     if !class.flags.is_struct() {
@@ -50,6 +70,10 @@ impl<'pool> Metadata<'pool> {
             types,
             function_meta,
             class_meta,
+            vtable_checks: VTableChecks::default(),
+            vtable_warnings: vec![],
+            empty: EmptyStrings::new(pool),
+            constants: Constants::new(pool),
         }
     }
 
@@ -58,6 +82,27 @@ impl<'pool> Metadata<'pool> {
         self.pool
     }
 
+    /// The constants used as the default values of the string types.
+    #[inline]
+    pub fn empty_strings(&self) -> EmptyStrings {
+        self.empty
+    }
+
+    /// Finds the constant of a name, TweakDB ID or resource table by its contents. Empty strings
+    /// resolve to the empty constant of [`EmptyStrings`] even when the pool doesn't define one.
+    pub fn find_constant(&self, typ: StringType, str: &str) -> Option<VMIndex> {
+        let (table, empty) = match typ {
+            StringType::String => return None,
+            StringType::Name => (&self.constants.names, self.empty.name),
+            StringType::TweakDbId => (&self.constants.tweakdb_ids, self.empty.tweakdb_id),
+            StringType::Resource => (&self.constants.resources, self.empty.resource),
+        };
+        if str.is_empty() {
+            return Some(empty);
+        }
+        table.get(str).copied()
+    }
+
     #[inline]
     pub fn get_type(&self, idx: PoolIndex<Type>) -> Option<&TypeId> {
         self.types.get(idx)
@@ -68,16 +113,67 @@ impl<'pool> Metadata<'pool> {
         self.symbols.classes.get(name).copied()
     }
 
+    /// Marks a class, usually declared as a `native class`, as backed by host objects of type `T`
+    /// passed by natives as [`Host`](crate::value::Host) values. Scripts can't construct instances
+    /// of the class, and casts to it only succeed for host objects of type `T`.
+    pub fn register_host_class<T: Any>(&mut self, name: &str) -> Option<()> {
+        let idx = self.get_class(name)?;
+        self.class_meta.get_mut(idx)?.host_type = Some(any::TypeId::of::<T>());
+        Some(())
+    }
+
+    /// The type of the host objects backing a class registered with [`Metadata::register_host_class`].
+    pub fn host_type(&self, class: PoolIndex<Class>) -> Option<any::TypeId> {
+        self.class_meta.get(class)?.host_type
+    }
+
     #[inline]
     pub fn get_function(&self, name: &str) -> Option<PoolIndex<Function>> {
         self.symbols.functions.get(name).copied()
     }
 
+    /// Resolves a free function or a `Class::StaticMethod` path.
+    pub fn resolve_function(&self, path: &str) -> Option<PoolIndex<Function>> {
+        match path.split_once("::") {
+            Some((class, method)) => self.get_static_method(self.get_class(class)?, method),
+            None => self.get_function(path),
+        }
+    }
+
+    /// The name of a function as `Class::name` for methods, without the signature suffix.
+    #[inline]
+    pub fn qualified_name(&self, idx: PoolIndex<Function>) -> String {
+        qualified_name(self.pool, idx)
+    }
+
+    /// Looks up a static method by its full or unmangled name, including the base classes.
+    pub fn get_static_method(&self, class: PoolIndex<Class>, name: &str) -> Option<PoolIndex<Function>> {
+        self.find_method(class, name, true)
+    }
+
+    /// Looks up the implementation of an instance method that `class` or its nearest base defines.
+    pub fn get_method(&self, class: PoolIndex<Class>, name: &str) -> Option<PoolIndex<Function>> {
+        self.find_method(class, name, false)
+    }
+
+    fn find_method(&self, class: PoolIndex<Class>, name: &str, is_static: bool) -> Option<PoolIndex<Function>> {
+        find_method(self.pool, class, name, is_static)
+    }
+
+    pub fn get_field(&self, class: PoolIndex<Class>, name: &str) -> Option<PoolIndex<Field>> {
+        find_field(self.pool, class, name)
+    }
+
     #[inline]
     pub fn get_native(&self, idx: PoolIndex<Function>) -> Option<&VMFunction> {
         self.function_meta.get(idx)?.native.as_ref().map(AsRef::as_ref)
     }
 
+    #[inline]
+    pub fn get_intrinsic(&self, idx: PoolIndex<Function>) -> Option<Intrinsic> {
+        self.function_meta.get(idx)?.intrinsic
+    }
+
     #[inline]
     pub fn get_code_offsets(&mut self, idx: PoolIndex<Function>) -> Option<Rc<[u16]>> {
         let meta = self.function_meta.get_mut(idx)?;
@@ -88,13 +184,37 @@ impl<'pool> Metadata<'pool> {
     #[inline]
     pub fn get_vtable(&mut self, idx: PoolIndex<Class>) -> Option<Rc<IndexMap<VMIndex>>> {
         let meta = self.class_meta.get_mut(idx)?;
-        meta.get_vtable(idx, self.pool)
+        meta.get_vtable(idx, self.pool, self.vtable_checks, &mut self.vtable_warnings)
+    }
+
+    pub fn set_vtable_checks(&mut self, checks: VTableChecks) {
+        self.vtable_checks = checks;
+    }
+
+    /// Warnings reported while constructing the vtables built so far.
+    #[inline]
+    pub fn vtable_warnings(&self) -> &[VTableWarning] {
+        &self.vtable_warnings
+    }
+
+    pub fn build_vtables(&mut self) {
+        let classes = self.class_meta.iter::<Class>().map(|(idx, _)| idx).collect::<Vec<_>>();
+        for idx in classes {
+            self.get_vtable(idx);
+        }
     }
 
     pub fn register_native<F: IntoVMFunction<A, R>, A, R>(&mut self, name: &str, function: F) -> Option<()> {
         self.set_native_function(name, function.into_vm_function())
     }
 
+    pub fn register_raw_native<F>(&mut self, name: &str, function: F) -> Option<()>
+    where
+        F: for<'gc> Fn(&mut NativeCtx<'_, 'gc>) -> RuntimeResult<()> + 'static,
+    {
+        self.set_native_function(name, Box::new(function))
+    }
+
     fn set_native_function(&mut self, name: &str, function: Box<VMFunction>) -> Option<()> {
         let idx = self.get_function(name)?;
         let meta = self.function_meta.get_mut(idx)?;
@@ -102,14 +222,59 @@ impl<'pool> Metadata<'pool> {
         Some(())
     }
 
-    pub fn is_instance_of(&self, instance: PoolIndex<Class>, of: PoolIndex<Class>) -> bool {
+    /// Registers a native method declared by `class`, looked up by its full or unmangled name. Raw
+    /// natives can access the object the method was called on with [`NativeCtx::this`].
+    pub fn register_native_method<F: IntoVMFunction<A, R>, A, R>(
+        &mut self,
+        class: &str,
+        name: &str,
+        function: F,
+    ) -> Option<()> {
+        self.set_native_method(class, name, function.into_vm_function())
+    }
+
+    pub fn register_raw_native_method<F>(&mut self, class: &str, name: &str, function: F) -> Option<()>
+    where
+        F: for<'gc> Fn(&mut NativeCtx<'_, 'gc>) -> RuntimeResult<()> + 'static,
+    {
+        self.set_native_method(class, name, Box::new(function))
+    }
+
+    fn set_native_method(&mut self, class: &str, name: &str, function: Box<VMFunction>) -> Option<()> {
+        let class = self.pool.class(self.get_class(class)?).ok()?;
+        let idx = class
+            .functions
+            .iter()
+            .copied()
+            .find(|&idx| method_name_matches(self.pool, idx, name))?;
+        let meta = self.function_meta.get_mut(idx)?;
+        meta.native = Some(function);
+        Some(())
+    }
+
+    /// Whether `class` is `base` or one of its subclasses.
+    pub fn derives_from(&self, class: PoolIndex<Class>, base: PoolIndex<Class>) -> bool {
+        let mut current = class;
+        while !current.is_undefined() {
+            if current == base {
+                return true;
+            }
+            match self.pool.class(current) {
+                Ok(class) => current = class.base,
+                Err(_) => return false,
+            }
+        }
+        false
+    }
+
+    pub fn is_instance_of(&self, instance: PoolIndex<Class>, of: PoolIndex<Class>) -> RuntimeResult<bool> {
         let mut expected = of;
         loop {
-            let class = self.pool.class(expected).expect("should resolve classes");
+            let class = self.pool.class(expected).map_err(|_| RuntimeError::missing(expected))?;
             if instance == expected {
-                break true;
+                break Ok(true);
             } else if class.base.is_undefined() {
-                break false;
+                break Ok(false);
             };
             expected = class.base;
         }
@@ -151,13 +316,73 @@ impl Symbols {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct VTableChecks {
+    pub signatures: bool,
+    pub final_overrides: bool,
+}
+
+impl Default for VTableChecks {
+    fn default() -> Self {
+        Self {
+            signatures: true,
+            final_overrides: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VTableWarning {
+    SignatureMismatch {
+        class: PoolIndex<Class>,
+        base: PoolIndex<Function>,
+        method: PoolIndex<Function>,
+    },
+    FinalOverride {
+        class: PoolIndex<Class>,
+        base: PoolIndex<Function>,
+        method: PoolIndex<Function>,
+    },
+}
+
+impl VTableWarning {
+    pub fn describe(&self, pool: &ConstantPool) -> String {
+        let name = |idx: PoolIndex<Definition>| pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default();
+        let method_name = |idx: PoolIndex<Function>| match pool.definition(idx) {
+            Ok(def) => format!("{}::{}", name(def.parent), name(idx.cast())),
+            Err(_) => name(idx.cast()),
+        };
+        match self {
+            VTableWarning::SignatureMismatch { class, base, method } => format!(
+                "{}: {} overrides {} with a different signature",
+                name(class.cast()),
+                method_name(*method),
+                method_name(*base)
+            ),
+            VTableWarning::FinalOverride { class, base, method } => format!(
+                "{}: {} overrides final method {}",
+                name(class.cast()),
+                method_name(*method),
+                method_name(*base)
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 struct ClassMetadata {
     vtable: Option<Rc<IndexMap<VMIndex>>>,
+    host_type: Option<any::TypeId>,
 }
 
 impl ClassMetadata {
-    fn get_vtable(&mut self, idx: PoolIndex<Class>, pool: &ConstantPool) -> Option<Rc<IndexMap<VMIndex>>> {
+    fn get_vtable(
+        &mut self,
+        idx: PoolIndex<Class>,
+        pool: &ConstantPool,
+        checks: VTableChecks,
+        warnings: &mut Vec<VTableWarning>,
+    ) -> Option<Rc<IndexMap<VMIndex>>> {
         match &self.vtable {
             Some(rc) => Some(rc.clone()),
             None => {
@@ -168,13 +393,47 @@ impl ClassMetadata {
                     current = pool.class(current).ok()?.base;
                 }
 
-                let mut vtable = IndexMap::new();
-                for class_idx in bases.into_iter() {
+                // bases are visited from the root down, so that overrides replace inherited entries
+                let mut vtable: IndexMap<VMIndex> = IndexMap::new();
+                let mut finals: IndexMap<PoolIndex<Function>> = IndexMap::new();
+                // subclasses revisit the methods of their bases, so warnings are reported once
+                let mut report = |warning| {
+                    if !warnings.contains(&warning) {
+                        warnings.push(warning);
+                    }
+                };
+                for class_idx in bases.into_iter().rev() {
                     let class = pool.class(class_idx).ok()?;
                     for fun_idx in &class.functions {
                         let def = pool.definition(*fun_idx).ok()?;
                         let fun = pool.function(*fun_idx).ok()?;
-                        if !fun.flags.is_final() && !fun.flags.is_static() {
+                        if fun.flags.is_static() {
+                            continue;
+                        }
+                        if checks.final_overrides {
+                            if let Some(&base) = finals.get(def.name) {
+                                report(VTableWarning::FinalOverride {
+                                    class: class_idx,
+                                    base,
+                                    method: *fun_idx,
+                                });
+                            }
+                        }
+                        if checks.signatures {
+                            if let Some(base) = vtable.get(def.name) {
+                                let base = base.to_pool();
+                                if !same_signature(base, *fun_idx, pool) {
+                                    report(VTableWarning::SignatureMismatch {
+                                        class: class_idx,
+                                        base,
+                                        method: *fun_idx,
+                                    });
+                                }
+                            }
+                        }
+                        if fun.flags.is_final() {
+                            finals.put(def.name, *fun_idx);
+                        } else {
                             vtable.put(def.name, (*fun_idx).into());
                         }
                     }
@@ -187,10 +446,91 @@ impl ClassMetadata {
     }
 }
 
+pub(crate) fn find_method(
+    pool: &ConstantPool,
+    class: PoolIndex<Class>,
+    name: &str,
+    is_static: bool,
+) -> Option<PoolIndex<Function>> {
+    let mut current = class;
+    while !current.is_undefined() {
+        let class = pool.class(current).ok()?;
+        let method = class.functions.iter().copied().find(|&idx| {
+            let kind_matches = pool.function(idx).is_ok_and(|fun| fun.flags.is_static() == is_static);
+            kind_matches && method_name_matches(pool, idx, name)
+        });
+        if method.is_some() {
+            return method;
+        }
+        current = class.base;
+    }
+    None
+}
+
+/// Whether the method is named `name`, either with or without its signature.
+fn method_name_matches(pool: &ConstantPool, idx: PoolIndex<Function>, name: &str) -> bool {
+    pool.def_name(idx)
+        .is_ok_and(|str| &*str == name || str.split_once(';').is_some_and(|(short, _)| short == name))
+}
+
+/// The name of a function without the signature suffix, e.g. `OperatorAdd` for `OperatorAdd;Int32Int32;Int32`.
+pub fn short_name(name: &str) -> &str {
+    name.split(';').next().unwrap_or_default()
+}
+
+/// The name of a definition as `Parent::name` for members, with functions named without their
+/// signature suffix.
+pub fn qualified_name<A>(pool: &ConstantPool, idx: PoolIndex<A>) -> String {
+    let name = pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default();
+    let name = short_name(&name);
+    let parent = pool
+        .definition(idx.cast())
+        .ok()
+        .filter(|def| !def.parent.is_undefined())
+        .and_then(|def| pool.def_name(def.parent).ok());
+    match parent {
+        Some(parent) => format!("{parent}::{name}"),
+        None => name.to_owned(),
+    }
+}
+
+/// Looks up a field declared by `class` or one of its bases.
+pub(crate) fn find_field(pool: &ConstantPool, class: PoolIndex<Class>, name: &str) -> Option<PoolIndex<Field>> {
+    let mut current = class;
+    while !current.is_undefined() {
+        let class = pool.class(current).ok()?;
+        let field = class
+            .fields
+            .iter()
+            .copied()
+            .find(|&idx| pool.def_name(idx).is_ok_and(|str| &*str == name));
+        if field.is_some() {
+            return field;
+        }
+        current = class.base;
+    }
+    None
+}
+
+fn same_signature(lhs: PoolIndex<Function>, rhs: PoolIndex<Function>, pool: &ConstantPool) -> bool {
+    let (Ok(lhs), Ok(rhs)) = (pool.function(lhs), pool.function(rhs)) else {
+        return false;
+    };
+    let param_type = |idx: &PoolIndex<Parameter>| pool.parameter(*idx).ok().map(|param| param.type_);
+    lhs.return_type == rhs.return_type
+        && lhs.parameters.len() == rhs.parameters.len()
+        && lhs
+            .parameters
+            .iter()
+            .zip(&rhs.parameters)
+            .all(|(l, r)| param_type(l) == param_type(r))
+}
+
 #[derive(Default)]
 struct FunctionMetadata {
     offsets: Option<Rc<[u16]>>,
     native: Option<Box<VMFunction>>,
+    intrinsic: Option<Intrinsic>,
 }
 
 impl FunctionMetadata {
@@ -207,7 +547,8 @@ impl FunctionMetadata {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Collect)]
+#[collect(require_static)]
 pub enum TypeId {
     I64,
     I32,
@@ -236,9 +577,104 @@ pub enum TypeId {
     StaticArray(Box<TypeId>, u32),
 }
 
+/// Indices of the empty constants of the string tables, a pool that doesn't define one of them gets
+/// [`VMIndex::EMPTY`] from the runtime string table instead.
+#[derive(Debug, Clone, Copy)]
+pub struct EmptyStrings {
+    pub string: VMIndex,
+    pub name: VMIndex,
+    pub tweakdb_id: VMIndex,
+    pub resource: VMIndex,
+}
+
+impl EmptyStrings {
+    fn new(pool: &ConstantPool) -> Self {
+        Self {
+            string: find_empty(|i| pool.strings.get(PoolIndex::new(i)).ok().map(|str| str.is_empty())),
+            name: find_empty(|i| pool.names.get(PoolIndex::new(i)).ok().map(|str| str.is_empty())),
+            tweakdb_id: find_empty(|i| {
+                let str = pool.tweakdb_ids.get(PoolIndex::new(i)).ok()?;
+                Some(str.as_ref().is_empty())
+            }),
+            resource: find_empty(|i| {
+                let str = pool.resources.get(PoolIndex::new(i)).ok()?;
+                Some(str.as_ref().is_empty())
+            }),
+        }
+    }
+}
+
+/// The indices of the constants of the string tables by their contents, built once so that host
+/// values don't have to be looked up by scanning the tables.
+#[derive(Debug, Default)]
+struct Constants {
+    names: HashMap<Box<str>, VMIndex>,
+    tweakdb_ids: HashMap<Box<str>, VMIndex>,
+    resources: HashMap<Box<str>, VMIndex>,
+}
+
+impl Constants {
+    fn new(pool: &ConstantPool) -> Self {
+        Self {
+            names: index_table(|i| Some(pool.names.get(PoolIndex::new(i)).ok()?.as_ref().into())),
+            tweakdb_ids: index_table(|i| Some(pool.tweakdb_ids.get(PoolIndex::new(i)).ok()?.as_ref().into())),
+            resources: index_table(|i| Some(pool.resources.get(PoolIndex::new(i)).ok()?.as_ref().into())),
+        }
+    }
+}
+
+/// Probes the indices of a string table until they run out, keeping the first index of each entry.
+fn index_table(lookup: impl Fn(u32) -> Option<Box<str>>) -> HashMap<Box<str>, VMIndex> {
+    let mut table = HashMap::new();
+    for (i, str) in (0..).map_while(|i| Some((i, lookup(i)?))) {
+        table.entry(str).or_insert(VMIndex(i));
+    }
+    table
+}
+
+/// Probes the indices of a string table until they run out, `lookup` tells whether an entry is empty.
+fn find_empty(lookup: impl Fn(u32) -> Option<bool>) -> VMIndex {
+    (0..)
+        .map_while(|i| Some((i, lookup(i)?)))
+        .find_map(|(i, empty)| empty.then_some(VMIndex(i)))
+        .unwrap_or(VMIndex::EMPTY)
+}
+
 impl TypeId {
-    pub fn default_value<'gc>(&self, mc: &Mutation<'gc>, meta: &Metadata<'_>) -> Value<'gc> {
-        match self {
+    /// The name the game gives the type, e.g. `handle:PlayerPuppet` or `array:Int32`.
+    pub fn name(&self, pool: &ConstantPool) -> RuntimeResult<String> {
+        let name = match self {
+            TypeId::I64 => "Int64".to_owned(),
+            TypeId::I32 => "Int32".to_owned(),
+            TypeId::I16 => "Int16".to_owned(),
+            TypeId::I8 => "Int8".to_owned(),
+            TypeId::U64 => "Uint64".to_owned(),
+            TypeId::U32 => "Uint32".to_owned(),
+            TypeId::U16 => "Uint16".to_owned(),
+            TypeId::U8 => "Uint8".to_owned(),
+            TypeId::F64 => "Double".to_owned(),
+            TypeId::F32 => "Float".to_owned(),
+            TypeId::Bool => "Bool".to_owned(),
+            TypeId::String => "String".to_owned(),
+            TypeId::CName => "CName".to_owned(),
+            TypeId::TweakDbId => "TweakDBID".to_owned(),
+            TypeId::ResRef => "ResRef".to_owned(),
+            TypeId::Variant => "Variant".to_owned(),
+            TypeId::NodeRef => "NodeRef".to_owned(),
+            TypeId::CRUID => "CRUID".to_owned(),
+            TypeId::Ref(class) => format!("handle:{}", def_name(pool, *class)?),
+            TypeId::WRef(class) => format!("whandle:{}", def_name(pool, *class)?),
+            TypeId::ScriptRef(inner) => format!("script_ref:{}", inner.name(pool)?),
+            TypeId::Enum(enum_) => def_name(pool, *enum_)?,
+            TypeId::Struct(class) => def_name(pool, *class)?,
+            TypeId::Array(inner) => format!("array:{}", inner.name(pool)?),
+            TypeId::StaticArray(inner, size) => format!("[{}]{}", size, inner.name(pool)?),
+        };
+        Ok(name)
+    }
+
+    pub fn default_value<'gc>(&self, mc: &Mutation<'gc>, meta: &Metadata<'_>) -> RuntimeResult<Value<'gc>> {
+        let val = match self {
             TypeId::I64 => Value::I64(0),
             TypeId::I32 => Value::I32(0),
             TypeId::I16 => Value::I16(0),
@@ -250,30 +686,47 @@ impl TypeId {
             TypeId::F64 => Value::F64(0.),
             TypeId::F32 => Value::F32(0.),
             TypeId::Bool => Value::Bool(false),
-            TypeId::String => Value::InternStr(StringType::String, VMIndex::ZERO),
-            TypeId::CName => Value::InternStr(StringType::Name, VMIndex::ZERO),
-            TypeId::TweakDbId => Value::InternStr(StringType::TweakDbId, VMIndex::ZERO),
-            TypeId::ResRef => Value::InternStr(StringType::Resource, VMIndex::ZERO),
+            TypeId::String => Value::InternStr(StringType::String, meta.empty_strings().string),
+            TypeId::CName => Value::InternStr(StringType::Name, meta.empty_strings().name),
+            TypeId::TweakDbId => Value::InternStr(StringType::TweakDbId, meta.empty_strings().tweakdb_id),
+            TypeId::ResRef => Value::InternStr(StringType::Resource, meta.empty_strings().resource),
             TypeId::Variant => Value::Obj(Obj::Null),
-            TypeId::NodeRef => todo!(),
-            TypeId::CRUID => todo!(),
+            TypeId::NodeRef => Value::NodeRef(0),
+            TypeId::CRUID => Value::Cruid(0),
             TypeId::Ref(_) => Value::Obj(Obj::Null),
             TypeId::WRef(_) => Value::Obj(Obj::Null),
-            TypeId::ScriptRef(_) => todo!(),
+            TypeId::ScriptRef(_) => return Err(RuntimeError::Unsupported("default values of script references")),
             TypeId::Enum(_) => Value::EnumVal(0),
             TypeId::Struct(class_idx) => {
-                let class = meta.pool().class(*class_idx).expect("should resolve classes");
-                let fields = class.fields.iter().copied();
-                let values = fields.clone().map(|field_idx| {
-                    let field = meta.pool().field(field_idx).expect("should resolve fields");
-                    let typ = meta.get_type(field.type_).expect("should resolve types");
-                    typ.default_value(mc, meta)
-                });
-                Value::BoxedStruct(Gc::new(mc, RefLock::new(fields.zip(values).collect())))
+                let class = meta
+                    .pool()
+                    .class(*class_idx)
+                    .map_err(|_| RuntimeError::missing(*class_idx))?;
+                let fields = class
+                    .fields
+                    .iter()
+                    .map(|&field_idx| {
+                        let field = meta
+                            .pool()
+                            .field(field_idx)
+                            .map_err(|_| RuntimeError::missing(field_idx))?;
+                        let typ = meta
+                            .get_type(field.type_)
+                            .ok_or_else(|| RuntimeError::missing(field.type_))?;
+                        Ok((field_idx, typ.default_value(mc, meta)?))
+                    })
+                    .collect::<RuntimeResult<_>>()?;
+                Value::BoxedStruct(Gc::new(mc, RefLock::new(fields)))
             }
             TypeId::Array(_) => Value::Array(Gc::new(mc, RefLock::default())),
-            TypeId::StaticArray(_, _) => todo!(),
-        }
+            TypeId::StaticArray(inner, size) => {
+                let elems = (0..*size)
+                    .map(|_| inner.default_value(mc, meta))
+                    .collect::<RuntimeResult<_>>()?;
+                Value::StaticArray(Gc::new(mc, RefLock::new(elems)))
+            }
+        };
+        Ok(val)
     }
 
     fn from(idx: PoolIndex<Type>, pool: &ConstantPool, symbols: &Symbols) -> Option<TypeId> {
@@ -340,3 +793,9 @@ impl TypeId {
         }
     }
 }
+
+fn def_name<A>(pool: &ConstantPool, idx: PoolIndex<A>) -> RuntimeResult<String> {
+    pool.def_name(idx)
+        .map(|name| name.to_string())
+        .map_err(|_| RuntimeError::missing(idx))
+}
@@ -1,8 +1,21 @@
-use rand::Rng;
-use redscript::bundle::ConstantPool;
-use redscript::definition::{Definition, Type};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
 
-use crate::interop::{Ret, RetOut};
+use gc_arena::lock::RefLock;
+use gc_arena::{Collect, Gc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::definition::{Class, Definition, Field, Type};
+
+use crate::error::{RuntimeError, RuntimeResult};
+use crate::interop::{FromVM, IntoVM, NativeCtx, Ret, RetOut};
+use crate::metadata::{self, Metadata};
+use crate::value::{FloatFormat, MapKey, Obj, Value};
 use crate::VM;
 
 pub fn default_pool() -> ConstantPool {
@@ -83,7 +96,12 @@ macro_rules! impl_arithmetic {
             concat!("OperatorAssignDivide;Out", stringify!($ty), stringify!($ty), ';', stringify!($ty)),
             |x: to_native!($ty), y: to_native!($ty)| RetOut(x / y, x / y)
         );
-    
+    };
+}
+
+#[rustfmt::skip]
+macro_rules! impl_comparisons {
+    ( $meta:expr, $ty:ident ) => {
         $meta.register_native(
             concat!("OperatorEqual;", stringify!($ty), stringify!($ty), ';', "Bool"),
             |x: to_native!($ty), y: to_native!($ty)| Ret(x == y)
@@ -104,21 +122,140 @@ macro_rules! impl_arithmetic {
             concat!("OperatorGreaterEqual;", stringify!($ty), stringify!($ty), ';', "Bool"),
             |x: to_native!($ty), y: to_native!($ty)| Ret(x >= y)
         );
-      
     };
 }
 
-macro_rules! impl_cast {
-    ($meta:expr, $from:ident, $to:ident) => {
-        $meta.register_native(
-            concat!("Cast;", stringify!($from), ';', stringify!($to)),
-            |x: to_native!($from)| Ret(x as to_native!($to)),
-        );
+/// How the integer operators handle overflow and division by zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArithmeticMode {
+    /// Results wrap around on overflow and division by zero yields zero.
+    #[default]
+    Wrapping,
+    /// Results are clamped to the bounds of the type and division by zero yields zero.
+    Saturating,
+    /// Overflow and division by zero abort the call with an error.
+    Checked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IntegerOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl IntegerOp {
+    const ALL: [IntegerOp; 4] = [
+        IntegerOp::Add,
+        IntegerOp::Subtract,
+        IntegerOp::Multiply,
+        IntegerOp::Divide,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            IntegerOp::Add => "Add",
+            IntegerOp::Subtract => "Subtract",
+            IntegerOp::Multiply => "Multiply",
+            IntegerOp::Divide => "Divide",
+        }
+    }
+}
+
+trait Integer: Copy + for<'gc> FromVM<'gc> + for<'gc> IntoVM<'gc> + 'static {
+    fn apply(op: IntegerOp, x: Self, y: Self, mode: ArithmeticMode) -> RuntimeResult<Self>;
+}
+
+macro_rules! impl_integer {
+    ( $ty:ident ) => {
+        impl Integer for $ty {
+            fn apply(op: IntegerOp, x: Self, y: Self, mode: ArithmeticMode) -> RuntimeResult<Self> {
+                if op == IntegerOp::Divide && y == 0 {
+                    return match mode {
+                        ArithmeticMode::Checked => Err(RuntimeError::DivisionByZero),
+                        _ => Ok(0),
+                    };
+                }
+                let res = match (mode, op) {
+                    (ArithmeticMode::Wrapping, IntegerOp::Add) => x.wrapping_add(y),
+                    (ArithmeticMode::Wrapping, IntegerOp::Subtract) => x.wrapping_sub(y),
+                    (ArithmeticMode::Wrapping, IntegerOp::Multiply) => x.wrapping_mul(y),
+                    (ArithmeticMode::Wrapping, IntegerOp::Divide) => x.wrapping_div(y),
+                    (ArithmeticMode::Saturating, IntegerOp::Add) => x.saturating_add(y),
+                    (ArithmeticMode::Saturating, IntegerOp::Subtract) => x.saturating_sub(y),
+                    (ArithmeticMode::Saturating, IntegerOp::Multiply) => x.saturating_mul(y),
+                    (ArithmeticMode::Saturating, IntegerOp::Divide) => x.saturating_div(y),
+                    (ArithmeticMode::Checked, op) => {
+                        let res = match op {
+                            IntegerOp::Add => x.checked_add(y),
+                            IntegerOp::Subtract => x.checked_sub(y),
+                            IntegerOp::Multiply => x.checked_mul(y),
+                            IntegerOp::Divide => x.checked_div(y),
+                        };
+                        res.ok_or(RuntimeError::ArithmeticOverflow(stringify!($ty)))?
+                    }
+                };
+                Ok(res)
+            }
+        }
     };
 }
 
-#[rustfmt::skip]
+impl_integer!(i8);
+impl_integer!(i16);
+impl_integer!(i32);
+impl_integer!(i64);
+impl_integer!(u8);
+impl_integer!(u16);
+impl_integer!(u32);
+impl_integer!(u64);
+
+/// Registers the integer operators with the given overflow behavior, replacing the ones registered
+/// by [`register_core`].
+pub fn register_arithmetic(vm: &mut VM<'_>, mode: ArithmeticMode) {
+    let meta = vm.metadata_mut();
+    register_integer_ops::<i8>(meta, "Int8", mode);
+    register_integer_ops::<i16>(meta, "Int16", mode);
+    register_integer_ops::<i32>(meta, "Int32", mode);
+    register_integer_ops::<i64>(meta, "Int64", mode);
+    register_integer_ops::<u8>(meta, "Uint8", mode);
+    register_integer_ops::<u16>(meta, "Uint16", mode);
+    register_integer_ops::<u32>(meta, "Uint32", mode);
+    register_integer_ops::<u64>(meta, "Uint64", mode);
+}
+
+fn register_integer_ops<A: Integer>(meta: &mut Metadata<'_>, typ: &str, mode: ArithmeticMode) {
+    for op in IntegerOp::ALL {
+        let name = op.name();
+        meta.register_raw_native(&format!("Operator{name};{typ}{typ};{typ}"), move |ctx| {
+            ctx.expect_args(2)?;
+            let y: A = ctx.pop()?;
+            let x: A = ctx.pop()?;
+            ctx.push(A::apply(op, x, y, mode)?)
+        });
+        meta.register_raw_native(&format!("OperatorAssign{name};Out{typ}{typ};{typ}"), move |ctx| {
+            ctx.expect_args(2)?;
+            let y: A = ctx.pop()?;
+            let pinned = ctx.pop_pinned()?;
+            let res = A::apply(op, ctx.read_pinned(pinned)?, y, mode)?;
+            ctx.write_pinned(pinned, res);
+            ctx.push(res)
+        });
+    }
+}
+
 pub fn register_natives(vm: &mut VM<'_>, on_log: impl Fn(String) + 'static) {
+    register_core(vm, on_log);
+    register_strings(vm);
+}
+
+#[rustfmt::skip]
+pub fn register_core(vm: &mut VM<'_>, on_log: impl Fn(String) + 'static) {
+    register_random(vm, rand::random());
+    vm.register_try();
+    vm.register_profiling_natives();
+
     let meta = vm.metadata_mut();
     
     meta.register_native(
@@ -126,25 +263,213 @@ pub fn register_natives(vm: &mut VM<'_>, on_log: impl Fn(String) + 'static) {
         on_log
     );
 
-    meta.register_native(
-        "RandRange",
-        |min: i32, max: i32| {
-            let res: i32 = rand::thread_rng().gen_range(min..max);
-            Ret(res)
+    meta.register_raw_native(
+        "Throw",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let message: String = ctx.pop()?;
+            Err(RuntimeError::Thrown { message, location: None })
         }
     );
 
-    meta.register_native(
-        "RandF",
-        || Ret(rand::random::<f32>())
+    meta.register_raw_native(
+        "MapNew",
+        |ctx| {
+            ctx.expect_args(0)?;
+            let id = ctx.maps().create();
+            ctx.push(id)
+        }
     );
-    meta.register_native(
-        "RandRangeF",
-        |min: f32, max: f32| {
-            let res: f32 = rand::thread_rng().gen_range(min..max);
-            Ret(res)
+    meta.register_raw_native(
+        "MapInsert",
+        |ctx| {
+            ctx.expect_args(3)?;
+            let val = ctx.pop_value()?;
+            let key = ctx.pop_value()?;
+            let map: u64 = ctx.pop()?;
+            let mc = ctx.mutation();
+            ctx.maps().get_mut(map)?.insert(MapKey::new(&key, mc), val.unpinned().copied(mc));
+            Ok(())
+        }
+    );
+    meta.register_raw_native(
+        "MapGet",
+        |ctx| {
+            ctx.expect_args(2)?;
+            let key = ctx.pop_value()?;
+            let map: u64 = ctx.pop()?;
+            let res = ctx.maps().get_mut(map)?.get(&MapKey::lookup(key)).cloned();
+            ctx.push_value(res.unwrap_or(Value::Obj(Obj::Null)))
+        }
+    );
+    meta.register_raw_native(
+        "MapContains",
+        |ctx| {
+            ctx.expect_args(2)?;
+            let key = ctx.pop_value()?;
+            let map: u64 = ctx.pop()?;
+            let res = ctx.maps().get_mut(map)?.contains_key(&MapKey::lookup(key));
+            ctx.push(res)
+        }
+    );
+    meta.register_raw_native(
+        "MapRemove",
+        |ctx| {
+            ctx.expect_args(2)?;
+            let key = ctx.pop_value()?;
+            let map: u64 = ctx.pop()?;
+            let res = ctx.maps().get_mut(map)?.remove(&MapKey::lookup(key)).is_some();
+            ctx.push(res)
+        }
+    );
+    meta.register_raw_native(
+        "MapSize",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let map: u64 = ctx.pop()?;
+            let res = ctx.maps().get_mut(map)?.len() as i32;
+            ctx.push(res)
+        }
+    );
+    meta.register_raw_native(
+        "MapKeys",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let map: u64 = ctx.pop()?;
+            let keys = ctx.maps().get_mut(map)?.keys().map(|key| key.value().clone()).collect();
+            let res = Value::Array(Gc::new(ctx.mutation(), RefLock::new(keys)));
+            ctx.push_value(res)
+        }
+    );
+    meta.register_raw_native(
+        "MapDestroy",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let map: u64 = ctx.pop()?;
+            ctx.maps().destroy(map)
+        }
+    );
+    meta.register_raw_native(
+        "QueueNew",
+        |ctx| {
+            ctx.expect_args(0)?;
+            let id = ctx.maps().create_queue();
+            ctx.push(id)
+        }
+    );
+    meta.register_raw_native(
+        "QueuePush",
+        |ctx| {
+            ctx.expect_args(3)?;
+            let val = ctx.pop_value()?;
+            let priority = queue_priority(&ctx.pop_value()?)?;
+            let queue: u64 = ctx.pop()?;
+            let mc = ctx.mutation();
+            ctx.maps().push_queue(queue, priority, val.unpinned().copied(mc))
+        }
+    );
+    meta.register_raw_native(
+        "QueuePop",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let queue: u64 = ctx.pop()?;
+            let res = ctx.maps().get_queue_mut(queue)?.pop().map(|entry| entry.value);
+            ctx.push_value(res.unwrap_or(Value::Obj(Obj::Null)))
+        }
+    );
+    meta.register_raw_native(
+        "QueuePeek",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let queue: u64 = ctx.pop()?;
+            let res = ctx.maps().get_queue_mut(queue)?.peek().map(|entry| entry.value.clone());
+            ctx.push_value(res.unwrap_or(Value::Obj(Obj::Null)))
         }
     );
+    meta.register_raw_native(
+        "QueueSize",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let queue: u64 = ctx.pop()?;
+            let res = ctx.maps().get_queue_mut(queue)?.len() as i32;
+            ctx.push(res)
+        }
+    );
+    meta.register_raw_native(
+        "QueueDestroy",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let queue: u64 = ctx.pop()?;
+            ctx.maps().destroy_queue(queue)
+        }
+    );
+    meta.register_raw_native(
+        "SetNew",
+        |ctx| {
+            ctx.expect_args(0)?;
+            let id = ctx.maps().create_set();
+            ctx.push(id)
+        }
+    );
+    meta.register_raw_native(
+        "SetInsert",
+        |ctx| {
+            ctx.expect_args(2)?;
+            let val = ctx.pop_value()?;
+            let set: u64 = ctx.pop()?;
+            let mc = ctx.mutation();
+            let res = ctx.maps().get_set_mut(set)?.insert(MapKey::new(&val, mc));
+            ctx.push(res)
+        }
+    );
+    meta.register_raw_native(
+        "SetContains",
+        |ctx| {
+            ctx.expect_args(2)?;
+            let val = ctx.pop_value()?;
+            let set: u64 = ctx.pop()?;
+            let res = ctx.maps().get_set_mut(set)?.contains(&MapKey::lookup(val));
+            ctx.push(res)
+        }
+    );
+    meta.register_raw_native(
+        "SetRemove",
+        |ctx| {
+            ctx.expect_args(2)?;
+            let val = ctx.pop_value()?;
+            let set: u64 = ctx.pop()?;
+            let res = ctx.maps().get_set_mut(set)?.remove(&MapKey::lookup(val));
+            ctx.push(res)
+        }
+    );
+    meta.register_raw_native(
+        "SetSize",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let set: u64 = ctx.pop()?;
+            let res = ctx.maps().get_set_mut(set)?.len() as i32;
+            ctx.push(res)
+        }
+    );
+    meta.register_raw_native(
+        "SetValues",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let set: u64 = ctx.pop()?;
+            let vals = ctx.maps().get_set_mut(set)?.iter().map(|val| val.value().clone()).collect();
+            let res = Value::Array(Gc::new(ctx.mutation(), RefLock::new(vals)));
+            ctx.push_value(res)
+        }
+    );
+    meta.register_raw_native(
+        "SetDestroy",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let set: u64 = ctx.pop()?;
+            ctx.maps().destroy_set(set)
+        }
+    );
+
     meta.register_native(
         "SqrtF",
         |val: f32| Ret(val.sqrt())
@@ -158,11 +483,6 @@ pub fn register_natives(vm: &mut VM<'_>, on_log: impl Fn(String) + 'static) {
         |val: f32| Ret(val.cos())
     );
 
-    meta.register_native(
-        "OperatorAdd;Script_RefStringScript_RefString;String",
-        |x: String, y: String| Ret(x + &y)
-    );
-
     meta.register_native(
         "OperatorLogicAnd;BoolBool;Bool",
         |x: bool, y: bool| Ret(x && y)
@@ -172,119 +492,487 @@ pub fn register_natives(vm: &mut VM<'_>, on_log: impl Fn(String) + 'static) {
         |x: bool, y: bool| Ret(x || y)
     );
 
+    impl_arithmetic!(meta, Float);
+    impl_arithmetic!(meta, Double);
+
+    impl_comparisons!(meta, Int8);
+    impl_comparisons!(meta, Int16);
+    impl_comparisons!(meta, Int32);
+    impl_comparisons!(meta, Int64);
+    impl_comparisons!(meta, Uint8);
+    impl_comparisons!(meta, Uint16);
+    impl_comparisons!(meta, Uint32);
+    impl_comparisons!(meta, Uint64);
+    impl_comparisons!(meta, Float);
+    impl_comparisons!(meta, Double);
+
+    register_arithmetic(vm, ArithmeticMode::default());
+}
+
+#[rustfmt::skip]
+pub fn register_strings(vm: &mut VM<'_>) {
+    let meta = vm.metadata_mut();
+
+    meta.register_native(
+        "OperatorAdd;Script_RefStringScript_RefString;String",
+        |x: String, y: String| Ret(x + &y)
+    );
     meta.register_native(
         "StrChar",
         |x: i32| Ret(String::from(char::from_u32(x as _).unwrap_or_default()))
     );
 
-    impl_arithmetic!(meta, Int8);
-    impl_arithmetic!(meta, Int16);
-    impl_arithmetic!(meta, Int32);
-    impl_arithmetic!(meta, Int64);
-    impl_arithmetic!(meta, Uint8);
-    impl_arithmetic!(meta, Uint16);
-    impl_arithmetic!(meta, Uint32);
-    impl_arithmetic!(meta, Uint64);
-    impl_arithmetic!(meta, Float);
-    impl_arithmetic!(meta, Double);
+    // the Str* natives count bytes like the game does, the UTF8Str* ones count code points
+    meta.register_native(
+        "StrLen",
+        |str: String| Ret(str.len() as i32)
+    );
+    meta.register_native(
+        "StrLeft",
+        |str: String, count: i32| Ret(byte_slice(&str, 0, Some(count)))
+    );
+    meta.register_native(
+        "StrRight",
+        |str: String, count: i32| Ret(byte_slice(&str, str.len() as i32 - count.max(0), None))
+    );
+    meta.register_raw_native(
+        "StrMid",
+        |ctx| {
+            let (str, start, count) = pop_mid_args(ctx)?;
+            ctx.push(byte_slice(&str, start, count))
+        }
+    );
+    meta.register_native(
+        "UTF8StrLen",
+        |str: String| Ret(str.chars().count() as i32)
+    );
+    meta.register_native(
+        "UTF8StrLeft",
+        |str: String, count: i32| Ret(char_slice(&str, 0, Some(count)))
+    );
+    meta.register_native(
+        "UTF8StrRight",
+        |str: String, count: i32| Ret(char_slice(&str, str.chars().count() as i32 - count.max(0), None))
+    );
+    meta.register_raw_native(
+        "UTF8StrMid",
+        |ctx| {
+            let (str, start, count) = pop_mid_args(ctx)?;
+            ctx.push(char_slice(&str, start, count))
+        }
+    );
+
+    meta.register_raw_native(
+        "FloatToString",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let val: f32 = ctx.pop()?;
+            let str = ctx.float_format().format(val);
+            ctx.push(str)
+        }
+    );
+    meta.register_native(
+        "FloatToStringPrec",
+        |val: f32, precision: i32| Ret(FloatFormat::Fixed(precision.clamp(0, 255) as u8).format(val))
+    );
+
+    let builders = Rc::new(RefCell::new(StringBuilders::default()));
+    let copy = builders.clone();
+    meta.register_native(
+        "StringBuilderNew",
+        move || Ret(copy.borrow_mut().create())
+    );
+    let copy = builders.clone();
+    meta.register_native(
+        "StringBuilderAppend",
+        move |sb: u64, str: String| copy.borrow_mut().append(sb, &str)
+    );
+    let copy = builders.clone();
+    meta.register_native(
+        "StringBuilderAppendInt",
+        move |sb: u64, i: i32| copy.borrow_mut().append(sb, &i.to_string())
+    );
+    meta.register_raw_native(
+        "StringBuilderBuild",
+        move |ctx| {
+            ctx.expect_args(1)?;
+            let sb: u64 = ctx.pop()?;
+            let str = builders
+                .borrow_mut()
+                .buffers
+                .remove(&sb)
+                .ok_or(RuntimeError::InvalidNativeArgument("Unknown string builder"))?;
+            let res = ctx.intern(&str);
+            ctx.push_value(res)
+        }
+    );
+}
+
+/// Pops the arguments of `StrMid(str, start, opt count)`, the count is omitted when not passed.
+fn pop_mid_args(ctx: &mut NativeCtx<'_, '_>) -> RuntimeResult<(String, i32, Option<i32>)> {
+    let count = match ctx.arg_count() {
+        2 => None,
+        3 => Some(ctx.pop()?),
+        actual => return Err(RuntimeError::NativeArgumentMismatch { expected: 3, actual }),
+    };
+    let start = ctx.pop()?;
+    let str = ctx.pop()?;
+    Ok((str, start, count))
+}
+
+/// Takes a range of bytes, characters cut in half at the edges are replaced.
+fn byte_slice(str: &str, start: i32, count: Option<i32>) -> String {
+    let bytes = str.as_bytes();
+    let start = usize::try_from(start).unwrap_or_default().min(bytes.len());
+    let end = match count {
+        Some(count) => start
+            .saturating_add(usize::try_from(count).unwrap_or_default())
+            .min(bytes.len()),
+        None => bytes.len(),
+    };
+    String::from_utf8_lossy(&bytes[start..end]).into_owned()
+}
+
+fn char_slice(str: &str, start: i32, count: Option<i32>) -> String {
+    let chars = str.chars().skip(usize::try_from(start).unwrap_or_default());
+    match count {
+        Some(count) => chars.take(usize::try_from(count).unwrap_or_default()).collect(),
+        None => chars.collect(),
+    }
+}
+
+/// The most string builders kept at once, creating another one drops the oldest.
+const MAX_STRING_BUILDERS: usize = 256;
+
+/// Buffers of the script string builders, identified by the handles returned from `StringBuilderNew`.
+/// Builders that are never built would otherwise be kept forever, so only the most recent ones are.
+#[derive(Debug, Default)]
+struct StringBuilders {
+    next_id: u64,
+    buffers: BTreeMap<u64, String>,
+}
+
+impl StringBuilders {
+    fn create(&mut self) -> u64 {
+        if self.buffers.len() >= MAX_STRING_BUILDERS {
+            self.buffers.pop_first();
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.buffers.insert(id, String::new());
+        id
+    }
+
+    fn append(&mut self, id: u64, str: &str) {
+        if let Some(buf) = self.buffers.get_mut(&id) {
+            buf.push_str(str);
+        }
+    }
+}
+
+/// Registers the random number natives with a fixed seed, so that the results can be reproduced.
+#[rustfmt::skip]
+pub fn register_random(vm: &mut VM<'_>, seed: u64) {
+    let meta = vm.metadata_mut();
+    let rng = Rc::new(RefCell::new(StdRng::seed_from_u64(seed)));
+
+    let copy = rng.clone();
+    meta.register_native(
+        "RandRange",
+        move |min: i32, max: i32| {
+            // the game returns the lower bound for empty ranges
+            let res: i32 = if min < max { copy.borrow_mut().gen_range(min..max) } else { min };
+            Ret(res)
+        }
+    );
+    let copy = rng.clone();
+    meta.register_native(
+        "RandF",
+        move || Ret(copy.borrow_mut().gen::<f32>())
+    );
+    meta.register_native(
+        "RandRangeF",
+        move |min: f32, max: f32| {
+            let res: f32 = if min < max { rng.borrow_mut().gen_range(min..max) } else { min };
+            Ret(res)
+        }
+    );
+}
+
+/// Script maps and sets keyed by values, identified by the handles returned from `MapNew` and `SetNew`.
+/// They live until they're released with `MapDestroy` and `SetDestroy`, the same goes for queues.
+#[derive(Debug, Default, Collect)]
+#[collect(no_drop)]
+pub(crate) struct ScriptMaps<'gc> {
+    next_id: u64,
+    maps: HashMap<u64, HashMap<MapKey<'gc>, Value<'gc>>>,
+    sets: HashMap<u64, HashSet<MapKey<'gc>>>,
+    queues: HashMap<u64, BinaryHeap<QueueEntry<'gc>>>,
+    /// Orders the entries pushed with the same priority.
+    next_seq: u64,
+}
+
+impl<'gc> ScriptMaps<'gc> {
+    fn create(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.maps.insert(id, HashMap::new());
+        id
+    }
+
+    fn get_mut(&mut self, id: u64) -> RuntimeResult<&mut HashMap<MapKey<'gc>, Value<'gc>>> {
+        self.maps
+            .get_mut(&id)
+            .ok_or(RuntimeError::InvalidNativeArgument("Unknown map"))
+    }
+
+    fn destroy(&mut self, id: u64) -> RuntimeResult<()> {
+        self.maps
+            .remove(&id)
+            .map(drop)
+            .ok_or(RuntimeError::InvalidNativeArgument("Unknown map"))
+    }
+
+    fn create_set(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.sets.insert(id, HashSet::new());
+        id
+    }
+
+    fn get_set_mut(&mut self, id: u64) -> RuntimeResult<&mut HashSet<MapKey<'gc>>> {
+        self.sets
+            .get_mut(&id)
+            .ok_or(RuntimeError::InvalidNativeArgument("Unknown set"))
+    }
+
+    fn destroy_set(&mut self, id: u64) -> RuntimeResult<()> {
+        self.sets
+            .remove(&id)
+            .map(drop)
+            .ok_or(RuntimeError::InvalidNativeArgument("Unknown set"))
+    }
+
+    fn create_queue(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queues.insert(id, BinaryHeap::new());
+        id
+    }
+
+    fn get_queue_mut(&mut self, id: u64) -> RuntimeResult<&mut BinaryHeap<QueueEntry<'gc>>> {
+        self.queues
+            .get_mut(&id)
+            .ok_or(RuntimeError::InvalidNativeArgument("Unknown queue"))
+    }
+
+    fn destroy_queue(&mut self, id: u64) -> RuntimeResult<()> {
+        self.queues
+            .remove(&id)
+            .map(drop)
+            .ok_or(RuntimeError::InvalidNativeArgument("Unknown queue"))
+    }
+
+    fn push_queue(&mut self, id: u64, priority: f64, value: Value<'gc>) -> RuntimeResult<()> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.get_queue_mut(id)?.push(QueueEntry { priority, seq, value });
+        Ok(())
+    }
+}
+
+/// An element of a script priority queue, the heap pops the lowest priority first and entries
+/// with equal priorities in the order they were pushed.
+#[derive(Debug, Collect)]
+#[collect(no_drop)]
+struct QueueEntry<'gc> {
+    priority: f64,
+    seq: u64,
+    value: Value<'gc>,
+}
+
+impl PartialEq for QueueEntry<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueueEntry<'_> {}
+
+impl PartialOrd for QueueEntry<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueueEntry<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .priority
+            .total_cmp(&self.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// Reads the priority of a queue entry, which can be declared as any numeric type.
+fn queue_priority(val: &Value<'_>) -> RuntimeResult<f64> {
+    let res = match &*val.unpinned() {
+        Value::I8(i) => f64::from(*i),
+        Value::I16(i) => f64::from(*i),
+        Value::I32(i) => f64::from(*i),
+        Value::I64(i) => *i as f64,
+        Value::U8(i) => f64::from(*i),
+        Value::U16(i) => f64::from(*i),
+        Value::U32(i) => f64::from(*i),
+        Value::U64(i) => *i as f64,
+        Value::F32(f) => f64::from(*f),
+        Value::F64(f) => *f,
+        _ => return Err(RuntimeError::InvalidNativeArgument("Expected a numeric priority")),
+    };
+    Ok(res)
+}
+
+#[rustfmt::skip]
+pub fn register_game_stubs(vm: &mut VM<'_>, on_log: impl Fn(String) + 'static) {
+    let meta = vm.metadata_mut();
+    let on_log = Rc::new(on_log);
+
+    let log = on_log.clone();
+    meta.register_native(
+        "Log",
+        move |str: String| log(str)
+    );
+    let log = on_log.clone();
+    meta.register_native(
+        "LogWarning",
+        move |str: String| log(format!("[warning] {str}"))
+    );
+    let log = on_log.clone();
+    meta.register_native(
+        "LogError",
+        move |str: String| log(format!("[error] {str}"))
+    );
+    meta.register_raw_native(
+        "LogChannel",
+        move |ctx| {
+            ctx.expect_args(2)?;
+            let str: String = ctx.pop()?;
+            let channel = ctx.pop_value()?.to_string(ctx.pool());
+            on_log(format!("[{channel}] {str}"));
+            Ok(())
+        }
+    );
+}
+
+#[rustfmt::skip]
+pub fn register_reflection(vm: &mut VM<'_>) {
+    let meta = vm.metadata_mut();
+
+    meta.register_raw_native(
+        "GetClassNameOf",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let obj = ctx.pop_value()?;
+            let name = match instance_class(&obj) {
+                Some(class) => ctx.pool().def_name(class).map_err(|_| RuntimeError::missing(class))?.to_string(),
+                None => String::new(),
+            };
+            ctx.push(name)
+        }
+    );
+    meta.register_raw_native(
+        "ListFieldsOf",
+        |ctx| {
+            ctx.expect_args(1)?;
+            let obj = ctx.pop_value()?;
+            let names = match &*obj.unpinned() {
+                Value::Obj(Obj::Instance(inst)) => inst
+                    .borrow()
+                    .fields
+                    .iter::<Field>()
+                    .map(|(idx, _)| ctx.pool().def_name(idx).map(|str| str.to_string()).unwrap_or_default())
+                    .collect(),
+                _ => vec![],
+            };
+            ctx.push(names)
+        }
+    );
+    meta.register_raw_native(
+        "HasMethod",
+        |ctx| {
+            ctx.expect_args(2)?;
+            let name: String = ctx.pop()?;
+            let obj = ctx.pop_value()?;
+            let found = instance_class(&obj)
+                .is_some_and(|class| metadata::find_method(ctx.pool(), class, &name, false).is_some());
+            ctx.push(found)
+        }
+    );
+}
+
+fn instance_class(val: &Value<'_>) -> Option<PoolIndex<Class>> {
+    match &*val.unpinned() {
+        Value::Obj(Obj::Instance(inst)) => Some(inst.borrow().tag.to_pool()),
+        _ => None,
+    }
+}
+
+#[rustfmt::skip]
+pub fn register_file_io(vm: &mut VM<'_>) {
+    let meta = vm.metadata_mut();
+
+    meta.register_native(
+        "ReadTextFile",
+        |path: String| Ret(fs::read_to_string(path).unwrap_or_default())
+    );
+    meta.register_native(
+        "WriteTextFile",
+        |path: String, contents: String| Ret(fs::write(path, contents).is_ok())
+    );
+    meta.register_native(
+        "FileExists",
+        |path: String| Ret(Path::new(&path).exists())
+    );
+}
+
+#[cfg(feature = "regex")]
+#[rustfmt::skip]
+pub fn register_regex(vm: &mut VM<'_>) {
+    let meta = vm.metadata_mut();
+
+    meta.register_native(
+        "RegexMatch",
+        |str: String, pattern: String| Ret(compile_regex(&pattern).is_some_and(|regex| regex.is_match(&str)))
+    );
+    meta.register_native(
+        "RegexCaptures",
+        |str: String, pattern: String| {
+            let captures: Option<Vec<String>> = compile_regex(&pattern).and_then(|regex| {
+                let captures = regex.captures(&str)?;
+                let groups = captures
+                    .iter()
+                    .map(|group| group.map(|group| group.as_str().to_owned()).unwrap_or_default())
+                    .collect();
+                Some(groups)
+            });
+            Ret(captures.unwrap_or_default())
+        }
+    );
+    meta.register_native(
+        "RegexReplace",
+        |str: String, pattern: String, replacement: String| {
+            let res = match compile_regex(&pattern) {
+                Some(regex) => regex.replace_all(&str, replacement.as_str()).into_owned(),
+                None => str,
+            };
+            Ret(res)
+        }
+    );
+}
 
-    impl_cast!(meta, Int8, Int16);
-    impl_cast!(meta, Int8, Int32);
-    impl_cast!(meta, Int8, Int64);
-    impl_cast!(meta, Int8, Uint8);
-    impl_cast!(meta, Int8, Uint16);
-    impl_cast!(meta, Int8, Uint32);
-    impl_cast!(meta, Int8, Uint64);
-    impl_cast!(meta, Int8, Float);
-    impl_cast!(meta, Int8, Double);
-
-    impl_cast!(meta, Int16, Int8);
-    impl_cast!(meta, Int16, Int32);
-    impl_cast!(meta, Int16, Int64);
-    impl_cast!(meta, Int16, Uint8);
-    impl_cast!(meta, Int16, Uint16);
-    impl_cast!(meta, Int16, Uint32);
-    impl_cast!(meta, Int16, Uint64);
-    impl_cast!(meta, Int16, Float);
-    impl_cast!(meta, Int16, Double);
-
-    impl_cast!(meta, Int32, Int8);
-    impl_cast!(meta, Int32, Int16);
-    impl_cast!(meta, Int32, Int64);
-    impl_cast!(meta, Int32, Uint8);
-    impl_cast!(meta, Int32, Uint16);
-    impl_cast!(meta, Int32, Uint32);
-    impl_cast!(meta, Int32, Uint64);
-    impl_cast!(meta, Int32, Float);
-    impl_cast!(meta, Int32, Double);
-
-    impl_cast!(meta, Int64, Int8);
-    impl_cast!(meta, Int64, Int16);
-    impl_cast!(meta, Int64, Int32);
-    impl_cast!(meta, Int64, Uint8);
-    impl_cast!(meta, Int64, Uint16);
-    impl_cast!(meta, Int64, Uint32);
-    impl_cast!(meta, Int64, Uint64);
-    impl_cast!(meta, Int64, Float);
-    impl_cast!(meta, Int64, Double);
-
-    impl_cast!(meta, Uint8, Int8);
-    impl_cast!(meta, Uint8, Int16);
-    impl_cast!(meta, Uint8, Int32);
-    impl_cast!(meta, Uint8, Int64);
-    impl_cast!(meta, Uint8, Uint16);
-    impl_cast!(meta, Uint8, Uint32);
-    impl_cast!(meta, Uint8, Uint64);
-    impl_cast!(meta, Uint8, Float);
-    impl_cast!(meta, Uint8, Double);
-
-    impl_cast!(meta, Uint16, Int8);
-    impl_cast!(meta, Uint16, Int16);
-    impl_cast!(meta, Uint16, Int32);
-    impl_cast!(meta, Uint16, Int64);
-    impl_cast!(meta, Uint16, Uint8);
-    impl_cast!(meta, Uint16, Uint32);
-    impl_cast!(meta, Uint16, Uint64);
-    impl_cast!(meta, Uint16, Float);
-    impl_cast!(meta, Uint16, Double);
-
-    impl_cast!(meta, Uint32, Int8);
-    impl_cast!(meta, Uint32, Int16);
-    impl_cast!(meta, Uint32, Int32);
-    impl_cast!(meta, Uint32, Int64);
-    impl_cast!(meta, Uint32, Uint8);
-    impl_cast!(meta, Uint32, Uint16);
-    impl_cast!(meta, Uint32, Uint64);
-    impl_cast!(meta, Uint32, Float);
-    impl_cast!(meta, Uint32, Double);
-
-    impl_cast!(meta, Uint64, Int8);
-    impl_cast!(meta, Uint64, Int16);
-    impl_cast!(meta, Uint64, Int32);
-    impl_cast!(meta, Uint64, Int64);
-    impl_cast!(meta, Uint64, Uint8);
-    impl_cast!(meta, Uint64, Uint16);
-    impl_cast!(meta, Uint64, Uint32);
-    impl_cast!(meta, Uint64, Float);
-    impl_cast!(meta, Uint64, Double);
-
-    impl_cast!(meta, Float, Int8);
-    impl_cast!(meta, Float, Int16);
-    impl_cast!(meta, Float, Int32);
-    impl_cast!(meta, Float, Int64);
-    impl_cast!(meta, Float, Uint8);
-    impl_cast!(meta, Float, Uint16);
-    impl_cast!(meta, Float, Uint32);
-    impl_cast!(meta, Float, Uint64);
-    impl_cast!(meta, Float, Double);
-
-    impl_cast!(meta, Double, Int8);
-    impl_cast!(meta, Double, Int16);
-    impl_cast!(meta, Double, Int32);
-    impl_cast!(meta, Double, Int64);
-    impl_cast!(meta, Double, Uint8);
-    impl_cast!(meta, Double, Uint16);
-    impl_cast!(meta, Double, Uint32);
-    impl_cast!(meta, Double, Uint64);
-    impl_cast!(meta, Double, Float);
+#[cfg(feature = "regex")]
+fn compile_regex(pattern: &str) -> Option<regex::Regex> {
+    regex::Regex::new(pattern)
+        .map_err(|err| log::warn!("invalid regex {pattern}: {err}"))
+        .ok()
 }
@@ -0,0 +1,282 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use redscript::bundle::PoolIndex;
+use redscript::definition::Function;
+
+use crate::index_map::IndexMap;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FunctionProfile {
+    pub calls: usize,
+    /// Time spent in the function, including its callees.
+    pub total: Duration,
+    /// Time spent in the function itself, excluding its callees.
+    pub self_time: Duration,
+    /// Time spent in the natives called by the function itself, part of the total but not of the
+    /// self time.
+    pub native_time: Duration,
+}
+
+/// The order of a profiling report, the largest values come first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProfileOrder {
+    #[default]
+    Total,
+    SelfTime,
+    NativeTime,
+    Calls,
+}
+
+/// Calls made from one function to another while profiling was enabled.
+#[derive(Debug, Clone, Copy)]
+pub struct CallEdge {
+    pub caller: PoolIndex<Function>,
+    pub callee: PoolIndex<Function>,
+    pub calls: usize,
+}
+
+/// Timings of a region of code delimited by the `ProfileBegin` and `ProfileEnd` natives.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RegionProfile {
+    pub entries: usize,
+    pub total: Duration,
+}
+
+/// Recursive calls of a function made during a top-level call.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecursionProfile {
+    /// Calls made while the function already had an active frame.
+    pub calls: usize,
+    /// The largest number of frames of the function that were active at once.
+    pub max_frames: usize,
+}
+
+/// Call depth statistics of the last top-level call, collected regardless of profiling.
+#[derive(Debug, Default)]
+pub(crate) struct CallDepths {
+    pub max_depth: usize,
+    /// The number of active frames of every function.
+    active: IndexMap<usize>,
+    recursion: IndexMap<RecursionProfile>,
+}
+
+impl CallDepths {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn enter(&mut self, function: PoolIndex<Function>, depth: usize) {
+        self.max_depth = self.max_depth.max(depth);
+        let frames = match self.active.get_mut(function) {
+            Some(frames) => {
+                *frames += 1;
+                *frames
+            }
+            None => {
+                self.active.put(function, 1);
+                return;
+            }
+        };
+        if frames < 2 {
+            return;
+        }
+        match self.recursion.get_mut(function) {
+            Some(profile) => {
+                profile.calls += 1;
+                profile.max_frames = profile.max_frames.max(frames);
+            }
+            None => self.recursion.put(
+                function,
+                RecursionProfile {
+                    calls: 1,
+                    max_frames: frames,
+                },
+            ),
+        }
+    }
+
+    pub fn exit(&mut self, function: PoolIndex<Function>) {
+        if let Some(frames) = self.active.get_mut(function) {
+            *frames = frames.saturating_sub(1);
+        }
+    }
+
+    pub fn recursion_report(&self) -> Vec<(PoolIndex<Function>, RecursionProfile)> {
+        let mut report = self
+            .recursion
+            .iter()
+            .map(|(idx, &profile)| (idx, profile))
+            .collect::<Vec<_>>();
+        report.sort_by_key(|&(_, profile)| Reverse(profile.max_frames));
+        report
+    }
+}
+
+/// Call statistics collected while profiling is enabled, which is toggled by the `StartProfiling`
+/// instruction or by [`VM::set_profiling`](crate::VM::set_profiling). The operand of the instruction
+/// is meant for the game's profiler and isn't used, every function is profiled while it's enabled.
+#[derive(Debug, Default)]
+pub(crate) struct Profiler {
+    pub enabled: bool,
+    /// Natives are only counted in the native time of their callers, instead of being reported as
+    /// functions of their own.
+    pub fold_natives: bool,
+    functions: IndexMap<FunctionProfile>,
+    /// Time spent in the callees of each call in progress.
+    callees: Vec<CalleeTime>,
+    /// Self time by call stack, outermost function first. It's only collected on request, because
+    /// every call has to copy its stack.
+    stacks: Option<HashMap<Vec<u32>, Duration>>,
+    /// Call counts by caller and callee.
+    edges: HashMap<(u32, u32), usize>,
+    regions: HashMap<String, RegionProfile>,
+    /// Start times of the regions that haven't ended yet, a region can be entered recursively.
+    open_regions: HashMap<String, Vec<Instant>>,
+}
+
+/// Time spent in the callees of a call in progress, and the part of it spent in natives.
+#[derive(Debug, Default)]
+struct CalleeTime {
+    total: Duration,
+    natives: Duration,
+}
+
+impl Profiler {
+    /// Starts timing a call, it has to be followed by [`Profiler::record`] once the call completes.
+    pub fn enter(&mut self) -> Instant {
+        self.callees.push(CalleeTime::default());
+        Instant::now()
+    }
+
+    pub fn set_collect_stacks(&mut self, enabled: bool) {
+        match (enabled, &self.stacks) {
+            (true, None) => self.stacks = Some(HashMap::new()),
+            (false, _) => self.stacks = None,
+            (true, Some(_)) => {}
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        function: PoolIndex<Function>,
+        native: bool,
+        start: Instant,
+        callers: &[PoolIndex<Function>],
+    ) {
+        let elapsed = start.elapsed();
+        let callees = self.callees.pop().unwrap_or_default();
+        let self_time = elapsed.saturating_sub(callees.total);
+        if let Some(caller) = self.callees.last_mut() {
+            caller.total += elapsed;
+            if native {
+                caller.natives += elapsed;
+            }
+        }
+        if native && self.fold_natives {
+            return;
+        }
+        if let Some(&caller) = callers.last() {
+            *self.edges.entry((caller.into(), function.into())).or_default() += 1;
+        }
+        if let Some(stacks) = &mut self.stacks {
+            let stack = callers.iter().chain([&function]).map(|&idx| idx.into()).collect();
+            *stacks.entry(stack).or_default() += self_time;
+        }
+        match self.functions.get_mut(function) {
+            Some(profile) => {
+                profile.calls += 1;
+                profile.total += elapsed;
+                profile.self_time += self_time;
+                profile.native_time += callees.natives;
+            }
+            None => self.functions.put(
+                function,
+                FunctionProfile {
+                    calls: 1,
+                    total: elapsed,
+                    self_time,
+                    native_time: callees.natives,
+                },
+            ),
+        }
+    }
+
+    pub fn begin_region(&mut self, name: String) {
+        if self.enabled {
+            self.open_regions.entry(name).or_default().push(Instant::now());
+        }
+    }
+
+    pub fn end_region(&mut self, name: &str) {
+        let Some(start) = self.open_regions.get_mut(name).and_then(Vec::pop) else {
+            if self.enabled {
+                log::warn!("region {name} ended without being started");
+            }
+            return;
+        };
+        let profile = self.regions.entry(name.to_owned()).or_default();
+        profile.entries += 1;
+        profile.total += start.elapsed();
+    }
+
+    pub fn reset(&mut self) {
+        self.functions = IndexMap::new();
+        self.callees.clear();
+        if let Some(stacks) = &mut self.stacks {
+            stacks.clear();
+        }
+        self.edges.clear();
+        self.regions.clear();
+        self.open_regions.clear();
+    }
+
+    pub fn report(&self, order: ProfileOrder) -> Vec<(PoolIndex<Function>, FunctionProfile)> {
+        let mut report = self
+            .functions
+            .iter()
+            .map(|(idx, &profile)| (idx, profile))
+            .collect::<Vec<_>>();
+        match order {
+            ProfileOrder::Total => report.sort_by_key(|&(_, profile)| Reverse(profile.total)),
+            ProfileOrder::SelfTime => report.sort_by_key(|&(_, profile)| Reverse(profile.self_time)),
+            ProfileOrder::NativeTime => report.sort_by_key(|&(_, profile)| Reverse(profile.native_time)),
+            ProfileOrder::Calls => report.sort_by_key(|&(_, profile)| Reverse(profile.calls)),
+        }
+        report
+    }
+
+    pub fn stacks(&self) -> Vec<(Vec<PoolIndex<Function>>, Duration)> {
+        let mut stacks = self.stacks.iter().flatten().collect::<Vec<_>>();
+        stacks.sort_unstable_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+        stacks
+            .into_iter()
+            .map(|(stack, &time)| (stack.iter().map(|&idx| PoolIndex::new(idx)).collect(), time))
+            .collect()
+    }
+
+    pub fn call_graph(&self) -> Vec<CallEdge> {
+        let mut edges = self
+            .edges
+            .iter()
+            .map(|(&(caller, callee), &calls)| CallEdge {
+                caller: PoolIndex::new(caller),
+                callee: PoolIndex::new(callee),
+                calls,
+            })
+            .collect::<Vec<_>>();
+        edges.sort_by_key(|edge| (Reverse(edge.calls), u32::from(edge.caller), u32::from(edge.callee)));
+        edges
+    }
+
+    pub fn region_report(&self) -> Vec<(String, RegionProfile)> {
+        let mut report = self
+            .regions
+            .iter()
+            .map(|(name, &profile)| (name.clone(), profile))
+            .collect::<Vec<_>>();
+        report.sort_by_key(|(_, profile)| Reverse(profile.total));
+        report
+    }
+}
@@ -0,0 +1,52 @@
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
+
+use redscript::bundle::PoolIndex;
+use redscript::definition::Function;
+
+use crate::metadata;
+
+/// A native call along with its rendered arguments, as seen by recorders and interceptors.
+#[derive(Debug, Clone)]
+pub struct NativeCall {
+    pub function: PoolIndex<Function>,
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl NativeCall {
+    pub fn short_name(&self) -> &str {
+        metadata::short_name(&self.name)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallRecorder {
+    calls: Rc<RefCell<Vec<NativeCall>>>,
+}
+
+impl CallRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn calls(&self) -> Ref<'_, Vec<NativeCall>> {
+        self.calls.borrow()
+    }
+
+    pub fn count(&self, name: &str) -> usize {
+        self.calls
+            .borrow()
+            .iter()
+            .filter(|call| call.short_name() == name || call.name == name)
+            .count()
+    }
+
+    pub fn clear(&self) {
+        self.calls.borrow_mut().clear();
+    }
+
+    pub(crate) fn record(&self, call: NativeCall) {
+        self.calls.borrow_mut().push(call);
+    }
+}
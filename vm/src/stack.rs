@@ -0,0 +1,199 @@
+use std::vec::Drain;
+
+use gc_arena::Collect;
+
+use crate::error::{RuntimeError, RuntimeResult};
+use crate::value::Value;
+
+/// The operand stack shared by all script frames. Every frame owns the region of values pushed
+/// after it was entered, so an instruction can never consume the operands of its caller.
+#[derive(Debug, Default, Collect)]
+#[collect(no_drop)]
+pub(crate) struct OperandStack<'gc> {
+    values: Vec<Value<'gc>>,
+    bases: Vec<usize>,
+}
+
+impl<'gc> OperandStack<'gc> {
+    #[inline]
+    fn base(&self) -> usize {
+        self.bases.last().copied().unwrap_or_default()
+    }
+
+    /// The absolute length of the stack, including the regions of all frames.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The number of frames currently owning a region.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.bases.len()
+    }
+
+    #[inline]
+    pub fn push(&mut self, val: Value<'gc>) {
+        self.values.push(val);
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> RuntimeResult<Value<'gc>> {
+        if self.values.len() <= self.base() {
+            return Err(RuntimeError::InvalidStack);
+        }
+        self.values.pop().ok_or(RuntimeError::InvalidStack)
+    }
+
+    /// Removes the top `count` values of the current region, the oldest first.
+    pub fn pop_many(&mut self, count: usize) -> RuntimeResult<Drain<'_, Value<'gc>>> {
+        let start = self
+            .values
+            .len()
+            .checked_sub(count)
+            .filter(|&start| start >= self.base())
+            .ok_or(RuntimeError::InvalidStack)?;
+        Ok(self.values.drain(start..))
+    }
+
+    #[inline]
+    pub fn last(&self) -> Option<&Value<'gc>> {
+        self.region().last()
+    }
+
+    /// Reads a value by its absolute position, only positions in the current region are accessible.
+    #[inline]
+    pub fn get(&self, idx: usize) -> Option<&Value<'gc>> {
+        idx.checked_sub(self.base()).and_then(|idx| self.region().get(idx))
+    }
+
+    #[inline]
+    pub fn region(&self) -> &[Value<'gc>] {
+        &self.values[self.base()..]
+    }
+
+    /// Returns up to `count` values from the top of the current region.
+    pub fn top(&self, count: usize) -> &[Value<'gc>] {
+        let region = self.region();
+        &region[region.len().saturating_sub(count)..]
+    }
+
+    /// Drops the values above an absolute position in the current region.
+    #[inline]
+    pub fn truncate(&mut self, len: usize) {
+        debug_assert!(len >= self.base(), "truncating the region of a caller frame");
+        self.values.truncate(len.max(self.base()));
+    }
+
+    /// Starts a new region for a frame, the values below it belong to the callers.
+    #[inline]
+    pub fn enter_frame(&mut self) {
+        self.bases.push(self.values.len());
+    }
+
+    /// Discards the region of the current frame without a return value.
+    pub fn discard_frame(&mut self) {
+        if let Some(base) = self.bases.pop() {
+            self.values.truncate(base);
+        }
+    }
+
+    /// Restores the stack to an earlier state, discarding the regions of the frames entered since.
+    pub fn unwind(&mut self, depth: usize, len: usize) {
+        self.bases.truncate(depth);
+        self.values.truncate(len);
+    }
+
+    /// Discards the region of the current frame, keeping its top value as the return value if the
+    /// frame returns one.
+    pub fn exit_frame(&mut self, returns: bool) -> RuntimeResult<()> {
+        let ret = returns.then(|| self.pop()).transpose();
+        let base = self.bases.pop();
+        debug_assert!(base.is_some(), "exiting a frame without a stack region");
+        self.values.truncate(base.unwrap_or_default());
+        self.values.extend(ret?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OperandStack;
+    use crate::error::RuntimeError;
+    use crate::value::Value;
+
+    fn ints(stack: &OperandStack<'_>) -> Vec<i32> {
+        stack
+            .region()
+            .iter()
+            .map(|val| match val {
+                Value::I32(i) => *i,
+                _ => panic!("expected an Int32"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pop_below_region_base_fails() {
+        let mut stack = OperandStack::default();
+        stack.push(Value::I32(1));
+        stack.enter_frame();
+        assert!(matches!(stack.pop(), Err(RuntimeError::InvalidStack)));
+        assert!(matches!(stack.pop_many(1), Err(RuntimeError::InvalidStack)));
+
+        stack.push(Value::I32(2));
+        assert!(matches!(stack.pop(), Ok(Value::I32(2))));
+        assert!(matches!(stack.pop(), Err(RuntimeError::InvalidStack)));
+        assert_eq!(stack.len(), 1);
+    }
+
+    #[test]
+    fn exit_frame_keeps_return_value() {
+        let mut stack = OperandStack::default();
+        stack.push(Value::I32(1));
+        stack.enter_frame();
+        stack.push(Value::I32(2));
+        stack.push(Value::I32(3));
+        stack.exit_frame(true).unwrap();
+        assert_eq!(stack.depth(), 0);
+        assert_eq!(ints(&stack), [1, 3]);
+    }
+
+    #[test]
+    fn exit_frame_drops_region_without_return_value() {
+        let mut stack = OperandStack::default();
+        stack.push(Value::I32(1));
+        stack.enter_frame();
+        stack.push(Value::I32(2));
+        stack.exit_frame(false).unwrap();
+        assert_eq!(stack.depth(), 0);
+        assert_eq!(ints(&stack), [1]);
+    }
+
+    #[test]
+    fn exit_frame_with_empty_region_fails() {
+        let mut stack = OperandStack::default();
+        stack.push(Value::I32(1));
+        stack.enter_frame();
+        assert!(matches!(stack.exit_frame(true), Err(RuntimeError::InvalidStack)));
+        assert_eq!(stack.depth(), 0);
+        assert_eq!(ints(&stack), [1]);
+    }
+
+    #[test]
+    fn unwind_restores_depth_and_length() {
+        let mut stack = OperandStack::default();
+        stack.push(Value::I32(1));
+        let (depth, len) = (stack.depth(), stack.len());
+
+        stack.enter_frame();
+        stack.push(Value::I32(2));
+        stack.enter_frame();
+        stack.push(Value::I32(3));
+        stack.unwind(depth, len);
+
+        assert_eq!(stack.depth(), depth);
+        assert_eq!(stack.len(), len);
+        assert_eq!(ints(&stack), [1]);
+    }
+}
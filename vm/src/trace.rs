@@ -0,0 +1,67 @@
+use std::cell::{Ref, RefCell};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::metadata::short_name;
+
+#[derive(Debug, Clone)]
+pub enum TraceEvent {
+    Enter {
+        name: String,
+        args: Vec<String>,
+    },
+    Exit {
+        name: String,
+        result: Option<String>,
+    },
+    /// A frame discarded because of an error raised in it or in one of its callees.
+    Unwind {
+        name: String,
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub depth: usize,
+    pub event: TraceEvent,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = "  ".repeat(self.depth);
+        match &self.event {
+            TraceEvent::Enter { name, args } => write!(f, "{indent}-> {}({})", short_name(name), args.join(", ")),
+            TraceEvent::Exit {
+                name,
+                result: Some(res),
+            } => write!(f, "{indent}<- {} = {res}", short_name(name)),
+            TraceEvent::Exit { name, result: None } => write!(f, "{indent}<- {}", short_name(name)),
+            TraceEvent::Unwind { name, error } => write!(f, "{indent}<! {} ({error})", short_name(name)),
+        }
+    }
+}
+
+/// Collects the calls made by the VM, both scripted and native, along with their arguments and results.
+#[derive(Debug, Clone, Default)]
+pub struct CallTracer {
+    entries: Rc<RefCell<Vec<TraceEntry>>>,
+}
+
+impl CallTracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> Ref<'_, Vec<TraceEntry>> {
+        self.entries.borrow()
+    }
+
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    pub(crate) fn record(&self, depth: usize, event: TraceEvent) {
+        self.entries.borrow_mut().push(TraceEntry { depth, event });
+    }
+}
@@ -1,14 +1,19 @@
-use std::fmt::Debug;
-use std::ops::Deref;
+use std::any::{self, Any};
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
+use std::time::Duration;
 
 use enum_as_inner::EnumAsInner;
 use gc_arena::lock::{GcRefLock, RefLock};
 use gc_arena::{Collect, Gc, Mutation};
-use itertools::{Either, Itertools};
+use itertools::{Either, EitherOrBoth, Itertools};
 use redscript::bundle::{ConstantPool, PoolIndex};
-use redscript::definition::{Class, Field};
+use redscript::definition::{AnyDefinition, Class, Definition, Field};
 
+use crate::error::{RuntimeError, RuntimeResult};
 use crate::index_map::IndexMap;
 use crate::interop::{FromVM, IntoVM};
 use crate::metadata::{Metadata, TypeId};
@@ -28,16 +33,41 @@ pub enum Value<'gc> {
     F64(f64),
     Bool(bool),
     EnumVal(i64),
+    NodeRef(u64),
+    Cruid(u64),
     PackedStruct(PackedStruct),
-    BoxedStruct(GcRefLock<'gc, IndexMap<Value<'gc>>>),
+    BoxedStruct(GcRefLock<'gc, StructFields<'gc>>),
     Obj(Obj<'gc>),
     Str(Gc<'gc, Box<str>>),
     InternStr(StringType, VMIndex),
     Array(GcRefLock<'gc, Vec<Value<'gc>>>),
+    StaticArray(GcRefLock<'gc, Box<[Value<'gc>]>>),
+    Variant(Gc<'gc, Variant<'gc>>),
+    UserData(HostObject),
     Pinned(GcRefLock<'gc, Value<'gc>>),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Collect)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FloatFormat {
+    /// The shortest representation that round-trips.
+    #[default]
+    Shortest,
+    /// A fixed number of decimal places, like the game's `FloatToString`.
+    Fixed(u8),
+}
+
+impl FloatFormat {
+    pub const GAME: FloatFormat = FloatFormat::Fixed(6);
+
+    pub fn format(self, val: impl fmt::Display) -> String {
+        match self {
+            FloatFormat::Shortest => val.to_string(),
+            FloatFormat::Fixed(precision) => format!("{:.*}", precision as usize, val),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Collect)]
 #[collect(require_static)]
 pub enum StringType {
     String,
@@ -63,19 +93,109 @@ impl<'gc> Value<'gc> {
         }
     }
 
-    #[inline]
+    /// Copies a value that's being stored in a local, a parameter, a field or an array. Structs aren't
+    /// copied but marked as shared, the first field assignment made through any of the places holding
+    /// a shared struct copies it, see [`Value::unshare`]. Static arrays are copied with their elements.
     pub fn copied(&self, mc: &Mutation<'gc>) -> Self {
         match self {
-            Value::BoxedStruct(str) => Value::BoxedStruct(Gc::new(mc, str.as_ref().clone())),
+            Value::BoxedStruct(str) => {
+                str.borrow_mut(mc).shared = true;
+                self.clone()
+            }
+            Value::StaticArray(arr) => {
+                let elems = arr.borrow().iter().map(|val| val.copied(mc)).collect();
+                Value::StaticArray(Gc::new(mc, RefLock::new(elems)))
+            }
+            other => other.clone(),
+        }
+    }
+
+    /// Prepares the value of a place for an assignment to one of its fields, a shared struct is replaced
+    /// with a copy owned by the place. The copy shares the structs nested in it, which are copied in
+    /// turn when their own fields are assigned.
+    pub fn unshare(&mut self, mc: &Mutation<'gc>) {
+        match self {
+            Value::BoxedStruct(str) if str.borrow().shared => {
+                let fields = str
+                    .borrow()
+                    .iter::<Field>()
+                    .map(|(idx, val)| (idx, val.unpinned().copied(mc)))
+                    .collect();
+                *self = Value::BoxedStruct(Gc::new(mc, RefLock::new(fields)));
+            }
+            Value::Pinned(cell) => cell.borrow_mut(mc).unshare(mc),
+            _ => {}
+        }
+    }
+
+    /// Rebinds the pool constants in the value from the `old` pool to the pool of `meta` by their
+    /// contents, the ones missing from the new pool are kept as strings. Structs and arrays are copied
+    /// with their constants rebound, objects are left to be migrated on their own.
+    pub fn rebind_constants(&self, old: &ConstantPool, meta: &Metadata<'_>, mc: &Mutation<'gc>) -> Self {
+        match self {
+            Value::InternStr(typ, _) => {
+                let str = self.to_string(old);
+                match meta.find_constant(typ.clone(), &str) {
+                    Some(idx) => Value::InternStr(typ.clone(), idx),
+                    None => Value::Str(Gc::new(mc, str.into_boxed_str())),
+                }
+            }
+            Value::BoxedStruct(str) => {
+                let fields = str
+                    .borrow()
+                    .iter::<Field>()
+                    .map(|(idx, val)| (idx, val.rebind_constants(old, meta, mc)))
+                    .collect();
+                Value::BoxedStruct(Gc::new(mc, RefLock::new(fields)))
+            }
+            Value::Array(arr) => {
+                let elems = arr
+                    .borrow()
+                    .iter()
+                    .map(|val| val.rebind_constants(old, meta, mc))
+                    .collect();
+                Value::Array(Gc::new(mc, RefLock::new(elems)))
+            }
+            Value::StaticArray(arr) => {
+                let elems = arr
+                    .borrow()
+                    .iter()
+                    .map(|val| val.rebind_constants(old, meta, mc))
+                    .collect();
+                Value::StaticArray(Gc::new(mc, RefLock::new(elems)))
+            }
+            Value::Variant(var) => {
+                let value = var.value.rebind_constants(old, meta, mc);
+                Value::Variant(Gc::new(
+                    mc,
+                    Variant {
+                        typ: var.typ.clone(),
+                        value,
+                    },
+                ))
+            }
+            Value::Pinned(cell) => {
+                let val = cell.borrow().rebind_constants(old, meta, mc);
+                *cell.borrow_mut(mc) = val;
+                self.clone()
+            }
             other => other.clone(),
         }
     }
 
+    #[inline]
     pub fn to_string(&self, pool: &ConstantPool) -> String {
-        fn aggregate_to_string(fields: &IndexMap<Value<'_>>, pool: &ConstantPool) -> String {
+        self.to_string_with(pool, FloatFormat::default())
+    }
+
+    pub fn to_string_with(&self, pool: &ConstantPool, floats: FloatFormat) -> String {
+        fn aggregate_to_string(fields: &IndexMap<Value<'_>>, pool: &ConstantPool, floats: FloatFormat) -> String {
             let formatted = fields
                 .iter::<Field>()
-                .map(|(idx, val)| format!("{}: {}", pool.def_name(idx).unwrap(), val.to_string(pool)))
+                .map(|(idx, val)| {
+                    let name = pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default();
+                    format!("{name}: {}", val.to_string_with(pool, floats))
+                })
                 .format(", ");
             format!("{{{formatted}}}")
         }
@@ -89,32 +209,92 @@ impl<'gc> Value<'gc> {
             Value::U16(i) => i.to_string(),
             Value::U32(i) => i.to_string(),
             Value::U64(i) => i.to_string(),
-            Value::F32(i) => i.to_string(),
-            Value::F64(i) => i.to_string(),
+            Value::F32(i) => floats.format(i),
+            Value::F64(i) => floats.format(i),
             Value::Bool(i) => i.to_string(),
             Value::EnumVal(i) => i.to_string(),
-            Value::PackedStruct(_) => todo!(),
-            Value::BoxedStruct(struct_) => aggregate_to_string(&struct_.borrow(), pool),
+            Value::NodeRef(id) => format!("NodeRef({id:#x})"),
+            Value::Cruid(id) => format!("CRUID({id})"),
+            Value::PackedStruct(_) => "<packed struct>".to_owned(),
+            Value::BoxedStruct(struct_) => aggregate_to_string(&struct_.borrow(), pool, floats),
             Value::Obj(Obj::Null) => "null".to_string(),
-            Value::Obj(Obj::Instance(inst)) => aggregate_to_string(&inst.borrow().fields, pool),
-            Value::Str(str) => str.as_ref().clone().into_string(),
-            Value::InternStr(StringType::String, idx) => pool.strings.get(idx.to_pool()).unwrap().deref().to_owned(),
-            Value::InternStr(StringType::Name, idx) => pool.names.get(idx.to_pool()).unwrap().deref().to_owned(),
-            Value::InternStr(StringType::TweakDbId, idx) => {
-                pool.tweakdb_ids.get(idx.to_pool()).unwrap().as_ref().to_owned()
-            }
-            Value::InternStr(StringType::Resource, idx) => {
-                pool.resources.get(idx.to_pool()).unwrap().as_ref().to_owned()
+            Value::Obj(Obj::Instance(inst)) => {
+                let inst = inst.borrow();
+                format!("#{} {}", inst.id, aggregate_to_string(&inst.fields, pool, floats))
             }
+            Value::Str(str) => str.as_ref().clone().into_string(),
+            Value::InternStr(StringType::String, idx) => pool
+                .strings
+                .get(idx.to_pool())
+                .map(|str| str.deref().to_owned())
+                .unwrap_or_default(),
+            Value::InternStr(StringType::Name, idx) => pool
+                .names
+                .get(idx.to_pool())
+                .map(|str| str.deref().to_owned())
+                .unwrap_or_default(),
+            Value::InternStr(StringType::TweakDbId, idx) => pool
+                .tweakdb_ids
+                .get(idx.to_pool())
+                .map(|str| str.as_ref().to_owned())
+                .unwrap_or_default(),
+            Value::InternStr(StringType::Resource, idx) => pool
+                .resources
+                .get(idx.to_pool())
+                .map(|str| str.as_ref().to_owned())
+                .unwrap_or_default(),
             Value::Array(arr) => {
                 let arr = arr.borrow();
-                let formatted = arr.iter().map(|val| val.to_string(pool)).format(", ");
+                let formatted = arr.iter().map(|val| val.to_string_with(pool, floats)).format(", ");
+                format!("[{formatted}]")
+            }
+            Value::StaticArray(arr) => {
+                let arr = arr.borrow();
+                let formatted = arr.iter().map(|val| val.to_string_with(pool, floats)).format(", ");
                 format!("[{formatted}]")
             }
-            Value::Pinned(v) => v.borrow().to_string(pool),
+            Value::Variant(var) => var.value.to_string_with(pool, floats),
+            Value::UserData(obj) => format!("<{}>", obj.type_name()),
+            Value::Pinned(v) => v.borrow().to_string_with(pool, floats),
         }
     }
 
+    /// Formats the value like [`Value::to_string_with`], except that objects are rendered with one
+    /// field per line, grouped by the class declaring them from the root of the hierarchy down.
+    pub fn dump_with(&self, pool: &ConstantPool, floats: FloatFormat) -> String {
+        let inst = match self {
+            Value::Obj(Obj::Instance(inst)) => inst.borrow(),
+            Value::Pinned(v) => return v.borrow().dump_with(pool, floats),
+            other => return other.to_string_with(pool, floats),
+        };
+
+        let mut hierarchy = vec![];
+        let mut current = inst.tag.to_pool::<Class>();
+        while !current.is_undefined() {
+            let Ok(class) = pool.class(current) else { break };
+            hierarchy.push((current, class));
+            current = class.base;
+        }
+
+        let class_name = |idx| pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default();
+        let mut out = format!("#{}", inst.id);
+        if let Some(&(idx, _)) = hierarchy.first() {
+            out.push_str(&format!(" {}", class_name(idx)));
+        }
+        for (idx, class) in hierarchy.iter().rev().filter(|(_, class)| !class.fields.is_empty()) {
+            out.push_str(&format!("\n  {}:", class_name(*idx)));
+            for &field in &class.fields {
+                let name = pool.def_name(field).map(|str| str.to_string()).unwrap_or_default();
+                let val = inst
+                    .fields
+                    .get(field)
+                    .map_or_else(|| "<unset>".to_owned(), |val| val.to_string_with(pool, floats));
+                out.push_str(&format!("\n    {name}: {val}"));
+            }
+        }
+        out
+    }
+
     pub fn equals(&self, other: &Self) -> bool {
         match (&*self.unpinned(), &*other.unpinned()) {
             (Value::I8(lhs), Value::I8(rhs)) => lhs == rhs,
@@ -129,12 +309,84 @@ impl<'gc> Value<'gc> {
             (Value::F64(lhs), Value::F64(rhs)) => lhs == rhs,
             (Value::Bool(lhs), Value::Bool(rhs)) => lhs == rhs,
             (Value::EnumVal(lhs), Value::EnumVal(rhs)) => lhs == rhs,
+            (Value::NodeRef(lhs), Value::NodeRef(rhs)) => lhs == rhs,
+            (Value::Cruid(lhs), Value::Cruid(rhs)) => lhs == rhs,
             (Value::Str(lhs), Value::Str(rhs)) => *lhs == *rhs,
             (Value::InternStr(ltyp, lidx), Value::InternStr(rtyp, ridx)) => ltyp == rtyp && lidx == ridx,
+            (Value::PackedStruct(lhs), Value::PackedStruct(rhs)) => lhs.0 == rhs.0,
+            (Value::BoxedStruct(lhs), Value::BoxedStruct(rhs)) => {
+                let (lhs, rhs) = (lhs.borrow(), rhs.borrow());
+                let res = lhs
+                    .iter::<Field>()
+                    .zip_longest(rhs.iter::<Field>())
+                    .all(|pair| match pair {
+                        EitherOrBoth::Both((lidx, lval), (ridx, rval)) => lidx == ridx && lval.equals(rval),
+                        _ => false,
+                    });
+                res
+            }
+            (Value::Array(lhs), Value::Array(rhs)) => elements_equal(&lhs.borrow(), &rhs.borrow()),
+            (Value::StaticArray(lhs), Value::StaticArray(rhs)) => elements_equal(&lhs.borrow(), &rhs.borrow()),
+            (Value::Variant(lhs), Value::Variant(rhs)) => lhs.equals(rhs),
+            (Value::Obj(lhs), Value::Obj(rhs)) => lhs.same_as(rhs),
+            (Value::UserData(lhs), Value::UserData(rhs)) => lhs.same_as(rhs),
             _ => false,
         }
     }
 
+    /// Compares two strings by their contents, script refs are dereferenced and constants are looked
+    /// up in the pool.
+    pub fn string_equals(&self, other: &Self, pool: &ConstantPool) -> bool {
+        let lhs = String::from_vm(self.clone(), pool);
+        let rhs = String::from_vm(other.clone(), pool);
+        matches!((lhs, rhs), (Ok(lhs), Ok(rhs)) if lhs == rhs)
+    }
+
+    /// Hashes the value consistently with `equals`, structs and arrays are hashed by their contents
+    /// and objects by their identity.
+    pub fn hash_into<H: Hasher>(&self, state: &mut H) {
+        fn hash_float<H: Hasher>(val: f64, state: &mut H) {
+            // 0.0 and -0.0 compare equal, so they need to hash the same
+            if val == 0.0 { 0 } else { val.to_bits() }.hash(state);
+        }
+
+        let val = self.unpinned();
+        std::mem::discriminant(&*val).hash(state);
+        match &*val {
+            Value::I8(i) => i.hash(state),
+            Value::I16(i) => i.hash(state),
+            Value::I32(i) => i.hash(state),
+            Value::I64(i) => i.hash(state),
+            Value::U8(i) => i.hash(state),
+            Value::U16(i) => i.hash(state),
+            Value::U32(i) => i.hash(state),
+            Value::U64(i) => i.hash(state),
+            Value::F32(f) => hash_float(f64::from(*f), state),
+            Value::F64(f) => hash_float(*f, state),
+            Value::Bool(b) => b.hash(state),
+            Value::EnumVal(i) => i.hash(state),
+            Value::NodeRef(id) | Value::Cruid(id) => id.hash(state),
+            Value::PackedStruct(str) => str.0.hash(state),
+            Value::BoxedStruct(str) => {
+                for (idx, val) in str.borrow().iter::<Field>() {
+                    u32::from(idx).hash(state);
+                    val.hash_into(state);
+                }
+            }
+            Value::Str(str) => str.as_ref().hash(state),
+            Value::InternStr(typ, idx) => {
+                typ.hash(state);
+                idx.0.hash(state);
+            }
+            Value::Array(arr) => arr.borrow().iter().for_each(|val| val.hash_into(state)),
+            Value::StaticArray(arr) => arr.borrow().iter().for_each(|val| val.hash_into(state)),
+            Value::Variant(var) => var.value.hash_into(state),
+            Value::UserData(obj) => Rc::as_ptr(&obj.value).cast::<()>().hash(state),
+            Value::Obj(Obj::Instance(obj)) => Gc::as_ptr(*obj).cast::<()>().hash(state),
+            Value::Obj(Obj::Null) | Value::Pinned(_) => {}
+        }
+    }
+
     pub fn has_type(&self, typ: &TypeId) -> bool {
         match (self, typ) {
             (Value::I8(_), TypeId::I8)
@@ -149,13 +401,17 @@ impl<'gc> Value<'gc> {
             | (Value::F64(_), TypeId::F64)
             | (Value::Bool(_), TypeId::Bool)
             | (Value::EnumVal(_), TypeId::Enum(_))
+            | (Value::NodeRef(_), TypeId::NodeRef)
+            | (Value::Cruid(_), TypeId::CRUID)
             | (Value::BoxedStruct(_) | Value::PackedStruct(_), TypeId::Struct(_))
             | (Value::Obj(Obj::Null), TypeId::Ref(_) | TypeId::WRef(_))
             | (Value::Str(_) | Value::InternStr(StringType::String, _), TypeId::String)
             | (Value::InternStr(StringType::Name, _), TypeId::CName)
             | (Value::InternStr(StringType::TweakDbId, _), TypeId::TweakDbId)
             | (Value::InternStr(StringType::Resource, _), TypeId::ResRef)
-            | (Value::Array(_), TypeId::Array(_)) => true,
+            | (Value::Array(_), TypeId::Array(_))
+            | (Value::StaticArray(_), TypeId::StaticArray(_, _))
+            | (Value::Variant(_), TypeId::Variant) => true,
             (Value::Obj(Obj::Instance(cell)), TypeId::Ref(class) | TypeId::WRef(class)) => {
                 cell.borrow().tag.to_pool() == *class
             }
@@ -165,6 +421,46 @@ impl<'gc> Value<'gc> {
     }
 }
 
+/// The fields of a boxed struct. Storing a struct only marks it as shared, so that it can be read
+/// through any of the places holding it until one of them assigns a field, see [`Value::unshare`].
+#[derive(Debug, Default, Collect)]
+#[collect(no_drop)]
+pub struct StructFields<'gc> {
+    fields: IndexMap<Value<'gc>>,
+    /// Set once the struct is held by more than one place, it's never cleared, because the places
+    /// holding the struct aren't tracked.
+    shared: bool,
+}
+
+impl<'gc> Deref for StructFields<'gc> {
+    type Target = IndexMap<Value<'gc>>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.fields
+    }
+}
+
+impl DerefMut for StructFields<'_> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.fields
+    }
+}
+
+impl<'gc> From<IndexMap<Value<'gc>>> for StructFields<'gc> {
+    #[inline]
+    fn from(fields: IndexMap<Value<'gc>>) -> Self {
+        Self { fields, shared: false }
+    }
+}
+
+impl<'gc, A> FromIterator<(PoolIndex<A>, Value<'gc>)> for StructFields<'gc> {
+    fn from_iter<I: IntoIterator<Item = (PoolIndex<A>, Value<'gc>)>>(iter: I) -> Self {
+        IndexMap::from_iter(iter).into()
+    }
+}
+
 #[derive(Debug, Clone, Collect, EnumAsInner)]
 #[collect(no_drop)]
 pub enum Obj<'gc> {
@@ -172,12 +468,25 @@ pub enum Obj<'gc> {
     Instance(GcRefLock<'gc, Instance<'gc>>),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Collect)]
+impl<'gc> Obj<'gc> {
+    /// Objects are equal only if they're the same instance.
+    pub fn same_as(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Obj::Null, Obj::Null) => true,
+            (Obj::Instance(lhs), Obj::Instance(rhs)) => Gc::ptr_eq(*lhs, *rhs),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Collect)]
 #[collect(require_static)]
 pub struct VMIndex(pub u32);
 
 impl VMIndex {
     pub const ZERO: VMIndex = VMIndex(0);
+    /// The empty constant of the runtime string table, it resolves to an empty string in any pool.
+    pub const EMPTY: VMIndex = VMIndex(u32::MAX);
 
     #[inline]
     pub fn to_pool<A>(self) -> PoolIndex<A> {
@@ -192,34 +501,168 @@ impl<A> From<PoolIndex<A>> for VMIndex {
     }
 }
 
+/// A value wrapped along with its static type by `ToVariant`.
+#[derive(Debug, Collect)]
+#[collect(no_drop)]
+pub struct Variant<'gc> {
+    pub typ: TypeId,
+    pub value: Value<'gc>,
+}
+
+impl<'gc> Variant<'gc> {
+    /// Variants are equal if they wrap equal values of the same type, so `1` and `1u` are different.
+    pub fn equals(&self, other: &Self) -> bool {
+        self.typ == other.typ && self.value.equals(&other.value)
+    }
+
+    pub fn is_ref(&self) -> bool {
+        matches!(self.typ, TypeId::Ref(_) | TypeId::WRef(_) | TypeId::ScriptRef(_))
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self.typ, TypeId::Array(_) | TypeId::StaticArray(_, _))
+    }
+
+    /// Unwraps the value if it can be read as `typ`.
+    pub fn read_as(&self, typ: &TypeId) -> Option<Value<'gc>> {
+        (self.typ == *typ || self.value.has_type(typ)).then(|| self.value.clone())
+    }
+}
+
+/// A host object passed through scripts opaquely, objects are only equal to themselves.
+#[derive(Clone, Collect)]
+#[collect(require_static)]
+pub struct HostObject {
+    value: Rc<dyn Any>,
+    type_name: &'static str,
+}
+
+impl HostObject {
+    pub fn new<T: Any>(val: T) -> Self {
+        Self::from_rc(Rc::new(val))
+    }
+
+    pub fn from_rc<T: Any>(val: Rc<T>) -> Self {
+        Self {
+            value: val,
+            type_name: any::type_name::<T>(),
+        }
+    }
+
+    pub fn downcast<T: Any>(&self) -> Option<Rc<T>> {
+        self.value.clone().downcast().ok()
+    }
+
+    #[inline]
+    pub fn type_id(&self) -> any::TypeId {
+        Any::type_id(&*self.value)
+    }
+
+    #[inline]
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    #[inline]
+    pub fn same_as(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.value, &other.value)
+    }
+}
+
+impl Debug for HostObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("HostObject").field(&self.type_name).finish()
+    }
+}
+
 #[derive(Debug, Collect)]
 #[collect(no_drop)]
 pub struct Instance<'gc> {
     pub tag: VMIndex,
+    /// Assigned by the VM in allocation order, so that it's the same across runs that allocate
+    /// the same objects.
+    pub id: u64,
     pub fields: IndexMap<Value<'gc>>,
     pub vtable: Rc<IndexMap<VMIndex>>,
 }
 
 impl<'gc> Instance<'gc> {
-    pub fn new(idx: PoolIndex<Class>, meta: &mut Metadata<'_>, mc: &Mutation<'gc>) -> Self {
+    pub fn new(idx: PoolIndex<Class>, id: u64, meta: &mut Metadata<'_>, mc: &Mutation<'gc>) -> RuntimeResult<Self> {
         let mut current = idx;
         let mut fields = IndexMap::new();
         while !current.is_undefined() {
-            let class = meta.pool().class(current).unwrap();
+            let class = meta.pool().class(current).map_err(|_| RuntimeError::missing(current))?;
             for field_idx in &class.fields {
-                let field = meta.pool().field(*field_idx).unwrap();
-                let typ = meta.get_type(field.type_).unwrap();
-                fields.put(*field_idx, typ.default_value(mc, meta));
+                let field = meta
+                    .pool()
+                    .field(*field_idx)
+                    .map_err(|_| RuntimeError::missing(*field_idx))?;
+                let typ = meta
+                    .get_type(field.type_)
+                    .ok_or_else(|| RuntimeError::missing(field.type_))?;
+                fields.put(*field_idx, typ.default_value(mc, meta)?);
             }
-            current = meta.pool().class(current).unwrap().base;
+            current = class.base;
         }
-        let vtable = meta.get_vtable(idx).unwrap();
+        let vtable = meta.get_vtable(idx).ok_or_else(|| RuntimeError::missing(idx))?;
 
-        Self {
+        Ok(Self {
             tag: idx.into(),
+            id,
             fields,
             vtable,
+        })
+    }
+
+    /// Adapts an instance created with the `old` pool to the layout of its class in the pool of `meta`.
+    /// Fields are matched by name, added or retyped fields are filled with defaults and removed fields
+    /// are dropped. Pool constants in the kept fields are rebound to the new pool by their contents.
+    pub fn migrate(&mut self, old: &ConstantPool, meta: &mut Metadata<'_>, mc: &Mutation<'gc>) -> RuntimeResult<()> {
+        let name = |pool: &ConstantPool, idx: PoolIndex<Definition>| {
+            pool.def_name(idx).map(|str| str.to_string()).unwrap_or_default()
+        };
+        let old_idx = self.tag.to_pool::<Class>();
+        let class_name = old.def_name(old_idx).map_err(|_| RuntimeError::missing(old_idx))?;
+        let idx = meta
+            .get_class(&class_name)
+            .ok_or_else(|| RuntimeError::missing(old_idx))?;
+
+        let mut previous: HashMap<String, (String, Value<'gc>)> = self
+            .fields
+            .iter::<Field>()
+            .filter_map(|(field_idx, val)| {
+                let field = old.field(field_idx).ok()?;
+                Some((
+                    name(old, field_idx.cast()),
+                    (name(old, field.type_.cast()), val.clone()),
+                ))
+            })
+            .collect();
+
+        let pool = meta.pool();
+        let mut fields = IndexMap::new();
+        let mut current = idx;
+        while !current.is_undefined() {
+            let class = pool.class(current).map_err(|_| RuntimeError::missing(current))?;
+            for field_idx in &class.fields {
+                let field = pool.field(*field_idx).map_err(|_| RuntimeError::missing(*field_idx))?;
+                let val = match previous.remove(&name(pool, field_idx.cast())) {
+                    Some((typ, val)) if typ == name(pool, field.type_.cast()) => val.rebind_constants(old, meta, mc),
+                    _ => {
+                        let typ = meta
+                            .get_type(field.type_)
+                            .ok_or_else(|| RuntimeError::missing(field.type_))?;
+                        typ.default_value(mc, meta)?
+                    }
+                };
+                fields.put(*field_idx, val);
+            }
+            current = class.base;
         }
+        self.vtable = meta.get_vtable(idx).ok_or_else(|| RuntimeError::missing(idx))?;
+        self.tag = idx.into();
+        self.fields = fields;
+        Ok(())
     }
 }
 
@@ -235,7 +678,7 @@ macro_rules! impl_prim_conversions {
     ($typ:ty, $constructor:ident) => {
         impl<'gc> IntoVM<'gc> for $typ {
             #[inline]
-            fn into_vm<'ctx>(self, _mc: &Mutation<'gc>) -> Value<'gc> {
+            fn into_vm(self, _mc: &Mutation<'gc>, _meta: &Metadata<'_>) -> Value<'gc> {
                 Value::$constructor(self)
             }
         }
@@ -259,6 +702,83 @@ impl_prim_conversions!(u8, U8);
 impl_prim_conversions!(u16, U16);
 impl_prim_conversions!(u32, U32);
 impl_prim_conversions!(u64, U64);
+
+/// The hash of a world node path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct NodeRef(pub u64);
+
+/// A globally unique record identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Cruid(pub u64);
+
+macro_rules! impl_id_conversions {
+    ($typ:ident) => {
+        impl<'gc> IntoVM<'gc> for $typ {
+            #[inline]
+            fn into_vm(self, _mc: &Mutation<'gc>, _meta: &Metadata<'_>) -> Value<'gc> {
+                Value::$typ(self.0)
+            }
+        }
+
+        impl<'gc> FromVM<'gc> for $typ {
+            fn from_vm<'pool>(val: Value<'gc>, _pool: &'pool ConstantPool) -> Result<Self, &'static str> {
+                match &*val.unpinned() {
+                    Value::$typ(id) => Ok($typ(*id)),
+                    _ => Err(concat!("Invalid argument, expected ", stringify!($typ))),
+                }
+            }
+        }
+    };
+}
+
+impl_id_conversions!(NodeRef);
+impl_id_conversions!(Cruid);
+
+/// A name, converted from and to the `names` table of the pool.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct CName(pub String);
+
+/// A TweakDB record path, converted from and to the `tweakdb_ids` table of the pool.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct TweakDbId(pub String);
+
+/// A resource path, converted from and to the `resources` table of the pool.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct ResRef(pub String);
+
+/// Values are converted to the constants of the table of their type. The pool can't be extended at
+/// runtime, so the ones that aren't there are kept as strings, which don't equal any constant.
+macro_rules! impl_interned_conversions {
+    ($typ:ident, $kind:ident, $table:ident) => {
+        impl<'gc> IntoVM<'gc> for $typ {
+            fn into_vm(self, mc: &Mutation<'gc>, meta: &Metadata<'_>) -> Value<'gc> {
+                match meta.find_constant(StringType::$kind, &self.0) {
+                    Some(idx) => Value::InternStr(StringType::$kind, idx),
+                    None => Value::Str(Gc::new(mc, self.0.into_boxed_str())),
+                }
+            }
+        }
+
+        impl<'gc> FromVM<'gc> for $typ {
+            fn from_vm<'pool>(val: Value<'gc>, pool: &'pool ConstantPool) -> Result<Self, &'static str> {
+                match &*val.unpinned() {
+                    Value::InternStr(StringType::$kind, VMIndex::EMPTY) => Ok($typ(String::new())),
+                    Value::Str(str) => Ok($typ(str.as_ref().clone().into_string())),
+                    Value::InternStr(StringType::$kind, idx) => pool
+                        .$table
+                        .get(idx.to_pool())
+                        .map(|entry| $typ(AsRef::<str>::as_ref(&entry).to_owned()))
+                        .map_err(|_| concat!("Unknown ", stringify!($typ), " constant")),
+                    _ => Err(concat!("Invalid argument, expected ", stringify!($typ))),
+                }
+            }
+        }
+    };
+}
+
+impl_interned_conversions!(CName, Name, names);
+impl_interned_conversions!(TweakDbId, TweakDbId, tweakdb_ids);
+impl_interned_conversions!(ResRef, Resource, resources);
 impl_prim_conversions!(f32, F32);
 impl_prim_conversions!(f64, F64);
 impl_prim_conversions!(bool, Bool);
@@ -267,6 +787,7 @@ impl<'gc> FromVM<'gc> for String {
     fn from_vm(val: Value<'gc>, pool: &ConstantPool) -> Result<Self, &'static str> {
         match &*val.unpinned() {
             Value::Str(i) => Ok(i.as_ref().clone().into_string()),
+            Value::InternStr(StringType::String, VMIndex::EMPTY) => Ok(String::new()),
             Value::InternStr(StringType::String, idx) => pool
                 .strings
                 .get(idx.to_pool())
@@ -279,14 +800,188 @@ impl<'gc> FromVM<'gc> for String {
 
 impl<'gc> IntoVM<'gc> for String {
     #[inline]
-    fn into_vm(self, mc: &Mutation<'gc>) -> Value<'gc> {
+    fn into_vm(self, mc: &Mutation<'gc>, _meta: &Metadata<'_>) -> Value<'gc> {
         Value::Str(Gc::new(mc, self.into_boxed_str()))
     }
 }
 
 impl<'gc> IntoVM<'gc> for &'static str {
     #[inline]
-    fn into_vm(self, mc: &Mutation<'gc>) -> Value<'gc> {
+    fn into_vm(self, mc: &Mutation<'gc>, _meta: &Metadata<'_>) -> Value<'gc> {
         Value::Str(Gc::new(mc, self.into()))
     }
 }
+
+/// Durations are passed to scripts as `Float` seconds, the unit used by delays and timers in the game.
+impl<'gc> IntoVM<'gc> for Duration {
+    #[inline]
+    fn into_vm(self, _mc: &Mutation<'gc>, _meta: &Metadata<'_>) -> Value<'gc> {
+        Value::F32(self.as_secs_f32())
+    }
+}
+
+impl<'gc> FromVM<'gc> for Duration {
+    fn from_vm(val: Value<'gc>, _pool: &ConstantPool) -> Result<Self, &'static str> {
+        let secs = match &*val.unpinned() {
+            Value::F32(secs) => f64::from(*secs),
+            Value::F64(secs) => *secs,
+            _ => return Err("Invalid argument, expected Float seconds"),
+        };
+        Duration::try_from_secs_f64(secs).map_err(|_| "Invalid argument, expected a non-negative duration")
+    }
+}
+
+/// A host object of type `T` taken or returned by a native, scripts usually refer to it through a
+/// class registered with [`Metadata::register_host_class`].
+#[derive(Debug)]
+pub struct Host<T>(pub Rc<T>);
+
+impl<'gc, T: Any> IntoVM<'gc> for Host<T> {
+    #[inline]
+    fn into_vm(self, _mc: &Mutation<'gc>, _meta: &Metadata<'_>) -> Value<'gc> {
+        Value::UserData(HostObject::from_rc(self.0))
+    }
+}
+
+impl<'gc, T: Any> FromVM<'gc> for Host<T> {
+    fn from_vm(val: Value<'gc>, _pool: &ConstantPool) -> Result<Self, &'static str> {
+        match &*val.unpinned() {
+            Value::UserData(obj) => obj
+                .downcast()
+                .map(Host)
+                .ok_or("Invalid argument, expected a host object of a different type"),
+            _ => Err("Invalid argument, expected a host object"),
+        }
+    }
+}
+
+impl<'gc, A: FromVM<'gc>> FromVM<'gc> for Vec<A> {
+    fn from_vm(val: Value<'gc>, pool: &ConstantPool) -> Result<Self, &'static str> {
+        match &*val.unpinned() {
+            Value::Array(arr) => arr.borrow().iter().map(|val| A::from_vm(val.clone(), pool)).collect(),
+            Value::StaticArray(arr) => arr.borrow().iter().map(|val| A::from_vm(val.clone(), pool)).collect(),
+            _ => Err("Invalid argument, expected Array"),
+        }
+    }
+}
+
+impl<'gc, A: IntoVM<'gc>> IntoVM<'gc> for Vec<A> {
+    fn into_vm(self, mc: &Mutation<'gc>, meta: &Metadata<'_>) -> Value<'gc> {
+        let vals = self.into_iter().map(|val| val.into_vm(mc, meta)).collect();
+        Value::Array(Gc::new(mc, RefLock::new(vals)))
+    }
+}
+
+fn elements_equal(lhs: &[Value<'_>], rhs: &[Value<'_>]) -> bool {
+    lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|(lhs, rhs)| lhs.equals(rhs))
+}
+
+/// A value used as a key of a script map, compared and hashed by its contents.
+#[derive(Debug, Clone, Collect)]
+#[collect(no_drop)]
+pub struct MapKey<'gc>(Value<'gc>);
+
+impl<'gc> MapKey<'gc> {
+    /// Copies the value, so that later changes to a struct don't affect the key.
+    pub fn new(val: &Value<'gc>, mc: &Mutation<'gc>) -> Self {
+        Self(val.unpinned().copied(mc))
+    }
+
+    /// Wraps the value without copying it, for lookups.
+    pub fn lookup(val: Value<'gc>) -> Self {
+        Self(val)
+    }
+
+    #[inline]
+    pub fn value(&self) -> &Value<'gc> {
+        &self.0
+    }
+}
+
+impl PartialEq for MapKey<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.equals(&other.0)
+    }
+}
+
+impl Eq for MapKey<'_> {}
+
+impl Hash for MapKey<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash_into(state);
+    }
+}
+
+/// Reads a field of a script struct by name, used by [`vm_struct`](crate::vm_struct).
+pub fn read_struct_field<'gc, A: FromVM<'gc>>(
+    val: &Value<'gc>,
+    name: &str,
+    pool: &ConstantPool,
+) -> Result<A, &'static str> {
+    let val = val.unpinned();
+    let Value::BoxedStruct(fields) = &*val else {
+        return Err("Invalid argument, expected a struct");
+    };
+    let field = fields
+        .borrow()
+        .iter::<Field>()
+        .find(|(idx, _)| pool.def_name(*idx).is_ok_and(|str| &*str == name))
+        .map(|(_, val)| val.clone())
+        .ok_or("Invalid argument, missing struct field")?;
+    A::from_vm(field, pool)
+}
+
+/// Returns the field values of a struct in declaration order.
+pub fn struct_fields<'gc>(val: &Value<'gc>) -> Result<Vec<Value<'gc>>, &'static str> {
+    let val = val.unpinned();
+    let Value::BoxedStruct(fields) = &*val else {
+        return Err("Invalid argument, expected a struct");
+    };
+    let res = fields.borrow().iter::<Field>().map(|(_, val)| val.clone()).collect();
+    Ok(res)
+}
+
+/// Creates an instance of the script struct `name` out of named field values, used by
+/// [`vm_struct`](crate::vm_struct). Fields of the script struct that aren't provided are left out.
+pub fn build_struct<'gc>(
+    name: &str,
+    fields: Vec<(&str, Value<'gc>)>,
+    mc: &Mutation<'gc>,
+    pool: &ConstantPool,
+) -> Value<'gc> {
+    let Some(class) = find_class(name, pool) else {
+        return Value::Obj(Obj::Null);
+    };
+    let mut values = IndexMap::with_capacity(fields.len());
+    for (name, val) in fields {
+        let field = class
+            .fields
+            .iter()
+            .find(|&&idx| pool.def_name(idx).is_ok_and(|str| &*str == name));
+        if let Some(&field) = field {
+            values.put(field, val);
+        }
+    }
+    Value::BoxedStruct(Gc::new(mc, RefLock::new(values.into())))
+}
+
+/// Creates an instance of the script struct `name` with the fields assigned in declaration order.
+pub fn build_positional_struct<'gc>(
+    name: &str,
+    vals: Vec<Value<'gc>>,
+    mc: &Mutation<'gc>,
+    pool: &ConstantPool,
+) -> Value<'gc> {
+    let Some(class) = find_class(name, pool) else {
+        return Value::Obj(Obj::Null);
+    };
+    let values = class.fields.iter().copied().zip(vals).collect();
+    Value::BoxedStruct(Gc::new(mc, RefLock::new(values)))
+}
+
+fn find_class<'a>(name: &str, pool: &'a ConstantPool) -> Option<&'a Class> {
+    pool.roots().find_map(|(_, def)| match &def.value {
+        AnyDefinition::Class(class) if pool.names.get(def.name).is_ok_and(|str| &*str == name) => Some(class),
+        _ => None,
+    })
+}
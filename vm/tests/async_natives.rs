@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::definition::{AnyDefinition, Function};
+use redscript_compiler::source_map::Files;
+use redscript_compiler::unit::CompilationUnit;
+use redscript_vm::error::RuntimeError;
+use redscript_vm::metadata::short_name;
+use redscript_vm::value::Value;
+use redscript_vm::{args, native, CallStatus, VM};
+
+const SLICE: Duration = Duration::from_secs(1);
+
+const TESTS: &str = "
+native func Fetch() -> Int32
+
+func One() -> Int32 {
+  return 1;
+}
+
+func First(a: Int32, b: Int32) -> Int32 {
+  return a;
+}
+
+func FetchesBeforeScriptCall() -> Int32 {
+  return First(Fetch(), One());
+}
+
+func FetchesAfterScriptCall() -> Int32 {
+  return First(One(), Fetch());
+}
+";
+
+fn compile(pool: &mut ConstantPool, name: &str, source: &str) {
+    let mut files = Files::from_files(Vec::<PathBuf>::new()).unwrap();
+    files.add(name.into(), source.to_owned());
+    CompilationUnit::new_with_defaults(pool)
+        .unwrap()
+        .compile_files(&files)
+        .unwrap();
+}
+
+fn find_function(pool: &ConstantPool, name: &str) -> PoolIndex<Function> {
+    pool.definitions()
+        .find(|(_, def)| {
+            matches!(def.value, AnyDefinition::Function(_))
+                && pool.names.get(def.name).is_ok_and(|str| short_name(&str) == name)
+        })
+        .map(|(idx, _)| idx.cast())
+        .unwrap()
+}
+
+fn pool() -> ConstantPool {
+    let mut pool = native::default_pool();
+    compile(&mut pool, "tests.reds", TESTS);
+    pool
+}
+
+fn new_vm(pool: &ConstantPool) -> VM<'_> {
+    let mut vm = VM::new(pool);
+    vm.metadata_mut().register_raw_native("Fetch", |ctx| {
+        ctx.suspend();
+        Ok(())
+    });
+    vm
+}
+
+#[test]
+fn suspended_statement_resumes_with_result() {
+    let pool = pool();
+    let mut vm = new_vm(&pool);
+    let status = vm.call_sliced_by_statement(find_function(&pool, "FetchesBeforeScriptCall"), args!(), SLICE);
+    assert_eq!(status.unwrap(), CallStatus::Awaiting);
+    assert_eq!(vm.awaited_native(), Some(find_function(&pool, "Fetch")));
+
+    let status = vm.complete_native(|_, _| Some(Value::I32(42)), SLICE);
+    assert_eq!(status.unwrap(), CallStatus::Complete);
+    let res = vm.take_result_with_callback(|res| res.and_then(|val| val.as_i32().copied()));
+    assert_eq!(res, Some(42));
+}
+
+#[test]
+fn statement_with_script_calls_cannot_suspend() {
+    let pool = pool();
+    let mut vm = new_vm(&pool);
+    let res = vm.call_sliced_by_statement(find_function(&pool, "FetchesAfterScriptCall"), args!(), SLICE);
+    assert!(matches!(
+        res.as_ref().map_err(RuntimeError::cause),
+        Err(RuntimeError::Unsupported(_))
+    ));
+    assert!(!vm.is_suspended());
+}
@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::definition::{AnyDefinition, Function};
+use redscript_compiler::source_map::Files;
+use redscript_compiler::unit::CompilationUnit;
+use redscript_vm::interop::FromVM;
+use redscript_vm::metadata::short_name;
+use redscript_vm::{args, native, VM};
+
+const TESTS: &str = "
+native func ToUint8(a: Int32) -> Uint8
+native func ToInt32(a: Float) -> Int32
+native func ToDouble(a: Int32) -> Double
+
+func ScriptedCast(a: Int32) -> Float {
+  return 0.5;
+}
+
+func WrapsToUint8() -> Uint8 {
+  return ToUint8(300);
+}
+
+func TruncatesToInt32() -> Int32 {
+  return ToInt32(2.75);
+}
+
+func WidensToDouble() -> Double {
+  return ToDouble(7);
+}
+
+func CallsScriptedCast() -> Float {
+  return ScriptedCast(3);
+}
+";
+
+fn compile(pool: &mut ConstantPool, name: &str, source: &str) {
+    let mut files = Files::from_files(Vec::<PathBuf>::new()).unwrap();
+    files.add(name.into(), source.to_owned());
+    CompilationUnit::new_with_defaults(pool)
+        .unwrap()
+        .compile_files(&files)
+        .unwrap();
+}
+
+fn find_function(pool: &ConstantPool, name: &str) -> PoolIndex<Function> {
+    pool.definitions()
+        .find(|(_, def)| {
+            matches!(def.value, AnyDefinition::Function(_))
+                && pool.names.get(def.name).is_ok_and(|str| short_name(&str) == name)
+        })
+        .map(|(idx, _)| idx.cast())
+        .unwrap()
+}
+
+/// Scripts can't declare the names the game gives its casts, so the compiled functions are renamed.
+fn rename(pool: &mut ConstantPool, function: &str, name: &str) {
+    let idx = find_function(pool, function);
+    let mut def = pool.definition(idx).unwrap().clone();
+    def.name = pool.names.add(name.into());
+    pool.put_definition(idx, def);
+}
+
+fn pool() -> ConstantPool {
+    let mut pool = native::default_pool();
+    compile(&mut pool, "tests.reds", TESTS);
+    rename(&mut pool, "ToUint8", "Cast;Int32;Uint8");
+    rename(&mut pool, "ToInt32", "Cast;Float;Int32");
+    rename(&mut pool, "ToDouble", "Cast;Int32;Double");
+    rename(&mut pool, "ScriptedCast", "Cast;Int32;Float");
+    pool
+}
+
+fn call<A: for<'gc> FromVM<'gc>>(pool: &ConstantPool, name: &str) -> A {
+    let mut vm = VM::new(pool);
+    vm.call(find_function(pool, name), args!()).unwrap()
+}
+
+#[test]
+fn native_casts_convert_like_the_game() {
+    let pool = pool();
+    assert_eq!(call::<u8>(&pool, "WrapsToUint8"), 44);
+    assert_eq!(call::<i32>(&pool, "TruncatesToInt32"), 2);
+    assert_eq!(call::<f64>(&pool, "WidensToDouble"), 7.0);
+}
+
+#[test]
+fn scripted_functions_named_like_casts_keep_their_body() {
+    let pool = pool();
+    assert_eq!(call::<f32>(&pool, "CallsScriptedCast"), 0.5);
+}
@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::definition::{AnyDefinition, Function};
+use redscript_compiler::source_map::Files;
+use redscript_compiler::unit::CompilationUnit;
+use redscript_vm::error::RuntimeError;
+use redscript_vm::metadata::short_name;
+use redscript_vm::{args, native, VM};
+
+const OPERATORS: &str = "
+native func OperatorLogicAnd(a: Bool, b: Bool) -> Bool
+native func OperatorLogicOr(a: Bool, b: Bool) -> Bool
+";
+
+// `Fault` isn't registered, so evaluating it fails the call
+const TESTS: &str = "
+native func Fault() -> Bool
+
+func AndSkipsFault() -> Bool {
+  return false && Fault();
+}
+
+func OrSkipsFault() -> Bool {
+  return true || Fault();
+}
+
+func AndEvaluatesFault() -> Bool {
+  return true && Fault();
+}
+
+func OrEvaluatesFault() -> Bool {
+  return false || Fault();
+}
+";
+
+fn compile(pool: &mut ConstantPool, name: &str, source: &str) {
+    let mut files = Files::from_files(Vec::<PathBuf>::new()).unwrap();
+    files.add(name.into(), source.to_owned());
+    CompilationUnit::new_with_defaults(pool)
+        .unwrap()
+        .compile_files(&files)
+        .unwrap();
+}
+
+fn find_function(pool: &ConstantPool, name: &str) -> PoolIndex<Function> {
+    pool.definitions()
+        .find(|(_, def)| {
+            matches!(def.value, AnyDefinition::Function(_))
+                && pool.names.get(def.name).is_ok_and(|str| short_name(&str) == name)
+        })
+        .map(|(idx, _)| idx.cast())
+        .unwrap()
+}
+
+/// The game compiles the second operand of logical operators as a short-circuited argument, which
+/// scripts can't declare, so the flag is set on the compiled parameter.
+fn mark_short_circuit(pool: &mut ConstantPool, operator: &str) {
+    let param = pool.function(find_function(pool, operator)).unwrap().parameters[1];
+    let mut def = pool.definition(param).unwrap().clone();
+    if let AnyDefinition::Parameter(param) = &mut def.value {
+        param.flags.set_is_short_circuit(true);
+    }
+    pool.put_definition(param, def);
+}
+
+fn pool() -> ConstantPool {
+    let mut pool = native::default_pool();
+    compile(&mut pool, "operators.reds", OPERATORS);
+    mark_short_circuit(&mut pool, "OperatorLogicAnd");
+    mark_short_circuit(&mut pool, "OperatorLogicOr");
+    compile(&mut pool, "tests.reds", TESTS);
+    pool
+}
+
+fn call(pool: &ConstantPool, name: &str) -> Result<bool, RuntimeError> {
+    let mut vm = VM::new(pool);
+    native::register_natives(&mut vm, |_| {});
+    vm.call(find_function(pool, name), args!())
+}
+
+#[test]
+fn decided_operands_skip_the_second_one() {
+    let pool = pool();
+    assert!(!call(&pool, "AndSkipsFault").unwrap());
+    assert!(call(&pool, "OrSkipsFault").unwrap());
+}
+
+/// Errors raised by scripts carry a backtrace, so the underlying error is matched.
+fn fails_on_undefined_native(pool: &ConstantPool, name: &str) -> bool {
+    matches!(
+        call(pool, name).as_ref().map_err(RuntimeError::cause),
+        Err(RuntimeError::UndefinedNative(_))
+    )
+}
+
+#[test]
+fn undecided_operands_evaluate_the_second_one() {
+    let pool = pool();
+    assert!(fails_on_undefined_native(&pool, "AndEvaluatesFault"));
+    assert!(fails_on_undefined_native(&pool, "OrEvaluatesFault"));
+}
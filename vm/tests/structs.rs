@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::definition::{AnyDefinition, Function};
+use redscript_compiler::source_map::Files;
+use redscript_compiler::unit::CompilationUnit;
+use redscript_vm::metadata::short_name;
+use redscript_vm::{args, native, VM};
+
+const TESTS: &str = "
+struct Point {
+  let x: Int32;
+  let y: Int32;
+}
+
+struct Line {
+  let start: Point;
+  let end: Point;
+}
+
+func Move(p: Point) -> Int32 {
+  p.x = 5;
+  return p.x;
+}
+
+func AssignedCopyIsWritten() -> Int32 {
+  let a = new Point(1, 2);
+  let b = a;
+  b.x = 10;
+  return b.x;
+}
+
+func AssignedCopyLeavesOriginal() -> Int32 {
+  let a = new Point(1, 2);
+  let b = a;
+  b.x = 10;
+  return a.x;
+}
+
+func OriginalLeavesAssignedCopy() -> Int32 {
+  let a = new Point(1, 2);
+  let b = a;
+  a.x = 10;
+  return b.x;
+}
+
+func NestedCopyLeavesOriginal() -> Int32 {
+  let a = new Line(new Point(1, 2), new Point(3, 4));
+  let b = a;
+  b.start.x = 10;
+  return a.start.x;
+}
+
+func ArgumentLeavesOriginal() -> Int32 {
+  let a = new Point(1, 2);
+  Move(a);
+  return a.x;
+}
+
+func ArgumentIsWritten() -> Int32 {
+  let a = new Point(1, 2);
+  return Move(a);
+}
+
+func ElementLeavesOriginal() -> Int32 {
+  let a = new Point(1, 2);
+  let arr: array<Point>;
+  ArrayPush(arr, a);
+  arr[0].x = 7;
+  return a.x;
+}
+
+func ElementIsWritten() -> Int32 {
+  let a = new Point(1, 2);
+  let arr: array<Point>;
+  ArrayPush(arr, a);
+  arr[0].x = 7;
+  return arr[0].x;
+}
+";
+
+fn compile(pool: &mut ConstantPool, name: &str, source: &str) {
+    let mut files = Files::from_files(Vec::<PathBuf>::new()).unwrap();
+    files.add(name.into(), source.to_owned());
+    CompilationUnit::new_with_defaults(pool)
+        .unwrap()
+        .compile_files(&files)
+        .unwrap();
+}
+
+fn find_function(pool: &ConstantPool, name: &str) -> PoolIndex<Function> {
+    pool.definitions()
+        .find(|(_, def)| {
+            matches!(def.value, AnyDefinition::Function(_))
+                && pool.names.get(def.name).is_ok_and(|str| short_name(&str) == name)
+        })
+        .map(|(idx, _)| idx.cast())
+        .unwrap()
+}
+
+fn pool() -> ConstantPool {
+    let mut pool = native::default_pool();
+    compile(&mut pool, "tests.reds", TESTS);
+    pool
+}
+
+fn call(pool: &ConstantPool, name: &str) -> i32 {
+    let mut vm = VM::new(pool);
+    vm.call(find_function(pool, name), args!()).unwrap()
+}
+
+#[test]
+fn assignments_copy_shared_structs() {
+    let pool = pool();
+    assert_eq!(call(&pool, "AssignedCopyIsWritten"), 10);
+    assert_eq!(call(&pool, "AssignedCopyLeavesOriginal"), 1);
+    assert_eq!(call(&pool, "OriginalLeavesAssignedCopy"), 1);
+    assert_eq!(call(&pool, "NestedCopyLeavesOriginal"), 1);
+}
+
+#[test]
+fn arguments_copy_shared_structs() {
+    let pool = pool();
+    assert_eq!(call(&pool, "ArgumentIsWritten"), 5);
+    assert_eq!(call(&pool, "ArgumentLeavesOriginal"), 1);
+}
+
+#[test]
+fn elements_copy_shared_structs() {
+    let pool = pool();
+    assert_eq!(call(&pool, "ElementIsWritten"), 7);
+    assert_eq!(call(&pool, "ElementLeavesOriginal"), 1);
+}